@@ -163,6 +163,11 @@ pub enum Commands {
         /// Command to execute (legacy mode). Pass after `--`.
         #[arg(last = true)]
         command: Vec<String>,
+
+        /// Stay in the foreground and stream all services' output with
+        /// `<service> |` prefixes; Ctrl+C stops them (orchestrator mode).
+        #[arg(short, long)]
+        attach: bool,
     },
 
     /// Generate shell completion script
@@ -190,15 +195,40 @@ pub enum Commands {
     #[command(visible_alias = "p", subcommand)]
     Project(ProjectCommand),
 
-    /// Tail the log of a running service.
+    /// Show and follow service logs.
     Logs {
-        /// Service identifier (e.g. front, back). Required when the project
-        /// has more than one running service.
+        /// Service identifier (e.g. front, back). Omit to merge all
+        /// services of the project.
         service: Option<String>,
 
         /// Project name (default: current project). Use `<workspace>/<project>`
         /// or `@workspace/project` to disambiguate.
         project: Option<String>,
+
+        /// Show the last N lines first, merged across services
+        #[arg(short = 'n', long)]
+        lines: Option<usize>,
+
+        /// Only show lines since a duration ago (30s, 10m, 2h, 1d) or an
+        /// RFC 3339 timestamp
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Print the history and exit instead of following
+        #[arg(long)]
+        no_follow: bool,
+
+        /// Only show lines matching this regex
+        #[arg(long)]
+        grep: Option<String>,
+
+        /// Also search rotated log files (.log.1, .log.2, ...)
+        #[arg(long)]
+        rotated: bool,
+
+        /// Show each line's timestamp and stream (out/err/pm)
+        #[arg(short, long)]
+        timestamps: bool,
     },
 
     /// Stop running services spawned by `pm run`.
@@ -210,6 +240,22 @@ pub enum Commands {
         project: Option<String>,
     },
 
+    /// Stop and start services again with the current configuration.
+    Restart {
+        /// Service identifier. Omit to restart all services in the project.
+        service: Option<String>,
+
+        /// Project name (default: current project).
+        project: Option<String>,
+    },
+
+    /// List services spawned by `pm run` across all workspaces.
+    Ps {
+        /// Show CPU, memory, threads and listening ports (Linux)
+        #[arg(long)]
+        stats: bool,
+    },
+
     /// Manage shared local Postgres / Redis containers
     #[command(subcommand)]
     Db(DbCommand),
@@ -226,6 +272,15 @@ pub enum Commands {
         foreground: bool,
     },
 
+    /// (internal) copies a service's stdout or stderr into its log.
+    /// Hidden from help.
+    #[command(name = "__log-pipe", hide = true)]
+    LogPipe {
+        /// `out` or `err`
+        stream: String,
+        log: std::path::PathBuf,
+    },
+
     /// Upgrade PM to the latest version
     Upgrade,
 }
@@ -244,6 +299,45 @@ pub enum ProxyCommand {
 
     /// Stop the daemon (graceful shutdown)
     Stop,
+
+    /// Print daemon events live: route reloads, service spawns, exits and
+    /// restarts, 502s and unknown-host 404s
+    Events {
+        /// Print raw newline-delimited JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// List recent requests through the proxy (the last 200 are kept)
+    Requests {
+        /// Only requests whose host contains this
+        #[arg(long)]
+        host: Option<String>,
+
+        /// Only this status or class (e.g. 404, 5xx)
+        #[arg(long)]
+        status: Option<String>,
+
+        /// Show at most this many (most recent)
+        #[arg(short = 'n', long, default_value = "20")]
+        limit: usize,
+
+        /// Also print headers and captured bodies
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// Print JSON
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Print the local HTTPS root CA (generated on first use) with
+    /// instructions for trusting it
+    Trust {
+        /// Write the CA certificate (PEM) to this path instead of stdout
+        #[arg(long)]
+        export: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
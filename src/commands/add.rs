@@ -2,7 +2,9 @@ use crate::error::PmError;
 use crate::git::{get_remote_url, is_git_repo, repo_slug_from_remote};
 use crate::models::Project;
 use crate::path::{expand_path, is_directory, normalize_path, path_exists};
-use crate::state::{find_workspace, load_state, relative_dir, save_state, workspace_root};
+use crate::state::{
+    find_workspace, load_state, lock_state, relative_dir, save_state, workspace_root,
+};
 use anyhow::Result;
 use colored::Colorize;
 use regex::Regex;
@@ -24,6 +26,7 @@ pub fn run(
     let normalized_path = normalize_path(&path)?;
     let expanded_path = expand_path(&normalized_path);
 
+    let _lock = lock_state()?;
     let (mut config, mut manifest) = load_state()?;
     let current_workspace = config.current_workspace.clone();
     let workspace = find_workspace(&manifest, &current_workspace)?;
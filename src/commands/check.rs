@@ -45,18 +45,18 @@ pub fn run() -> Result<()> {
             continue;
         }
 
-        if let Some(remote) = &project.remote {
-            if !remote_matches(&path, remote) {
-                println!(
-                    "{} {:<16} {} ({})",
-                    "✗".red(),
-                    project.name,
-                    path_display,
-                    "remote-mismatch".red()
-                );
-                issues += 1;
-                continue;
-            }
+        if let Some(remote) = &project.remote
+            && !remote_matches(&path, remote)
+        {
+            println!(
+                "{} {:<16} {} ({})",
+                "✗".red(),
+                project.name,
+                path_display,
+                "remote-mismatch".red()
+            );
+            issues += 1;
+            continue;
         }
 
         println!("{} {:<16} {}", "✓".green(), project.name, path_display);
@@ -13,8 +13,8 @@ pub fn run(limit: usize) -> Result<()> {
     }
 
     println!(
-        "  {:<20} {:<12} {:<16} {}",
-        "WHEN", "ACTION", "PROJECT", "PATH"
+        "  {:<20} {:<12} {:<16} PATH",
+        "WHEN", "ACTION", "PROJECT"
     );
     for entry in entries {
         println!(
@@ -93,11 +93,11 @@ pub fn run(
         }
 
         match sort {
-            SortField::Accessed => projects.sort_by(|a, b| b.last_accessed.cmp(&a.last_accessed)),
+            SortField::Accessed => projects.sort_by_key(|p| std::cmp::Reverse(p.last_accessed)),
             SortField::Name => projects.sort_by(|a, b| a.name.cmp(&b.name)),
             SortField::Path => projects.sort_by(|a, b| a.dir.cmp(&b.dir)),
-            SortField::Added => projects.sort_by(|a, b| b.added_at.cmp(&a.added_at)),
-            SortField::Frequency => projects.sort_by(|a, b| b.access_count.cmp(&a.access_count)),
+            SortField::Added => projects.sort_by_key(|p| std::cmp::Reverse(p.added_at)),
+            SortField::Frequency => projects.sort_by_key(|p| std::cmp::Reverse(p.access_count)),
             SortField::Status => projects.sort_by(|a, b| {
                 let a_path = project_path(&config, &manifest, a).ok();
                 let b_path = project_path(&config, &manifest, b).ok();
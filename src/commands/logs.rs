@@ -1,89 +1,556 @@
-//! `pm logs <service> [project]` — tail the log file of a running service.
+//! `pm logs [service] [project]` — show and follow service logs.
 //!
-//! Implementation strategy: `tail -f` over a regular file. We open the file
-//! at its current end, then poll for size growth on a 100ms cadence. The
-//! tail output streams to stdout. SIGINT exits cleanly.
+//! Without a service, every service of the project that has a log file is
+//! shown, each line prefixed with a colored `<service> |` label. History
+//! comes first (`-n`, `--since`, optionally reaching into rotated
+//! `.log.N` files with `--rotated`), merged across services by pm's line
+//! timestamps, then the files are followed unless `--no-follow` was
+//! given. `--grep` filters both.
+//!
+//! Implementation strategy: `tail -f` over regular files. We remember how
+//! far each file has been read, then poll for size growth on a 100ms
+//! cadence. Truncation or rotation restarts from the top of the new file.
+//! SIGINT exits cleanly.
+//!
+//! [`follow_many`] is shared with `pm run --attach`.
 
 use crate::commands::run::resolve_project;
+use crate::config::service_log_path;
+use crate::log_line;
+use crate::log_rotation;
+use crate::project::load_proj_config;
 use crate::services as svc_state;
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
+use colored::{Color, Colorize};
+use regex::Regex;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-pub fn run(service: Option<String>, project: Option<String>) -> Result<()> {
-    let (workspace, project_obj, _project_dir) = resolve_project(project)?;
-    let services = svc_state::list_project(&workspace, &project_obj.name)?;
+/// Flags of `pm logs` beyond the service/project selection.
+#[derive(Debug, Default)]
+pub struct LogsOptions {
+    /// Show the last N lines (of all shown services together) before
+    /// following.
+    pub lines: Option<usize>,
+    /// Only show lines newer than this (`30s`, `10m`, `2h`, `1d`, or an
+    /// RFC 3339 timestamp).
+    pub since: Option<String>,
+    pub no_follow: bool,
+    pub grep: Option<String>,
+    /// Include rotated `.log.N` files in the history.
+    pub rotated: bool,
+    /// Show pm's timestamp and stream tag on each line.
+    pub timestamps: bool,
+}
+
+pub fn run(service: Option<String>, project: Option<String>, opts: LogsOptions) -> Result<()> {
+    let (workspace, project_obj, project_dir) = resolve_project(project)?;
+    let targets = log_targets(&workspace, &project_obj.name, &project_dir, service.as_deref())?;
+
+    let grep = opts
+        .grep
+        .as_deref()
+        .map(|pattern| Regex::new(pattern).with_context(|| format!("invalid --grep '{pattern}'")))
+        .transpose()?;
+    let since = opts.since.as_deref().map(parse_since).transpose()?;
+    // Following from "now" is the default; history is printed when asked
+    // for, or always when not following.
+    let history = opts.lines.is_some() || since.is_some() || opts.no_follow;
+
+    let labels: Vec<String> = targets.keys().cloned().collect();
+    let writer = LineWriter::new(&labels, service.is_none(), opts.timestamps, grep);
+    let stdout = std::io::stdout();
+    let mut sources = Vec::new();
+    let mut histories = Vec::new();
+    for (name, path) in &targets {
+        let (lines, end) = if history {
+            read_history(path, opts.rotated, since, None, &writer)?
+        } else {
+            (Vec::new(), std::fs::metadata(path).map(|m| m.len()).unwrap_or(0))
+        };
+        histories.push(lines);
+        sources.push(LogSource::new(name.clone(), path.clone(), end));
+    }
+    let mut out = stdout.lock();
+    for (idx, line) in merge_histories(histories, opts.lines) {
+        writer.write(&mut out, idx, &line)?;
+    }
+    out.flush()?;
+    drop(out);
 
-    if services.is_empty() {
-        return Err(anyhow!(
-            "no services running for {}/{}",
-            workspace,
-            project_obj.name
-        ));
+    if opts.no_follow {
+        return Ok(());
     }
+    if let [name] = labels.as_slice()
+        && let Some(state) = svc_state::get(&workspace, &project_obj.name, name)?
+    {
+        eprintln!(
+            "==> tailing {} (pid {}, port {})",
+            state.log_path.display(),
+            state.pid,
+            state.port
+        );
+    }
+    follow_many(sources, &writer, || false)
+}
 
-    let target = match service {
-        Some(s) => s,
-        None => {
-            if services.len() == 1 {
-                services.keys().next().unwrap().clone()
-            } else {
-                let mut keys: Vec<&String> = services.keys().collect();
-                keys.sort();
+/// Log file per service, by name. A named service may be stopped as long
+/// as its log exists; otherwise all running services plus any
+/// `.project.yaml` service with a log file are included.
+fn log_targets(
+    workspace: &str,
+    project: &str,
+    project_dir: &Path,
+    service: Option<&str>,
+) -> Result<BTreeMap<String, PathBuf>> {
+    let running = svc_state::list_project(workspace, project)?;
+    let log_for = |name: &str| match running.get(name) {
+        Some(state) => state.log_path.clone(),
+        None => service_log_path(workspace, project, name),
+    };
+
+    let mut targets = BTreeMap::new();
+    match service {
+        Some(name) => {
+            let path = log_for(name);
+            if !path.exists() {
                 return Err(anyhow!(
-                    "multiple services running ({}); specify which to tail",
-                    keys.iter()
-                        .map(|s| s.as_str())
-                        .collect::<Vec<_>>()
-                        .join(", ")
+                    "no logs for service '{}' in {}/{}",
+                    name,
+                    workspace,
+                    project
                 ));
             }
+            targets.insert(name.to_string(), path);
+        }
+        None => {
+            let defined = load_proj_config(project_dir)
+                .map(|c| c.services.into_keys().collect())
+                .unwrap_or_else(|_| Vec::new());
+            for name in running.keys().chain(defined.iter()) {
+                let path = log_for(name);
+                if path.exists() {
+                    targets.insert(name.clone(), path);
+                }
+            }
+            if targets.is_empty() {
+                return Err(anyhow!("no service logs for {}/{}", workspace, project));
+            }
         }
+    }
+    Ok(targets)
+}
+
+// ── History ──
+
+/// Lines of `path` (and, with `rotated`, its older `.log.N` files) that
+/// pass `since` and the writer's filter, keeping the last `limit`. Also
+/// returns the live file's length, where following should pick up.
+pub fn read_history(
+    path: &Path,
+    rotated: bool,
+    since: Option<DateTime<Local>>,
+    limit: Option<usize>,
+    writer: &LineWriter,
+) -> Result<(Vec<String>, u64)> {
+    let mut files = if rotated {
+        log_rotation::rotated_files(path, log_rotation::KEEP)
+    } else {
+        Vec::new()
     };
+    files.push(path.to_path_buf());
 
-    let state = services
-        .get(&target)
-        .ok_or_else(|| anyhow!("service '{}' is not running", target))?;
-
-    eprintln!(
-        "==> tailing {} (pid {}, port {})",
-        state.log_path.display(),
-        state.pid,
-        state.port
-    );
-    tail_follow(&state.log_path)
+    let mut lines = Vec::new();
+    let mut end = 0;
+    for file in &files {
+        let Ok(bytes) = std::fs::read(file) else { continue };
+        if file == path {
+            end = bytes.len() as u64;
+        }
+        // Lines without pm's timestamp (stack traces split by the service,
+        // logs from before pm timestamped them) are dated by the closest
+        // timestamped line above them, or by the file's mtime.
+        let mtime: Option<DateTime<Local>> = std::fs::metadata(file)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(DateTime::from);
+        let mut current = None;
+        for line in String::from_utf8_lossy(&bytes).lines() {
+            if let Some(parsed) = log_line::parse(line) {
+                current = Some(parsed.at.with_timezone(&Local));
+            }
+            let recent = match (since, current.or(mtime)) {
+                (Some(cutoff), Some(at)) => at >= cutoff,
+                _ => true,
+            };
+            if recent && writer.matches(line) {
+                lines.push(line.to_string());
+            }
+        }
+    }
+    if let Some(n) = limit {
+        let skip = lines.len().saturating_sub(n);
+        lines.drain(..skip);
+    }
+    Ok((lines, end))
 }
 
-fn tail_follow(path: &Path) -> Result<()> {
-    let mut file = File::open(path).with_context(|| format!("opening log {}", path.display()))?;
-    let mut pos = file.seek(SeekFrom::End(0))?;
-    let mut buf = [0u8; 4096];
-    let stdout = std::io::stdout();
+/// Interleave the histories of several sources by pm's line timestamps,
+/// keeping the last `limit` lines overall; each line comes with the index
+/// of its source. Lines without a timestamp (stack traces, logs written
+/// before pm timestamped them) stay right after the line above them in
+/// their own file; leading ones, with nothing above them, stay right
+/// before the first timestamped line. Ties keep source order, so the
+/// result is stable.
+fn merge_histories(histories: Vec<Vec<String>>, limit: Option<usize>) -> Vec<(usize, String)> {
+    let mut merged = Vec::new();
+    for (idx, lines) in histories.into_iter().enumerate() {
+        let mut current = lines
+            .iter()
+            .find_map(|line| log_line::parse(line))
+            .map(|parsed| parsed.at);
+        for line in lines {
+            if let Some(parsed) = log_line::parse(&line) {
+                current = Some(parsed.at);
+            }
+            merged.push((current, idx, line));
+        }
+    }
+    merged.sort_by_key(|(at, _, _)| *at);
+    let skip = limit.map_or(0, |n| merged.len().saturating_sub(n));
+    merged
+        .into_iter()
+        .skip(skip)
+        .map(|(_, idx, line)| (idx, line))
+        .collect()
+}
 
-    loop {
+/// Parse `--since`: a duration ago (`45s`, `10m`, `2h`, `1d`) or an
+/// absolute RFC 3339 timestamp.
+fn parse_since(value: &str) -> Result<DateTime<Local>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Local));
+    }
+    let invalid = || anyhow!("invalid --since '{value}' (expected e.g. 30s, 10m, 2h, 1d)");
+    let split = value.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let (digits, unit) = value.split_at(split);
+    let amount: i64 = digits.parse().map_err(|_| invalid())?;
+    let ago = match unit {
+        "s" => chrono::Duration::seconds(amount),
+        "m" => chrono::Duration::minutes(amount),
+        "h" => chrono::Duration::hours(amount),
+        "d" => chrono::Duration::days(amount),
+        _ => return Err(invalid()),
+    };
+    Ok(Local::now() - ago)
+}
+
+// ── Multiplexed following ──
+
+const PREFIX_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Prints lines for a fixed set of sources: colored, padded `label |`
+/// prefixes (when enabled), pm's timestamps (when asked for) and an
+/// optional `--grep` filter, which sees the line as displayed.
+pub struct LineWriter {
+    prefixes: Vec<String>,
+    timestamps: bool,
+    grep: Option<Regex>,
+}
+
+impl LineWriter {
+    pub fn new(labels: &[String], prefixed: bool, timestamps: bool, grep: Option<Regex>) -> Self {
+        let width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+        let prefixes = labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                if prefixed {
+                    format!("{label:width$} | ")
+                        .color(PREFIX_COLORS[i % PREFIX_COLORS.len()])
+                        .to_string()
+                } else {
+                    String::new()
+                }
+            })
+            .collect();
+        Self {
+            prefixes,
+            timestamps,
+            grep,
+        }
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        self.grep
+            .as_ref()
+            .is_none_or(|re| re.is_match(&log_line::display(line, self.timestamps)))
+    }
+
+    /// Write stored `line` for source `idx` unless the filter rejects it.
+    fn write(&self, out: &mut impl Write, idx: usize, line: &str) -> std::io::Result<()> {
+        if self.matches(line) {
+            let shown = log_line::display(line, self.timestamps);
+            writeln!(out, "{}{}", self.prefixes[idx], shown)?;
+        }
+        Ok(())
+    }
+}
+
+/// A log file followed from a byte offset.
+pub struct LogSource {
+    label: String,
+    path: PathBuf,
+    pos: u64,
+    partial: Vec<u8>,
+}
+
+impl LogSource {
+    /// Follow `path` starting at byte `pos` (use the file length to skip
+    /// existing content).
+    pub fn new(label: impl Into<String>, path: impl Into<PathBuf>, pos: u64) -> Self {
+        Self {
+            label: label.into(),
+            path: path.into(),
+            pos,
+            partial: Vec::new(),
+        }
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Complete lines appended since the last call. A trailing fragment
+    /// without a newline is held back until it is finished.
+    pub fn poll(&mut self) -> Result<Vec<String>> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return Ok(Vec::new());
+        };
         let len = file.metadata()?.len();
-        if len < pos {
-            // File was rotated/truncated; reopen from the start.
-            file = File::open(path)?;
-            pos = 0;
+        if len < self.pos {
+            // Rotated or truncated: start over on the new file.
+            self.pos = 0;
+            self.partial.clear();
+        }
+        if len > self.pos {
+            file.seek(SeekFrom::Start(self.pos))?;
+            let read = file.read_to_end(&mut self.partial)?;
+            self.pos += read as u64;
         }
+        let Some(end) = self.partial.iter().rposition(|b| *b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        Ok(String::from_utf8_lossy(&complete)
+            .lines()
+            .map(|l| l.to_string())
+            .collect())
+    }
 
-        if len > pos {
-            file.seek(SeekFrom::Start(pos))?;
-            loop {
-                let n = file.read(&mut buf)?;
-                if n == 0 {
-                    break;
-                }
-                let mut handle = stdout.lock();
-                handle.write_all(&buf[..n])?;
-                handle.flush()?;
-                pos += n as u64;
-            }
+    /// Whatever is left in the buffer, for the final flush.
+    fn remainder(&mut self) -> Option<String> {
+        if self.partial.is_empty() {
+            return None;
         }
+        let rest = String::from_utf8_lossy(&self.partial).into_owned();
+        self.partial.clear();
+        Some(rest)
+    }
+}
 
+/// Interleave new lines from all `sources` on stdout through `writer`
+/// (whose labels are in the same order) until `stop` returns true.
+pub fn follow_many(
+    mut sources: Vec<LogSource>,
+    writer: &LineWriter,
+    mut stop: impl FnMut() -> bool,
+) -> Result<()> {
+    let stdout = std::io::stdout();
+    loop {
+        let stopping = stop();
+        for (idx, source) in sources.iter_mut().enumerate() {
+            let mut lines = source.poll()?;
+            if stopping {
+                lines.extend(source.remainder());
+            }
+            if lines.is_empty() {
+                continue;
+            }
+            let mut out = stdout.lock();
+            for line in lines {
+                writer.write(&mut out, idx, &line)?;
+            }
+            out.flush()?;
+        }
+        if stopping {
+            return Ok(());
+        }
         std::thread::sleep(Duration::from_millis(100));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_source_yields_complete_lines_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(&path, "before\n").unwrap();
+        let mut source = LogSource::new("back", &path, 7);
+        assert!(source.poll().unwrap().is_empty());
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "one\ntw").unwrap();
+        assert_eq!(source.poll().unwrap(), vec!["one"]);
+        writeln!(file, "o").unwrap();
+        assert_eq!(source.poll().unwrap(), vec!["two"]);
+        write!(file, "tail").unwrap();
+        assert!(source.poll().unwrap().is_empty());
+        assert_eq!(source.remainder().as_deref(), Some("tail"));
+    }
+
+    #[test]
+    fn log_source_restarts_after_truncation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(&path, "a long first line\n").unwrap();
+        let mut source = LogSource::new("back", &path, 0);
+        assert_eq!(source.poll().unwrap(), vec!["a long first line"]);
+        std::fs::write(&path, "new\n").unwrap();
+        assert_eq!(source.poll().unwrap(), vec!["new"]);
+    }
+
+    #[test]
+    fn line_writer_prefixes_and_filters() {
+        let labels = ["back".to_string(), "front".to_string()];
+        let writer = LineWriter::new(&labels, true, false, Some(Regex::new("err").unwrap()));
+        let mut out = Vec::new();
+        writer.write(&mut out, 0, "an error").unwrap();
+        writer.write(&mut out, 1, "fine").unwrap();
+        // The stream tag is hidden, so it does not match either.
+        writer.write(&mut out, 1, "2026-10-18T09:12:01.000Z err fine").unwrap();
+        writer.write(&mut out, 1, "2026-10-18T09:12:01.000Z out stderr line").unwrap();
+        let ansi = Regex::new("\x1b\\[[0-9;]*m").unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            ansi.replace_all(&out, ""),
+            "back  | an error\nfront | stderr line\n"
+        );
+    }
+
+    #[test]
+    fn history_spans_rotated_files_and_keeps_last_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(&path, "old 1\nold 2\n").unwrap();
+        log_rotation::rotate(&path, log_rotation::KEEP).unwrap();
+        std::fs::write(&path, "new 1\nnew 2\n").unwrap();
+        let writer = LineWriter::new(&["svc".to_string()], false, false, None);
+
+        let (lines, end) = read_history(&path, false, None, None, &writer).unwrap();
+        assert_eq!(lines, vec!["new 1", "new 2"]);
+        assert_eq!(end, 12);
+        let (lines, _) = read_history(&path, true, None, Some(3), &writer).unwrap();
+        assert_eq!(lines, vec!["old 2", "new 1", "new 2"]);
+    }
+
+    #[test]
+    fn since_uses_line_timestamps() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(
+            &path,
+            "2020-01-01T00:00:00.000Z out booting\n\
+             continuation of an old line\n\
+             2030-01-01T00:00:00.000Z err future\n\
+             continuation of a new line\n",
+        )
+        .unwrap();
+        let writer = LineWriter::new(&["svc".to_string()], false, false, None);
+        let cutoff = parse_since("2025-01-01T00:00:00Z").unwrap();
+        let (lines, _) = read_history(&path, false, Some(cutoff), None, &writer).unwrap();
+        assert_eq!(
+            lines,
+            vec!["2030-01-01T00:00:00.000Z err future", "continuation of a new line"]
+        );
+    }
+
+    #[test]
+    fn histories_merge_by_timestamp_before_the_limit() {
+        let front = vec![
+            "2026-10-18T09:00:01.000Z out front 1".to_string(),
+            "2026-10-18T09:00:03.000Z err front 3".to_string(),
+            "    at handler (front.js:1)".to_string(),
+        ];
+        let back = vec![
+            "2026-10-18T09:00:02.000Z out back 2".to_string(),
+            "2026-10-18T09:00:03.000Z out back 3".to_string(),
+            "2026-10-18T09:00:04.000Z pm back 4".to_string(),
+        ];
+        let merged = merge_histories(vec![front, back], Some(4));
+        let shown: Vec<(usize, &str)> = merged
+            .iter()
+            .map(|(idx, line)| (*idx, log_line::message(line)))
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                (0, "front 3"),
+                (0, "    at handler (front.js:1)"),
+                (1, "back 3"),
+                (1, "back 4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_untimestamped_lines_stay_with_their_file() {
+        // The history of `front` starts in the middle of a stack trace.
+        let front = vec![
+            "    at handler (front.js:1)".to_string(),
+            "2026-10-18T09:00:03.000Z out front 3".to_string(),
+        ];
+        let back = vec![
+            "2026-10-18T09:00:01.000Z out back 1".to_string(),
+            "2026-10-18T09:00:04.000Z out back 4".to_string(),
+        ];
+        let merged = merge_histories(vec![front, back], None);
+        let shown: Vec<(usize, &str)> = merged
+            .iter()
+            .map(|(idx, line)| (*idx, log_line::message(line)))
+            .collect();
+        assert_eq!(
+            shown,
+            vec![
+                (1, "back 1"),
+                (0, "    at handler (front.js:1)"),
+                (0, "front 3"),
+                (1, "back 4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_since_durations() {
+        let ago = Local::now() - parse_since("10m").unwrap();
+        assert!((ago.num_seconds() - 600).abs() <= 2);
+        assert!(parse_since("1d").is_ok());
+        assert!(parse_since("10").is_err());
+        assert!(parse_since("m").is_err());
+        assert!(parse_since("5w").is_err());
+    }
+}
@@ -15,8 +15,14 @@ pub mod plugin;
 pub mod ports;
 pub mod project;
 pub mod proxy;
+#[cfg(unix)]
+pub mod ps;
+#[cfg(unix)]
+pub mod readiness;
 pub mod remove;
 pub mod repo;
+#[cfg(unix)]
+pub mod restart;
 pub mod run;
 #[cfg(unix)]
 pub mod stop;
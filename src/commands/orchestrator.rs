@@ -2,36 +2,55 @@
 //!
 //! See `design.md` for the full chain. The high-level flow is:
 //!
-//! 1. Resolve services (all or one) from `.proj.yaml`.
+//! 1. Resolve services (all, or one plus its `depends_on`) from `.proj.yaml`
+//!    and order them so dependencies start first.
 //! 2. If `dev.auto_start_docker`, ensure `pm-local-db` and `pm-local-redis`
 //!    are running.
 //! 3. If Postgres is reachable on loopback, ensure the per-project database
 //!    exists (auto-`CREATE DATABASE`). Emit a v0.3.0 → v0.4.0 migration
 //!    notice if a legacy `<ws>_<proj>_local` is present.
 //! 4. Ensure the daemon is running (auto-spawn if needed).
-//! 5. For each target service: allocate a port via `pm ports assign` if
-//!    missing, open the log file, spawn the dev_cmd as a detached
-//!    subprocess, register the route, persist the running state.
-//! 6. Print a friendly summary and exit. The CLI returns; spawned services
+//! 5. Allocate a port for every service in `.proj.yaml` that lacks one, so
+//!    each service gets `PM_SERVICE_<NAME>_*` variables for all siblings.
+//!    Then, for each target service, open the log file and hand the
+//!    resolved command to the daemon, which spawns it detached, supervises
+//!    it according to its `restart:` policy, registers the route and
+//!    persists the state.
+//! 6. For services with a `ready:` probe, wait (with a spinner) until the
+//!    probe passes; on timeout or exit, show the last log lines.
+//! 7. Print a friendly summary and exit. The CLI returns; spawned services
 //!    keep running until `pm stop`.
+//!
+//! With `--attach`, step 7 instead follows every started service's log
+//! with `<service> |` prefixes until Ctrl+C (or until all of them have
+//! exited), then stops them in reverse dependency order.
 
 use crate::commands::db;
+use crate::commands::logs::{self, LineWriter, LogSource};
+use crate::commands::proxy::control;
 use crate::commands::proxy::daemon as proxy_daemon;
-use crate::commands::run::build_port_env;
-use crate::config::{load_config, logs_dir};
+use crate::commands::proxy::supervisor::SpawnSpec;
+use crate::commands::readiness;
+use crate::commands::run::{
+    EnvLayer, apply_env_layers, build_port_env, service_discovery_env, service_ports,
+};
+use crate::commands::stop;
+use crate::config::{load_config, logs_dir, service_log_path};
 use crate::models::{PortKind, PortProject, PortService, Project};
 use crate::path::collapse_path;
-use crate::project::{ProjConfig, ResolvedService, ServiceDef, resolve_service_defaults};
+use crate::project::{
+    ProjConfig, ResolvedService, ServiceDef, dependency_closure, resolve_service_defaults,
+    service_start_order,
+};
 use crate::routes;
 use crate::services as svc_state;
+use crate::shell_words;
 use anyhow::{Context, Result};
-use chrono::Utc;
 use colored::Colorize;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, OpenOptions};
-use std::os::unix::process::CommandExt;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// Entry point invoked by `pm run` once orchestrator mode has been selected.
 pub fn start(
@@ -40,6 +59,7 @@ pub fn start(
     project_dir: &Path,
     proj_config: &ProjConfig,
     target_service: Option<&str>,
+    attach: bool,
 ) -> Result<()> {
     let config = load_config()?;
     let services_to_start = pick_services(proj_config, target_service)?;
@@ -79,56 +99,220 @@ pub fn start(
         daemon_pid
     );
 
-    // 4. For each service: allocate port (if missing), spawn, register route.
+    // 4. Allocate ports for every defined service up front, so each spawned
+    //    service can be told where all of its siblings live.
+    allocate_all_ports(workspace, project, proj_config)?;
+
+    // 5. For each service: spawn, register route,
+    //    and wait for its readiness probe.
+    //    Services come in dependency order, so a dependency is ready (or
+    //    known to have failed) before its dependents are considered.
+    let mut not_ready: Vec<String> = Vec::new();
+    let mut started: Vec<String> = Vec::new();
+    let mut sources: Vec<LogSource> = Vec::new();
     for (name, resolved) in &services_to_start {
-        spawn_service(workspace, project, project_dir, name, resolved)?;
+        if let Some(dep) = resolved.depends_on.iter().find(|d| not_ready.contains(d)) {
+            eprintln!(
+                "  {} skipped {}/{} (dependency '{}' is not ready)",
+                "—".dimmed(),
+                project.name,
+                name,
+                dep
+            );
+            not_ready.push(name.clone());
+            continue;
+        }
+        let spawned = spawn_service(workspace, project, project_dir, proj_config, name, resolved)?;
+        if !spawned.ready {
+            not_ready.push(name.clone());
+        }
+        if spawned.started {
+            started.push(name.clone());
+        }
+        sources.push(LogSource::new(name.clone(), spawned.log_path, spawned.log_offset));
     }
 
-    print_summary(workspace, &project.name, &services_to_start);
+    let running: Vec<_> = services_to_start
+        .iter()
+        .filter(|(name, _)| !not_ready.contains(name))
+        .cloned()
+        .collect();
+    if !running.is_empty() {
+        print_summary(workspace, &project.name, &running);
+    }
+    if attach {
+        let names: Vec<String> = services_to_start.iter().map(|(n, _)| n.clone()).collect();
+        attach_and_stop(workspace, &project.name, &names, &started, sources)?;
+    }
+    if !not_ready.is_empty() {
+        return Err(anyhow::anyhow!(
+            "not ready: {} (see `pm logs <service>`)",
+            not_ready.join(", ")
+        ));
+    }
     Ok(())
 }
 
 // ── Service selection ──
 
-fn pick_services(
+/// Services to start, in dependency order. Starting a single service also
+/// starts whatever it (transitively) `depends_on`.
+pub fn pick_services(
     config: &ProjConfig,
     target: Option<&str>,
 ) -> Result<Vec<(String, ResolvedService)>> {
-    let mut out = Vec::new();
-    let mut keys: Vec<&String> = config.services.keys().collect();
-    keys.sort();
+    let order = service_start_order(&config.services)?;
+    let wanted = match target {
+        Some(t) if !config.services.contains_key(t) => {
+            return Err(anyhow::anyhow!(
+                "service '{t}' is not defined in .proj.yaml"
+            ));
+        }
+        Some(t) => Some(dependency_closure(&config.services, t)),
+        None => None,
+    };
 
-    for key in keys {
-        if let Some(t) = target
-            && t != key
+    let mut out = Vec::new();
+    for key in order {
+        if let Some(wanted) = &wanted
+            && !wanted.contains(&key)
         {
             continue;
         }
-        let def: &ServiceDef = &config.services[key];
+        let def: &ServiceDef = &config.services[&key];
         let resolved = resolve_service_defaults(def, config.framework.as_deref())
             .with_context(|| format!("resolving service '{key}'"))?;
-        out.push((key.clone(), resolved));
+        out.push((key, resolved));
     }
+    check_unique_paths(config)?;
+    Ok(out)
+}
 
-    if let Some(t) = target
-        && out.is_empty()
-    {
-        return Err(anyhow::anyhow!(
-            "service '{t}' is not defined in .proj.yaml"
-        ));
+/// Two services claiming the same `path:` would make the project hostname
+/// ambiguous; reject that up front rather than letting the last spawn win.
+fn check_unique_paths(config: &ProjConfig) -> Result<()> {
+    let mut seen: Vec<(String, &String)> = Vec::new();
+    let mut keys: Vec<&String> = config.services.keys().collect();
+    keys.sort();
+    for key in keys {
+        // Unresolvable services are reported when (and if) they are started.
+        let Ok(resolved) =
+            resolve_service_defaults(&config.services[key], config.framework.as_deref())
+        else {
+            continue;
+        };
+        let Some(path) = resolved.path else { continue };
+        if let Some((_, other)) = seen.iter().find(|(p, _)| *p == path) {
+            return Err(anyhow::anyhow!(
+                "services '{other}' and '{key}' both claim path '{path}' in .proj.yaml"
+            ));
+        }
+        seen.push((path, key));
     }
-    Ok(out)
+    Ok(())
+}
+
+// ── Attached mode ──
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: nix::libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Follow the services' logs in the foreground until Ctrl+C or until none
+/// of them is running any more, then stop the ones this run `started`
+/// (dependents first). Services that were already running are left alone.
+/// A failed stop does not keep the others running; all failures are
+/// reported together at the end.
+fn attach_and_stop(
+    workspace: &str,
+    project: &str,
+    names: &[String],
+    started: &[String],
+    sources: Vec<LogSource>,
+) -> Result<()> {
+    use nix::sys::signal::{SaFlags, SigAction, SigHandler, SigSet, Signal, sigaction};
+
+    let action = SigAction::new(
+        SigHandler::Handler(on_interrupt),
+        SaFlags::empty(),
+        SigSet::empty(),
+    );
+    for signal in [Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP] {
+        // SAFETY: the handler only stores to an atomic.
+        unsafe { sigaction(signal, &action) }.context("installing signal handler")?;
+    }
+    let on_exit = match started.len() {
+        0 => "Ctrl+C detaches".to_string(),
+        n if n == names.len() => "Ctrl+C stops them".to_string(),
+        n => format!("Ctrl+C stops the {n} started by this run"),
+    };
+    eprintln!(
+        "  {} attached to {} service(s); {}",
+        "i".cyan(),
+        names.len(),
+        on_exit
+    );
+
+    let labels: Vec<String> = sources.iter().map(|s| s.label().to_string()).collect();
+    let writer = LineWriter::new(&labels, true, false, None);
+    let mut polls: u32 = 0;
+    let mut all_exited = false;
+    logs::follow_many(sources, &writer, || {
+        polls += 1;
+        // services.json is consulted about once a second.
+        if polls.is_multiple_of(10) {
+            all_exited = names.iter().all(|name| {
+                !matches!(
+                    svc_state::get(workspace, project, name),
+                    Ok(Some(state)) if state.status != svc_state::ServiceStatus::Exited
+                )
+            });
+        }
+        all_exited || INTERRUPTED.load(Ordering::SeqCst)
+    })?;
+
+    if all_exited {
+        eprintln!("  {} all services have exited", "i".cyan());
+    } else {
+        eprintln!();
+    }
+    let mut failed = Vec::new();
+    for name in names.iter().rev().filter(|name| started.contains(name)) {
+        if let Err(e) = stop::stop_service(workspace, project, name, true) {
+            eprintln!("  {} stopping {}/{}: {:#}", "!".yellow(), project, name, e);
+            failed.push(name.as_str());
+        }
+    }
+    if !failed.is_empty() {
+        return Err(anyhow::anyhow!("could not stop: {}", failed.join(", ")));
+    }
+    Ok(())
 }
 
 // ── Per-service spawn ──
 
+/// Where a spawned service logs, and whether its readiness probe passed.
+struct Spawned {
+    log_path: PathBuf,
+    /// Log length before this spawn; following from here shows its output.
+    log_offset: u64,
+    ready: bool,
+    /// False when the service was already running and was left as is.
+    started: bool,
+}
+
+/// Spawn one service through the daemon. `ready` is false when the
+/// service was spawned but its readiness probe failed (already reported).
 fn spawn_service(
     workspace: &str,
     project: &Project,
     project_dir: &Path,
+    proj_config: &ProjConfig,
     service_key: &str,
     resolved: &ResolvedService,
-) -> Result<()> {
+) -> Result<Spawned> {
     // If a previous spawn is still alive, treat as a no-op so that
     // `pm run` is idempotent.
     if let Some(state) = svc_state::get(workspace, &project.name, service_key)?
@@ -142,87 +326,199 @@ fn spawn_service(
             state.pid,
             state.port
         );
-        return Ok(());
+        let log_offset = fs::metadata(&state.log_path).map(|m| m.len()).unwrap_or(0);
+        return Ok(Spawned {
+            log_path: state.log_path,
+            log_offset,
+            ready: true,
+            started: false,
+        });
     }
 
+    // The child gets the CLI's environment plus the pm-provided ports, so
+    // it behaves as if it had been started from this shell.
+    let spec = spawn_spec(
+        workspace,
+        project,
+        project_dir,
+        proj_config,
+        service_key,
+        resolved,
+        std::env::vars().collect(),
+    )?;
+    // Log probes only look at what this spawn writes.
+    let log_offset = fs::metadata(&spec.log_path).map(|m| m.len()).unwrap_or(0);
+    let (log_path, port) = (spec.log_path.clone(), spec.port);
+    // The daemon spawns the process, records it in services.json, and
+    // registers its routes.
+    let pid = control::request_spawn(&spec)
+        .with_context(|| format!("starting service '{service_key}'"))?;
+
+    let Some(probe) = &resolved.ready else {
+        eprintln!(
+            "  {} spawned {}/{} (pid {}, port {})",
+            "✓".green(),
+            project.name,
+            service_key,
+            pid,
+            port
+        );
+        return Ok(Spawned {
+            log_path,
+            log_offset,
+            ready: true,
+            started: true,
+        });
+    };
+
+    let label = format!("{}/{}", project.name, service_key);
+    let target = readiness::Target {
+        label: &label,
+        port,
+        log_path: &log_path,
+        log_offset,
+    };
+    let outcome = readiness::wait_until_ready(&target, probe, || {
+        gone(workspace, &project.name, service_key)
+    });
+    let ready = match outcome {
+        readiness::Outcome::Ready(elapsed) => {
+            eprintln!(
+                "  {} {} ready in {:.1}s (pid {}, port {})",
+                "✓".green(),
+                label,
+                elapsed.as_secs_f32(),
+                pid,
+                port
+            );
+            true
+        }
+        failed => {
+            readiness::report_failure(&target, probe, &failed);
+            false
+        }
+    };
+    Ok(Spawned {
+        log_path,
+        log_offset,
+        ready,
+        started: true,
+    })
+}
+
+/// Resolve everything the daemon needs to run one service: its port
+/// (allocated if missing), working directory, fresh log file, and the
+/// environment — `base_env` plus pm's ports, service discovery and the
+/// `.proj.yaml` env layers. Shared by `pm run` (with the shell's
+/// environment) and the daemon's control API.
+pub fn spawn_spec(
+    workspace: &str,
+    project: &Project,
+    project_dir: &Path,
+    proj_config: &ProjConfig,
+    service_key: &str,
+    resolved: &ResolvedService,
+    base_env: HashMap<String, String>,
+) -> Result<SpawnSpec> {
     let port = ensure_port(workspace, project, service_key, resolved)?;
     let cwd: PathBuf = PathBuf::from(collapse_path(&project_dir.join(&resolved.dir)));
+    let ignore_files = ignore_files(project_dir, &cwd);
     fs::create_dir_all(&cwd).ok();
 
     let log_path = open_service_log(workspace, &project.name, service_key)?;
-    let log_for_stdout = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)?;
-    let log_for_stderr = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)?;
 
-    let mut env = build_port_env(workspace, project)?;
+    let mut env = base_env.clone();
+    env.extend(build_port_env(workspace, project)?);
+    let ports = service_ports(workspace, project)?;
+    let proxy_port = load_config().map(|c| c.dev.proxy_port).unwrap_or(7100);
+    env.extend(service_discovery_env(workspace, &project.name, &ports, proxy_port));
     // Service-specific port — overlays the kind's default APP_PORT/etc.
     env.insert(env_var_for_kind(resolved.port_kind).into(), port.to_string());
+    // Then `.proj.yaml` env: project level, then this service.
+    apply_env_layers(
+        &mut env,
+        &[
+            EnvLayer {
+                base_dir: project_dir,
+                env_file: &proj_config.env_file,
+                env: &proj_config.env,
+            },
+            EnvLayer {
+                base_dir: &cwd,
+                env_file: &resolved.env_file,
+                env: &resolved.env,
+            },
+        ],
+        &ports,
+    )
+    .with_context(|| format!("building environment for '{service_key}'"))?;
+
+    let (program, args) = split_dev_cmd(resolved, &mut env)?;
+    Ok(SpawnSpec {
+        workspace: workspace.to_string(),
+        project: project.name.clone(),
+        service: service_key.to_string(),
+        program,
+        args,
+        cwd,
+        env,
+        shell_env: base_env,
+        log_path,
+        port,
+        dev_cmd: resolved.dev_cmd.clone(),
+        restart: resolved.restart,
+        stop_timeout_secs: resolved.stop_timeout_secs,
+        path_prefix: resolved.path.clone(),
+        strip_prefix: resolved.strip_path,
+        access_log: resolved.access_log,
+        rewrite_host: resolved.rewrite_host,
+        hostnames: resolved.hostnames.clone(),
+        wildcard: resolved.wildcard,
+        watch: resolved.watch.clone(),
+        ignore_files,
+    })
+}
 
-    let (program, args) = split_dev_cmd(&resolved.dev_cmd);
-    let mut cmd = Command::new(&program);
-    cmd.args(&args)
-        .current_dir(&cwd)
-        .envs(&env)
-        .stdin(Stdio::null())
-        .stdout(Stdio::from(log_for_stdout))
-        .stderr(Stdio::from(log_for_stderr));
-
-    unsafe {
-        cmd.pre_exec(|| {
-            // Detach from the CLI's process group so Ctrl+C in the parent
-            // shell does not propagate to long-running services.
-            nix::unistd::setsid()
-                .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
-            Ok(())
-        });
+/// For readiness waits: why the service is down for good, if it is.
+pub fn gone(workspace: &str, project: &str, service: &str) -> Option<String> {
+    match svc_state::get(workspace, project, service) {
+        Ok(None) => Some("stopped".to_string()),
+        Ok(Some(state)) if state.status == svc_state::ServiceStatus::Exited => {
+            Some(state.last_exit().unwrap_or_else(|| "exited".to_string()))
+        }
+        _ => None,
     }
+}
 
-    let child = cmd.spawn().with_context(|| {
-        format!("spawning service '{service_key}' (cmd: {})", resolved.dev_cmd)
-    })?;
-    let pid = child.id();
-    // Important: do not wait. Drop the Child handle so it doesn't reap on
-    // CLI exit (it can't anyway thanks to setsid, but we are explicit).
-    drop(child);
-
-    // Persist state.
-    svc_state::upsert(
-        workspace,
-        &project.name,
-        service_key,
-        svc_state::ServiceState {
-            pid,
-            port,
-            started_at: Utc::now(),
-            log_path: log_path.clone(),
-            dev_cmd: resolved.dev_cmd.clone(),
-        },
-    )?;
-
-    // Register route.
-    routes::register_service(workspace, &project.name, service_key, port)?;
-
-    eprintln!(
-        "  {} spawned {}/{} (pid {}, port {})",
-        "✓".green(),
-        project.name,
-        service_key,
-        pid,
-        port
-    );
-    Ok(())
+/// `.gitignore` files that apply to a watched service: the project's and
+/// the service directory's own (pm synthesizes the former).
+fn ignore_files(project_dir: &Path, cwd: &Path) -> Vec<PathBuf> {
+    let mut files = vec![project_dir.join(".gitignore")];
+    if cwd != project_dir {
+        files.push(cwd.join(".gitignore"));
+    }
+    files
 }
 
-fn split_dev_cmd(dev_cmd: &str) -> (String, Vec<String>) {
-    let mut iter = dev_cmd.split_whitespace();
-    let program = iter.next().unwrap_or("").to_string();
-    let args: Vec<String> = iter.map(|s| s.to_string()).collect();
-    (program, args)
+/// Turn the service's `dev_cmd` into a program and arguments. Leading
+/// `VAR=value` words are moved into `env`; `shell: true` hands the whole
+/// command to `$SHELL -c` instead.
+fn split_dev_cmd(
+    resolved: &ResolvedService,
+    env: &mut HashMap<String, String>,
+) -> Result<(String, Vec<String>)> {
+    if resolved.shell {
+        let shell = env
+            .get("SHELL")
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .unwrap_or_else(|| "/bin/sh".to_string());
+        return Ok((shell, vec!["-c".to_string(), resolved.dev_cmd.clone()]));
+    }
+    let parsed = shell_words::parse_command(&resolved.dev_cmd)
+        .with_context(|| format!("parsing dev_cmd `{}`", resolved.dev_cmd))?;
+    env.extend(parsed.env);
+    Ok((parsed.program, parsed.args))
 }
 
 fn env_var_for_kind(kind: PortKind) -> &'static str {
@@ -231,15 +527,34 @@ fn env_var_for_kind(kind: PortKind) -> &'static str {
 
 // ── Port allocation ──
 
+/// Make sure every service in `.proj.yaml` has a port, not just the ones
+/// being started. Services that do not resolve are skipped here and
+/// reported if they are started.
+pub fn allocate_all_ports(workspace: &str, project: &Project, config: &ProjConfig) -> Result<()> {
+    let mut keys: Vec<&String> = config.services.keys().collect();
+    keys.sort();
+    for key in keys {
+        if let Ok(resolved) =
+            resolve_service_defaults(&config.services[key], config.framework.as_deref())
+        {
+            ensure_port(workspace, project, key, &resolved)?;
+        }
+    }
+    Ok(())
+}
+
 fn ensure_port(
     workspace: &str,
     project: &Project,
     service_key: &str,
     resolved: &ResolvedService,
 ) -> Result<u16> {
-    use crate::config::{load_ports, save_ports};
+    use crate::config::{load_ports, ports_path, save_ports};
 
     let project_key = format!("{workspace}/{}", project.name);
+    // Held until the new port is saved, so parallel `pm run`s cannot both
+    // pick the same free port or drop each other's assignments.
+    let _lock = crate::lock::lock(&ports_path())?;
     let mut ports = load_ports()?;
 
     // Existing assignment?
@@ -331,10 +646,8 @@ fn ensure_project_database(workspace: &str, project: &str) -> Result<()> {
 // ── Logging ──
 
 fn open_service_log(workspace: &str, project: &str, service: &str) -> Result<PathBuf> {
-    let dir = logs_dir();
-    fs::create_dir_all(&dir).ok();
-    let name = format!("{workspace}_{project}_{service}.log");
-    let path = dir.join(name);
+    fs::create_dir_all(logs_dir()).ok();
+    let path = service_log_path(workspace, project, service);
 
     // Rotate before re-opening so the new spawn writes to a fresh `.log`
     // when the previous run accumulated more than MAX_BYTES.
@@ -366,23 +679,42 @@ fn announce_container_state(name: &str, state: db::ContainerState) {
 }
 
 fn print_summary(workspace: &str, project: &str, services: &[(String, ResolvedService)]) {
+    let proxy_port = load_config()
+        .map(|c| c.dev.proxy_port)
+        .unwrap_or(7100);
+    let project_host = routes::project_hostnames(workspace, project)
+        .pop()
+        .unwrap_or_default();
     println!();
     println!("{}", "Services running:".bold());
-    for (name, _) in services {
+    for (name, resolved) in services {
         let host = if workspace == "default" {
             format!("{name}.{project}.localhost")
         } else {
             format!("{name}.{project}.{workspace}.localhost")
         };
-        let proxy_port = load_config()
-            .map(|c| c.dev.proxy_port)
-            .unwrap_or(7100);
-        println!(
+        print!(
             "  {} http://{}:{}/",
             "→".cyan(),
             host,
             proxy_port
         );
+        let mut also: Vec<String> = resolved
+            .hostnames
+            .iter()
+            .map(|h| format!("http://{h}:{proxy_port}/"))
+            .collect();
+        if resolved.wildcard {
+            also.push(format!("http://*.{host}:{proxy_port}/"));
+        }
+        if let Some(path) = resolved.path.as_deref() {
+            also.push(format!("http://{project_host}:{proxy_port}{path}"));
+        }
+        if also.is_empty() {
+            println!();
+        } else {
+            println!("  {}", format!("(also {})", also.join(", ")).dimmed());
+        }
     }
     println!();
     println!(
@@ -396,24 +728,71 @@ fn print_summary(workspace: &str, project: &str, services: &[(String, ResolvedSe
 mod tests {
     use super::*;
 
+    fn service(dev_cmd: &str, shell: bool) -> ResolvedService {
+        ResolvedService {
+            dir: ".".into(),
+            dev_cmd: dev_cmd.into(),
+            shell,
+            port_kind: PortKind::Backend,
+            framework: None,
+            path: None,
+            strip_path: false,
+            access_log: false,
+            rewrite_host: false,
+            hostnames: Vec::new(),
+            wildcard: false,
+            restart: Default::default(),
+            stop_timeout_secs: 10,
+            ready: None,
+            depends_on: Vec::new(),
+            watch: None,
+            env: HashMap::new(),
+            env_file: Vec::new(),
+        }
+    }
+
+    fn split(dev_cmd: &str) -> (String, Vec<String>) {
+        split_dev_cmd(&service(dev_cmd, false), &mut HashMap::new()).unwrap()
+    }
+
     #[test]
     fn split_dev_cmd_simple() {
-        let (p, a) = split_dev_cmd("cargo run");
+        let (p, a) = split("cargo run");
         assert_eq!(p, "cargo");
         assert_eq!(a, vec!["run".to_string()]);
     }
 
     #[test]
     fn split_dev_cmd_multi_arg() {
-        let (p, a) = split_dev_cmd("pnpm next dev --turbopack");
+        let (p, a) = split("pnpm next dev --turbopack");
         assert_eq!(p, "pnpm");
         assert_eq!(a, vec!["next", "dev", "--turbopack"]);
     }
 
     #[test]
     fn split_dev_cmd_single_program() {
-        let (p, a) = split_dev_cmd("flutter");
+        let (p, a) = split("flutter");
         assert_eq!(p, "flutter");
         assert!(a.is_empty());
     }
+
+    #[test]
+    fn split_dev_cmd_moves_assignments_into_env() {
+        let mut env = HashMap::from([("FOO".to_string(), "old".to_string())]);
+        let (p, a) = split_dev_cmd(&service("FOO=bar cargo run", false), &mut env).unwrap();
+        assert_eq!(p, "cargo");
+        assert_eq!(a, vec!["run"]);
+        assert_eq!(env["FOO"], "bar");
+    }
+
+    #[test]
+    fn split_dev_cmd_shell_mode_uses_shell_env() {
+        let mut env = HashMap::from([("SHELL".to_string(), "/bin/zsh".to_string())]);
+        let (p, a) = split_dev_cmd(&service("a && b", true), &mut env).unwrap();
+        assert_eq!(p, "/bin/zsh");
+        assert_eq!(a, vec!["-c", "a && b"]);
+
+        let (p, _) = split_dev_cmd(&service("a && b", true), &mut HashMap::new()).unwrap();
+        assert_eq!(p, "/bin/sh");
+    }
 }
@@ -1,5 +1,6 @@
 use crate::cli::PortsCommand;
-use crate::config::{load_ports, save_ports};
+use crate::config::{load_ports, ports_path, save_ports};
+use crate::lock;
 use crate::error::PmError;
 use crate::models::{PortKind, PortProject, PortService, PortsData, Project};
 use crate::state::{detect_current_project, load_state, parse_target, project_path_display};
@@ -66,6 +67,7 @@ fn assign(project_name: Option<String>, kinds: Vec<PortKind>, force: bool) -> Re
         ));
     }
 
+    let _lock = lock::lock(&ports_path())?;
     let mut ports = load_ports()?;
 
     ensure_project_entry(&mut ports, &project_key, &workspace, &project, &path);
@@ -159,9 +161,8 @@ fn check(project_name: Option<String>, all: bool) -> Result<()> {
     println!();
     if has_per_project_issue {
         println!(
-            "{} {}",
-            "!".yellow(),
-            "Review duplicate rows. Bound means localhost already has a listener on that port."
+            "{} Review duplicate rows. Bound means localhost already has a listener on that port.",
+            "!".yellow()
         );
     } else if shared_bound {
         println!(
@@ -178,6 +179,7 @@ fn check(project_name: Option<String>, all: bool) -> Result<()> {
 fn repair(project_name: Option<String>) -> Result<()> {
     let (workspace, project, _) = resolve_project(project_name)?;
     let project_key = project_key(&workspace, &project.name);
+    let _lock = lock::lock(&ports_path())?;
     let mut ports = load_ports()?;
     let duplicates = duplicate_ports(&ports);
 
@@ -223,19 +225,19 @@ fn repair(project_name: Option<String>) -> Result<()> {
         }
 
         let new_port = choose_port(&ports, service.kind, Some((&project_key, &service_key)))?;
-        if let Some(entry) = ports.projects.get_mut(&project_key) {
-            if let Some(target) = entry.services.get_mut(&service_key) {
-                target.port = new_port;
-                repaired += 1;
-                println!(
-                    "{} Repaired {}.{} {} -> {}",
-                    "✓".green(),
-                    project.name.cyan(),
-                    service_key.cyan(),
-                    service.port,
-                    new_port
-                );
-            }
+        if let Some(entry) = ports.projects.get_mut(&project_key)
+            && let Some(target) = entry.services.get_mut(&service_key)
+        {
+            target.port = new_port;
+            repaired += 1;
+            println!(
+                "{} Repaired {}.{} {} -> {}",
+                "✓".green(),
+                project.name.cyan(),
+                service_key.cyan(),
+                service.port,
+                new_port
+            );
         }
     }
 
@@ -250,6 +252,7 @@ fn repair(project_name: Option<String>) -> Result<()> {
 fn release(project_name: Option<String>, kinds: Vec<PortKind>) -> Result<()> {
     let (workspace, project, _) = resolve_project(project_name)?;
     let project_key = project_key(&workspace, &project.name);
+    let _lock = lock::lock(&ports_path())?;
     let mut ports = load_ports()?;
 
     if kinds.is_empty() {
@@ -296,6 +299,7 @@ fn release(project_name: Option<String>, kinds: Vec<PortKind>) -> Result<()> {
 fn lock(project_name: Option<String>, service_key: String, locked: bool) -> Result<()> {
     let (workspace, project, _) = resolve_project(project_name)?;
     let project_key = project_key(&workspace, &project.name);
+    let _lock = lock::lock(&ports_path())?;
     let mut ports = load_ports()?;
 
     let service = ports
@@ -333,6 +337,7 @@ fn shared(postgres: Option<u16>, redis: Option<u16>) -> Result<()> {
         return Err(anyhow!("Invalid redis port: 0"));
     }
 
+    let _lock = lock::lock(&ports_path())?;
     let mut ports = load_ports()?;
 
     if postgres.is_none() && redis.is_none() {
@@ -398,14 +403,13 @@ fn resolve_project(project_name: Option<String>) -> Result<(String, Project, Str
     let project = match project_name {
         Some(target) => {
             let (workspace_name, project_name) = parse_target(target);
-            if let Some(workspace_name) = &workspace_name {
-                if !manifest
+            if let Some(workspace_name) = &workspace_name
+                && !manifest
                     .workspaces
                     .iter()
                     .any(|workspace| &workspace.name == workspace_name)
-                {
-                    return Err(PmError::WorkspaceNotFound(workspace_name.clone()).into());
-                }
+            {
+                return Err(PmError::WorkspaceNotFound(workspace_name.clone()).into());
             }
 
             manifest
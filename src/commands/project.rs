@@ -176,6 +176,8 @@ fn cmd_init(
         config_version: String::new(),
         includes,
         services,
+        env: Default::default(),
+        env_file: Vec::new(),
     };
     let source_files = proj::collect_all_source_files(&repo_path, &proj_config)?;
 
@@ -297,6 +299,8 @@ fn cmd_add(language: Option<String>, framework: Option<String>) -> Result<()> {
         config_version: head.clone(),
         includes: Vec::new(),
         services: Default::default(),
+        env: Default::default(),
+        env_file: Vec::new(),
     };
     proj::save_proj_config(&cwd, &proj_config)?;
 
@@ -444,7 +448,7 @@ fn sync_project(
 }
 
 fn sync_all(repo_path: &Path, dry_run: bool) -> Result<()> {
-    let (config, mut manifest) = state::load_state()?;
+    let (config, manifest) = state::load_state()?;
 
     let proj_projects: Vec<_> = manifest
         .projects
@@ -459,7 +463,7 @@ fn sync_all(repo_path: &Path, dry_run: bool) -> Result<()> {
     }
 
     let head = proj::config_repo_head(repo_path)?;
-    let mut manifest_changed = false;
+    let mut synced = Vec::new();
 
     for (name, _meta) in &proj_projects {
         let project = state::find_project(&manifest, name)?;
@@ -475,12 +479,7 @@ fn sync_all(repo_path: &Path, dry_run: bool) -> Result<()> {
         match sync_project(repo_path, &project_path, Some(name), dry_run) {
             Ok(()) => {
                 if !dry_run {
-                    if let Ok(p) = find_project_mut(&mut manifest, name) {
-                        if let Some(ref mut proj_meta) = p.proj {
-                            proj_meta.config_version = head.clone();
-                            manifest_changed = true;
-                        }
-                    }
+                    synced.push(name);
                 }
             }
             Err(e) => {
@@ -490,8 +489,19 @@ fn sync_all(repo_path: &Path, dry_run: bool) -> Result<()> {
         println!();
     }
 
-    if manifest_changed {
-        let _ = state::save_state(&config, &manifest);
+    // Record the new versions under the state lock, which the syncs
+    // above would have held for too long.
+    if !synced.is_empty() {
+        let _ = state::update_state(|_, manifest| {
+            for name in synced {
+                if let Ok(p) = find_project_mut(manifest, name)
+                    && let Some(ref mut proj_meta) = p.proj
+                {
+                    proj_meta.config_version = head.clone();
+                }
+            }
+            Ok(())
+        });
     }
 
     Ok(())
@@ -839,6 +849,9 @@ fn try_update_manifest_proj_meta(
     framework: Option<&str>,
     config_version: &str,
 ) {
+    let Ok(_lock) = state::lock_state() else {
+        return;
+    };
     let Ok((config, mut manifest)) = state::load_state() else {
         return;
     };
@@ -2,36 +2,80 @@
 //!
 //! Endpoints (all on `127.0.0.1:dev.control_port`, default 7101):
 //!
-//! | method | path     | purpose                                            |
-//! |--------|----------|----------------------------------------------------|
-//! | GET    | /health  | liveness — returns 200 with `{ pid }`              |
-//! | GET    | /status  | introspection — pid, uptime, route count, port     |
-//! | POST   | /reload  | force-reload routes.json (debugging aid)           |
-//! | POST   | /stop    | trigger graceful shutdown                          |
+//! | method | path            | purpose                                          |
+//! |--------|-----------------|--------------------------------------------------|
+//! | GET    | /health         | liveness — returns 200 with `{ pid }`            |
+//! | GET    | /status         | introspection — pid, uptime, route count, ports  |
+//! | GET    | /events         | live NDJSON event stream ([`events`](super::events)) |
+//! | GET    | /requests       | recent proxied requests ([`inspect`](super::inspect)) |
+//! | POST   | /reload         | reload routes.json now, returns `{ routes }`     |
+//! | POST   | /stop           | trigger graceful shutdown                        |
+//! | GET    | /services       | per-service CPU, RSS, threads, listening ports   |
+//! | POST   | /services/spawn | start and supervise a service ([`SpawnSpec`])    |
+//!
+//! Per-service start, stop, restart and logs live under
+//! `/services/{ws}/{project}/{svc}/...`; see [`service_api`].
+//!
+//! Anything but a `GET` changes state, and web pages can reach loopback
+//! ports too (a no-cors `POST`, or DNS rebinding a hostile name onto
+//! 127.0.0.1). Such requests are only accepted without an `Origin`, with
+//! `Content-Type: application/json` and with a `Host` naming the control
+//! port itself; see [`check_caller`].
 //!
 //! All responses use `application/json`. Bodies are best-effort; clients
 //! that only care about status codes (e.g. `pm proxy status` printing a
 //! tabular view) should still work if the body parse fails.
 
 use crate::commands::proxy::daemon;
+use crate::commands::proxy::events::Events;
+use crate::commands::proxy::inspect::{RequestFilter, RequestRecord};
+use crate::commands::proxy::reverse::Proxy;
+use crate::commands::proxy::service_api::{self, ApiError, LogQuery};
+use crate::commands::proxy::supervisor::{SpawnReply, SpawnSpec, Supervisor};
 use crate::config::{daemon_pid_path, load_config};
-use crate::routes::load_routes;
+use crate::lock::LOCK_TIMEOUT;
+use crate::proc_stats;
+use crate::services as svc_state;
 use anyhow::{Context, Result};
 use bytes::Bytes;
 use colored::Colorize;
-use http_body_util::Full;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
 use hyper::body::Incoming;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
+use hyper::header::{CONTENT_TYPE, HOST, HeaderMap, ORIGIN};
 use hyper::{Method, Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
 use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
 use std::fs;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
 use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
-use tokio::sync::Notify;
+use tokio::sync::{mpsc, Notify};
+
+type ControlBody = BoxBody<Bytes, Infallible>;
+
+/// Streamed response body fed by a channel. The sending task can tell
+/// from `closed()` when the client has gone away.
+pub struct ChannelBody(pub mpsc::Receiver<Bytes>);
+
+impl hyper::body::Body for ChannelBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<hyper::body::Frame<Bytes>, Infallible>>> {
+        self.0
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|b| Ok(hyper::body::Frame::data(b))))
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StatusBody {
@@ -39,10 +83,18 @@ pub struct StatusBody {
     pub uptime_sec: u64,
     pub proxy_port: u16,
     pub control_port: u16,
+    #[serde(default)]
+    pub https_port: Option<u16>,
     pub routes_count: usize,
 }
 
-pub async fn serve(port: u16, shutdown: Arc<Notify>) -> Result<()> {
+pub async fn serve(
+    port: u16,
+    shutdown: Arc<Notify>,
+    supervisor: Arc<Supervisor>,
+    events: Events,
+    proxy: Arc<Proxy>,
+) -> Result<()> {
     let addr = format!("127.0.0.1:{port}");
     let listener = TcpListener::bind(&addr).await?;
     let started_at = Instant::now();
@@ -61,12 +113,20 @@ pub async fn serve(port: u16, shutdown: Arc<Notify>) -> Result<()> {
         };
 
         let shutdown = shutdown.clone();
+        let supervisor = supervisor.clone();
+        let events = events.clone();
+        let proxy = proxy.clone();
         let io = TokioIo::new(stream);
         tokio::spawn(async move {
             let svc = service_fn(move |req: Request<Incoming>| {
                 let shutdown = shutdown.clone();
+                let supervisor = supervisor.clone();
+                let events = events.clone();
+                let proxy = proxy.clone();
                 async move {
-                    Ok::<_, Infallible>(dispatch(req, started_at, shutdown).await)
+                    Ok::<_, Infallible>(
+                        dispatch(req, port, started_at, shutdown, supervisor, events, proxy).await,
+                    )
                 }
             });
             if let Err(e) = http1::Builder::new().serve_connection(io, svc).await {
@@ -79,55 +139,150 @@ pub async fn serve(port: u16, shutdown: Arc<Notify>) -> Result<()> {
 
 async fn dispatch(
     req: Request<Incoming>,
+    port: u16,
     started_at: Instant,
     shutdown: Arc<Notify>,
-) -> Response<Full<Bytes>> {
+    supervisor: Arc<Supervisor>,
+    events: Events,
+    proxy: Arc<Proxy>,
+) -> Response<ControlBody> {
+    if req.method() != Method::GET
+        && let Err((status, msg)) = check_caller(req.headers(), port)
+    {
+        return text(status, msg);
+    }
     match (req.method(), req.uri().path()) {
         (&Method::GET, "/health") => json(StatusCode::OK, &serde_json::json!({ "pid": std::process::id() })),
-        (&Method::GET, "/status") => match build_status(started_at) {
+        (&Method::GET, "/status") => match build_status(started_at, &proxy) {
             Ok(s) => json(StatusCode::OK, &s),
             Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e}")),
         },
-        (&Method::POST, "/reload") => {
-            // The proxy already mtime-checks on every request, so explicit
-            // reload is a no-op here. Provided for parity with portless and
-            // user expectations.
-            json(StatusCode::OK, &serde_json::json!({ "ok": true }))
-        }
+        (&Method::GET, "/events") => Response::builder()
+            .status(StatusCode::OK)
+            .header("content-type", "application/x-ndjson")
+            .body(events.stream().boxed())
+            .unwrap(),
+        (&Method::GET, "/requests") => match RequestFilter::parse(req.uri().query()) {
+            Ok(filter) => json(StatusCode::OK, &proxy.inspector().recent(&filter)),
+            Err(e) => text(StatusCode::BAD_REQUEST, &format!("{e:#}")),
+        },
+        (&Method::POST, "/reload") => match proxy.reload() {
+            Ok(routes) => json(StatusCode::OK, &serde_json::json!({ "ok": true, "routes": routes })),
+            Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e:#}")),
+        },
         (&Method::POST, "/stop") => {
             shutdown.notify_waiters();
             json(StatusCode::OK, &serde_json::json!({ "ok": true }))
         }
-        _ => text(StatusCode::NOT_FOUND, "not found"),
+        (&Method::GET, "/services") => {
+            let stats = tokio::task::spawn_blocking(|| {
+                proc_stats::collect(&svc_state::load()?, proc_stats::SAMPLE_INTERVAL)
+            })
+            .await;
+            match stats {
+                Ok(Ok(stats)) => json(StatusCode::OK, &stats),
+                Ok(Err(e)) => text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e:#}")),
+                Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e}")),
+            }
+        }
+        (&Method::POST, "/services/spawn") => {
+            let spec: SpawnSpec = match read_json(req).await {
+                Ok(spec) => spec,
+                Err(e) => return text(StatusCode::BAD_REQUEST, &format!("{e:#}")),
+            };
+            // Recording the service takes the state-file locks.
+            match tokio::task::spawn_blocking(move || supervisor.spawn(spec)).await {
+                Ok(Ok(pid)) => json(StatusCode::OK, &SpawnReply { pid }),
+                Ok(Err(e)) => text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e:#}")),
+                Err(e) => text(StatusCode::INTERNAL_SERVER_ERROR, &format!("{e}")),
+            }
+        }
+        (method, path) => match service_api::parse_path(path) {
+            Some(target) if method == Method::GET && target.action == "logs" => {
+                let query = match LogQuery::parse(req.uri().query()) {
+                    Ok(query) => query,
+                    Err(e) => return text(StatusCode::BAD_REQUEST, &format!("{e:#}")),
+                };
+                match service_api::logs(&target, &query) {
+                    Ok(body) => Response::builder()
+                        .status(StatusCode::OK)
+                        .header("content-type", "text/plain; charset=utf-8")
+                        .body(body.boxed())
+                        .unwrap(),
+                    Err(ApiError(status, msg)) => text(status, &msg),
+                }
+            }
+            Some(target) if method == Method::POST && target.action != "logs" => {
+                match service_api::lifecycle(&target, supervisor).await {
+                    Ok(reply) => json(StatusCode::OK, &reply),
+                    Err(ApiError(status, msg)) => text(status, &msg),
+                }
+            }
+            _ => text(StatusCode::NOT_FOUND, "not found"),
+        },
+    }
+}
+
+/// Admit a state-changing request only from a local, non-browser client.
+/// Browsers always send `Origin` on cross-site `POST`s, cannot send
+/// `application/json` without a CORS preflight (which this server never
+/// answers), and keep the attacker's hostname in `Host` after a DNS
+/// rebind.
+fn check_caller(headers: &HeaderMap, port: u16) -> Result<(), (StatusCode, &'static str)> {
+    if headers.contains_key(ORIGIN) {
+        return Err((StatusCode::FORBIDDEN, "cross-origin requests are not accepted"));
     }
+    let content_type = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    let is_json = content_type
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"));
+    if !is_json {
+        return Err((StatusCode::UNSUPPORTED_MEDIA_TYPE, "expected Content-Type: application/json"));
+    }
+    let host = headers.get(HOST).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let local = [format!("127.0.0.1:{port}"), format!("localhost:{port}")];
+    if !local.iter().any(|h| h.eq_ignore_ascii_case(host)) {
+        return Err((StatusCode::FORBIDDEN, "unexpected Host for the control plane"));
+    }
+    Ok(())
 }
 
-fn build_status(started_at: Instant) -> Result<StatusBody> {
+async fn read_json<T: serde::de::DeserializeOwned>(req: Request<Incoming>) -> Result<T> {
+    let body = req
+        .into_body()
+        .collect()
+        .await
+        .context("reading request body")?
+        .to_bytes();
+    serde_json::from_slice(&body).context("decoding request body")
+}
+
+fn build_status(started_at: Instant, proxy: &Proxy) -> Result<StatusBody> {
     let config = load_config()?;
-    let routes = load_routes().unwrap_or_default();
     Ok(StatusBody {
         pid: std::process::id(),
         uptime_sec: started_at.elapsed().as_secs(),
         proxy_port: config.dev.proxy_port,
         control_port: config.dev.control_port,
-        routes_count: routes.entries.len(),
+        https_port: config.dev.https_port,
+        routes_count: proxy.route_count(),
     })
 }
 
-fn json<T: Serialize>(status: StatusCode, body: &T) -> Response<Full<Bytes>> {
+fn json<T: Serialize>(status: StatusCode, body: &T) -> Response<ControlBody> {
     let bytes = serde_json::to_vec(body).unwrap_or_default();
     Response::builder()
         .status(status)
         .header("content-type", "application/json")
-        .body(Full::new(Bytes::from(bytes)))
+        .body(Full::new(Bytes::from(bytes)).boxed())
         .unwrap()
 }
 
-fn text(status: StatusCode, msg: &str) -> Response<Full<Bytes>> {
+fn text(status: StatusCode, msg: &str) -> Response<ControlBody> {
     Response::builder()
         .status(status)
         .header("content-type", "text/plain; charset=utf-8")
-        .body(Full::new(Bytes::from(msg.to_string())))
+        .body(Full::new(Bytes::from(msg.to_string())).boxed())
         .unwrap()
 }
 
@@ -157,6 +312,30 @@ pub fn send_stop() -> Result<()> {
     Ok(())
 }
 
+/// Ask the daemon to spawn and supervise a service. Returns the PID.
+pub fn request_spawn(spec: &SpawnSpec) -> Result<u32> {
+    let config = load_config()?;
+    let port = config.dev.control_port;
+    let (status, body) = blocking_post_json(port, "/services/spawn", &serde_json::to_vec(spec)?)?;
+    if status != StatusCode::OK {
+        return Err(anyhow::anyhow!(
+            "daemon refused to spawn: {}",
+            String::from_utf8_lossy(&body).trim()
+        ));
+    }
+    let reply: SpawnReply = serde_json::from_slice(&body).context("decoding spawn reply")?;
+    Ok(reply.pid)
+}
+
+/// Recent requests recorded by the proxy, oldest first.
+pub fn fetch_requests(filter: &RequestFilter) -> Result<Vec<RequestRecord>> {
+    let config = load_config()?;
+    let path = format!("/requests?{}", filter.to_query());
+    let body = blocking_get_body(config.dev.control_port, &path)
+        .context("daemon is not running (start it with `pm proxy start`)")?;
+    serde_json::from_slice(&body).context("decoding /requests body")
+}
+
 // ── Sync HTTP helpers ──
 //
 // We avoid pulling in a full HTTP client crate for a handful of one-shot
@@ -166,6 +345,9 @@ pub fn send_stop() -> Result<()> {
 use std::io::{Read, Write};
 use std::net::{Shutdown, TcpStream};
 
+/// Time a spawn may take on top of waiting for the state lock.
+const SPAWN_GRACE: Duration = Duration::from_secs(5);
+
 fn blocking_get(port: u16, path: &str) -> Result<StatusCode> {
     let mut stream = match TcpStream::connect_timeout(
         &format!("127.0.0.1:{port}").parse()?,
@@ -205,7 +387,8 @@ fn blocking_post(port: u16, path: &str) -> Result<StatusCode> {
     )?;
     stream.set_read_timeout(Some(Duration::from_millis(500)))?;
     let req = format!(
-        "POST {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        "POST {path} HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nContent-Type: application/json\r\n\
+         Content-Length: 0\r\nConnection: close\r\n\r\n"
     );
     stream.write_all(req.as_bytes())?;
     let mut buf = Vec::with_capacity(256);
@@ -213,7 +396,28 @@ fn blocking_post(port: u16, path: &str) -> Result<StatusCode> {
     Ok(parse_status(&buf).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR))
 }
 
-fn parse_status(buf: &[u8]) -> Option<StatusCode> {
+fn blocking_post_json(port: u16, path: &str, body: &[u8]) -> Result<(StatusCode, Vec<u8>)> {
+    let mut stream = TcpStream::connect_timeout(
+        &format!("127.0.0.1:{port}").parse()?,
+        Duration::from_millis(500),
+    )?;
+    // Spawning waits for the services.json lock; outlast that wait so a
+    // busy daemon answers with its error instead of the client timing out.
+    stream.set_read_timeout(Some(LOCK_TIMEOUT + SPAWN_GRACE))?;
+    let head = format!(
+        "POST {path} HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(body)?;
+    let mut buf = Vec::with_capacity(256);
+    stream.read_to_end(&mut buf)?;
+    let status = parse_status(&buf).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    Ok((status, extract_body(&buf)))
+}
+
+pub(crate) fn parse_status(buf: &[u8]) -> Option<StatusCode> {
     // "HTTP/1.1 200 OK\r\n..."
     let prefix = std::str::from_utf8(buf.get(..15)?).ok()?;
     let mut parts = prefix.split_whitespace();
@@ -251,6 +455,9 @@ pub fn cmd_status() -> Result<()> {
                 );
                 println!("  uptime:       {}s", s.uptime_sec);
                 println!("  proxy:        http://127.0.0.1:{}", s.proxy_port);
+                if let Some(port) = s.https_port {
+                    println!("  https:        https://127.0.0.1:{port}");
+                }
                 println!("  control:      http://127.0.0.1:{}", s.control_port);
                 println!("  routes:       {}", s.routes_count);
                 Ok(())
@@ -309,6 +516,36 @@ mod tests {
         assert_eq!(parse_status(buf), Some(StatusCode::NOT_FOUND));
     }
 
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(k, v)| (hyper::header::HeaderName::from_static(k), v.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn local_json_callers_are_accepted() {
+        let ok = headers(&[("host", "127.0.0.1:7101"), ("content-type", "application/json")]);
+        assert!(check_caller(&ok, 7101).is_ok());
+        let ok = headers(&[("host", "localhost:7101"), ("content-type", "application/json; charset=utf-8")]);
+        assert!(check_caller(&ok, 7101).is_ok());
+    }
+
+    #[test]
+    fn browser_style_requests_are_refused() {
+        let status = |pairs: &[(&'static str, &'static str)]| check_caller(&headers(pairs), 7101).unwrap_err().0;
+        let json = ("content-type", "application/json");
+        let host = ("host", "127.0.0.1:7101");
+        assert_eq!(status(&[host, json, ("origin", "https://evil.example")]), StatusCode::FORBIDDEN);
+        assert_eq!(status(&[host, ("content-type", "text/plain")]), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(status(&[host]), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        // DNS rebinding keeps the attacker's name in Host.
+        assert_eq!(status(&[("host", "evil.example:7101"), json]), StatusCode::FORBIDDEN);
+        assert_eq!(status(&[("host", "127.0.0.1"), json]), StatusCode::FORBIDDEN);
+        assert_eq!(status(&[("host", "127.0.0.1:8080"), json]), StatusCode::FORBIDDEN);
+        assert_eq!(status(&[json]), StatusCode::FORBIDDEN);
+    }
+
     #[test]
     fn extract_body_finds_separator() {
         let buf = b"HTTP/1.1 200 OK\r\nA: 1\r\n\r\nbody-content";
@@ -2,13 +2,19 @@
 //!
 //! The daemon is implemented as a `tokio::main` async runtime that owns:
 //! - the reverse proxy on `dev.proxy_port` (default 7100), and
-//! - the control-plane HTTP server on `dev.control_port` (default 7101).
+//! - the control-plane HTTP server on `dev.control_port` (default 7101),
+//! - optionally, the HTTPS proxy on `dev.https_port`,
+//! - the [`Supervisor`] that spawns and restarts services for `pm run`.
 //!
-//! Both servers run as concurrent tasks; SIGTERM/SIGINT or a control-plane
+//! The servers run as concurrent tasks; SIGTERM/SIGINT or a control-plane
 //! `/stop` cleanly cancels them.
 
 use crate::commands::proxy::control;
+use crate::commands::proxy::events::Events;
+use crate::commands::proxy::inspect::Inspector;
 use crate::commands::proxy::reverse;
+use crate::commands::proxy::supervisor::Supervisor;
+use crate::commands::proxy::tls;
 use crate::config::{config_dir, daemon_pid_path, load_config, logs_dir};
 use anyhow::{Context, Result};
 use std::fs;
@@ -49,6 +55,12 @@ fn run_inner() -> Result<()> {
     let config = load_config()?;
     let proxy_port = config.dev.proxy_port;
     let control_port = config.dev.control_port;
+    // Load the CA up front so a broken CA fails the daemon start loudly
+    // instead of every handshake failing later.
+    let https = match config.dev.https_port {
+        Some(port) => Some((port, tls::LocalCa::load_or_create()?)),
+        None => None,
+    };
 
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -56,12 +68,40 @@ fn run_inner() -> Result<()> {
 
     runtime.block_on(async move {
         let shutdown = Arc::new(Notify::new());
-        let proxy_task = reverse::serve(proxy_port, shutdown.clone());
-        let control_task = control::serve(control_port, shutdown.clone());
+        let events = Events::default();
+        let inspector = Inspector::new();
+        let proxy = reverse::Proxy::new(events.clone(), inspector);
+        if let Err(e) = proxy.reload() {
+            eprintln!("pm-daemon: loading routes.json failed (starting with no routes): {e:#}");
+        }
+        // Kept alive for the daemon's lifetime; dropping it stops the watch.
+        let _routes_watcher = match reverse::watch_routes(proxy.clone()) {
+            Ok(watcher) => Some(watcher),
+            Err(e) => {
+                eprintln!("pm-daemon: cannot watch routes.json ({e:#}); polling it instead");
+                tokio::spawn(reverse::poll_routes(proxy.clone()));
+                None
+            }
+        };
+        let proxy_task = reverse::serve(proxy_port, proxy.clone(), shutdown.clone());
+        let supervisor = Arc::new(Supervisor::new(events.clone()));
+        let control_task =
+            control::serve(control_port, shutdown.clone(), supervisor, events, proxy.clone());
+        let tls_shutdown = shutdown.clone();
+        let tls_task = async move {
+            match https {
+                Some((port, ca)) => {
+                    let tls_config = tls::server_config(ca, proxy.clone())?;
+                    reverse::serve_tls(port, tls_config, proxy, tls_shutdown).await
+                }
+                None => std::future::pending().await,
+            }
+        };
 
         tokio::select! {
             r = proxy_task   => r?,
             r = control_task => r?,
+            r = tls_task     => r?,
             _ = wait_for_signal(shutdown.clone()) => {
                 eprintln!("pm-daemon: shutdown signal received");
             }
@@ -0,0 +1,249 @@
+//! Daemon event stream: `GET /events` and `pm proxy events`.
+//!
+//! The proxy and the supervisor publish [`Event`]s on a broadcast channel;
+//! every `/events` client gets its own subscription and receives them as
+//! newline-delimited JSON, one object per line with an RFC 3339 `at` and
+//! a `type`:
+//!
+//! | type               | when                                                 |
+//! |--------------------|------------------------------------------------------|
+//! | `routes_reloaded`  | the proxy picked up a changed `routes.json`          |
+//! | `service_spawned`  | `pm run` handed a service to the supervisor          |
+//! | `service_exited`   | a supervised process exited                          |
+//! | `service_restarted`| the supervisor started it again (crash or file change) |
+//! | `upstream_error`   | a request got 502 because the upstream did not answer |
+//! | `unknown_host`     | a request got 404 because no route matches its host  |
+//! | `lagged`           | this client fell behind and `missed` events were dropped |
+//!
+//! Nothing is buffered for clients that are not connected.
+
+use crate::commands::proxy::control::ChannelBody;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Local, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+
+/// Events a slow client may fall behind by before it misses some.
+const CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    RoutesReloaded {
+        routes: usize,
+    },
+    ServiceSpawned {
+        service: String,
+        pid: u32,
+        port: u16,
+    },
+    ServiceExited {
+        service: String,
+        pid: u32,
+        /// e.g. `exit code 1` or `signal 9`.
+        exit: String,
+        restarting: bool,
+    },
+    ServiceRestarted {
+        service: String,
+        pid: u32,
+        /// `crash` or `file change`.
+        reason: String,
+    },
+    UpstreamError {
+        host: String,
+        port: u16,
+        error: String,
+    },
+    UnknownHost {
+        host: String,
+        path: String,
+    },
+    Lagged {
+        missed: u64,
+    },
+}
+
+/// Publishing side, cloned into the proxy and the supervisor.
+#[derive(Clone)]
+pub struct Events {
+    tx: broadcast::Sender<Event>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    pub fn emit(&self, kind: EventKind) {
+        // No subscribers is the normal case.
+        let _ = self.tx.send(Event { at: Utc::now(), kind });
+    }
+
+    /// A `/events` response body: every event from now on, as NDJSON,
+    /// until the client disconnects.
+    pub fn stream(&self) -> ChannelBody {
+        let mut rx = self.tx.subscribe();
+        let (tx, body) = mpsc::channel(16);
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    event = rx.recv() => event,
+                    _ = tx.closed() => return,
+                };
+                let event = match event {
+                    Ok(event) => event,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => Event {
+                        at: Utc::now(),
+                        kind: EventKind::Lagged { missed },
+                    },
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                let mut line = serde_json::to_vec(&event).unwrap_or_default();
+                line.push(b'\n');
+                if tx.send(Bytes::from(line)).await.is_err() {
+                    return;
+                }
+            }
+        });
+        ChannelBody(body)
+    }
+}
+
+// ── `pm proxy events` ──
+
+pub fn cmd_events(port: u16, json: bool) -> Result<()> {
+    let mut stream = TcpStream::connect_timeout(
+        &format!("127.0.0.1:{port}").parse()?,
+        Duration::from_millis(500),
+    )
+    .context("daemon is not running (start it with `pm proxy start`)")?;
+    // HTTP/1.0 gets a close-delimited body: plain lines, no chunking.
+    stream.write_all(b"GET /events HTTP/1.0\r\nHost: localhost\r\n\r\n")?;
+    let mut reader = BufReader::new(stream);
+
+    let mut status = String::new();
+    reader.read_line(&mut status)?;
+    if !status.contains(" 200 ") {
+        anyhow::bail!("daemon refused /events: {}", status.trim());
+    }
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim() != "" {
+        header.clear();
+    }
+
+    eprintln!("{} listening for daemon events (Ctrl+C to stop)", "i".cyan());
+    let stdout = std::io::stdout();
+    for line in reader.lines() {
+        let line = line?;
+        let mut out = stdout.lock();
+        if json {
+            writeln!(out, "{line}")?;
+        } else {
+            match serde_json::from_str::<Event>(&line) {
+                Ok(event) => writeln!(out, "{}", format_event(&event))?,
+                Err(_) => writeln!(out, "{line}")?,
+            }
+        }
+        out.flush()?;
+    }
+    eprintln!("{} daemon closed the event stream", "—".dimmed());
+    Ok(())
+}
+
+fn format_event(event: &Event) -> String {
+    let at = event.at.with_timezone(&Local).format("%H:%M:%S%.3f");
+    let (label, detail) = match &event.kind {
+        EventKind::RoutesReloaded { routes } => {
+            ("routes".cyan(), format!("reloaded ({routes} routes)"))
+        }
+        EventKind::ServiceSpawned { service, pid, port } => {
+            ("spawned".green(), format!("{service} (pid {pid}, port {port})"))
+        }
+        EventKind::ServiceExited { service, pid, exit, restarting } => (
+            "exited".red(),
+            format!(
+                "{service} (pid {pid}) {exit}{}",
+                if *restarting { ", restarting" } else { "" }
+            ),
+        ),
+        EventKind::ServiceRestarted { service, pid, reason } => {
+            ("restarted".yellow(), format!("{service} (pid {pid}) after {reason}"))
+        }
+        EventKind::UpstreamError { host, port, error } => {
+            ("502".red(), format!("{host} → port {port}: {error}"))
+        }
+        EventKind::UnknownHost { host, path } => ("404".yellow(), format!("{host}{path}")),
+        EventKind::Lagged { missed } => {
+            ("lagged".dimmed(), format!("{missed} events dropped"))
+        }
+    };
+    format!("{}  {:<9}  {}", at.to_string().dimmed(), label, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+
+    #[test]
+    fn events_serialize_flat_with_a_type_tag() {
+        let event = Event {
+            at: "2026-10-18T09:12:03Z".parse().unwrap(),
+            kind: EventKind::UnknownHost {
+                host: "nope.localhost".into(),
+                path: "/".into(),
+            },
+        };
+        let json = serde_json::to_string(&event).unwrap();
+        assert_eq!(
+            json,
+            r#"{"at":"2026-10-18T09:12:03Z","type":"unknown_host","host":"nope.localhost","path":"/"}"#
+        );
+        assert_eq!(serde_json::from_str::<Event>(&json).unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn stream_delivers_events_as_ndjson() {
+        let events = Events::default();
+        let mut body = events.stream();
+        events.emit(EventKind::RoutesReloaded { routes: 3 });
+        events.emit(EventKind::ServiceSpawned {
+            service: "default/api/back".into(),
+            pid: 42,
+            port: 3001,
+        });
+
+        let mut text = String::new();
+        while text.matches('\n').count() < 2 {
+            let frame = tokio::time::timeout(Duration::from_secs(2), body.frame())
+                .await
+                .expect("no event")
+                .unwrap()
+                .unwrap();
+            text.push_str(std::str::from_utf8(&frame.into_data().unwrap()).unwrap());
+        }
+        let kinds: Vec<EventKind> = text
+            .lines()
+            .map(|l| serde_json::from_str::<Event>(l).unwrap().kind)
+            .collect();
+        assert_eq!(kinds[0], EventKind::RoutesReloaded { routes: 3 });
+        assert!(matches!(kinds[1], EventKind::ServiceSpawned { pid: 42, .. }));
+    }
+}
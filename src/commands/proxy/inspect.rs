@@ -0,0 +1,631 @@
+//! Request inspector: recent proxied requests and per-route access logs.
+//!
+//! Every request through the proxy is recorded — method, host, URI,
+//! status, time to response headers, total time, response size, headers
+//! and the first [`BODY_LIMIT`] bytes of both bodies — in an in-memory
+//! ring of the last [`CAPACITY`] requests. The control plane serves it on
+//! `GET /requests` and `pm proxy requests` prints it. Nothing is written
+//! to disk unless the route has `access_log`, in which case a one-line
+//! summary is appended to the service's log. Those lines go through a
+//! channel to a single writer task, so file I/O stays off the request
+//! path; if the writer falls [`ACCESS_LOG_BACKLOG`] lines behind, new
+//! lines are dropped rather than slowing down the proxy.
+//!
+//! Bodies are captured by wrapping them ([`Tap`]) as they stream through,
+//! so recording never delays or buffers a response. A request is recorded
+//! once its response body has finished (or the client went away).
+
+use crate::commands::proxy::control;
+use crate::config::service_log_path;
+use crate::log_line;
+use crate::routes::RouteEntry;
+use anyhow::{anyhow, Result};
+use bytes::{Buf, Bytes};
+use chrono::{DateTime, Local, Utc};
+use colored::Colorize;
+use hyper::body::{Body, Frame};
+use hyper::{HeaderMap, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+/// Requests kept in memory.
+pub const CAPACITY: usize = 200;
+
+/// Bytes of each request and response body kept per request.
+pub const BODY_LIMIT: usize = 4096;
+
+/// Access-log lines queued for the writer before new ones are dropped.
+pub const ACCESS_LOG_BACKLOG: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestRecord {
+    pub id: u64,
+    pub at: DateTime<Utc>,
+    pub method: String,
+    pub host: String,
+    /// Path and query as the client sent them.
+    pub uri: String,
+    pub status: u16,
+    /// Time until the response headers were ready.
+    pub latency_ms: u64,
+    /// Time until the response body was complete.
+    pub duration_ms: u64,
+    /// Response body size.
+    pub bytes: u64,
+    /// `<workspace>/<project>/<service>` of the matched route.
+    #[serde(default)]
+    pub service: Option<String>,
+    #[serde(default)]
+    pub upstream_port: Option<u16>,
+    pub request_headers: Vec<(String, String)>,
+    pub response_headers: Vec<(String, String)>,
+    pub request_body: CapturedBody,
+    pub response_body: CapturedBody,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CapturedBody {
+    /// The first [`BODY_LIMIT`] bytes, decoded lossily as UTF-8.
+    pub text: String,
+    /// The body was longer than what was kept.
+    pub truncated: bool,
+}
+
+/// Which records `GET /requests` returns.
+#[derive(Debug, Default, PartialEq)]
+pub struct RequestFilter {
+    /// Substring of the request host.
+    pub host: Option<String>,
+    /// Exact status (`502`) or class (`5xx`).
+    pub status: Option<String>,
+    /// Only the most recent N matches.
+    pub limit: Option<usize>,
+}
+
+impl RequestFilter {
+    pub fn parse(query: Option<&str>) -> Result<Self> {
+        let mut out = RequestFilter::default();
+        for pair in query.unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode_query_value(value)?;
+            let value = value.as_str();
+            match key {
+                "host" => out.host = Some(value.to_string()),
+                "status" => {
+                    check_status_filter(value)?;
+                    out.status = Some(value.to_string());
+                }
+                "limit" => {
+                    out.limit = Some(value.parse().map_err(|_| anyhow!("invalid limit '{value}'"))?)
+                }
+                _ => {}
+            }
+        }
+        Ok(out)
+    }
+
+    /// The query string [`RequestFilter::parse`] reads back.
+    pub fn to_query(&self) -> String {
+        let mut query = Vec::new();
+        if let Some(host) = &self.host {
+            query.push(format!("host={}", encode_query_value(host)));
+        }
+        if let Some(status) = &self.status {
+            query.push(format!("status={}", encode_query_value(status)));
+        }
+        if let Some(limit) = self.limit {
+            query.push(format!("limit={limit}"));
+        }
+        query.join("&")
+    }
+
+    fn matches(&self, record: &RequestRecord) -> bool {
+        let host_ok = self.host.as_deref().is_none_or(|h| record.host.contains(h));
+        let status_ok = self.status.as_deref().is_none_or(|s| status_matches(s, record.status));
+        host_ok && status_ok
+    }
+}
+
+/// Percent-encode everything but unreserved characters (RFC 3986).
+fn encode_query_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    out
+}
+
+/// Undo percent-encoding (and `+` for a space, as forms send it).
+fn decode_query_value(value: &str) -> Result<String> {
+    percent_decode(value, true)
+}
+
+/// Undo percent-encoding in a URL path segment, where `+` is literal.
+pub fn decode_path_segment(segment: &str) -> Result<String> {
+    percent_decode(segment, false)
+}
+
+fn percent_decode(value: &str, plus_is_space: bool) -> Result<String> {
+    let invalid = || anyhow!("invalid percent-encoding in '{value}'");
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.bytes();
+    while let Some(byte) = rest.next() {
+        bytes.push(match byte {
+            b'+' if plus_is_space => b' ',
+            b'%' => {
+                let hex = [rest.next().ok_or_else(invalid)?, rest.next().ok_or_else(invalid)?];
+                let hex = std::str::from_utf8(&hex).map_err(|_| invalid())?;
+                u8::from_str_radix(hex, 16).map_err(|_| invalid())?
+            }
+            other => other,
+        });
+    }
+    String::from_utf8(bytes).map_err(|_| invalid())
+}
+
+/// Accept `404`, `5xx`, `4XX`.
+pub fn check_status_filter(value: &str) -> Result<()> {
+    let valid = value.len() == 3
+        && value.chars().next().is_some_and(|c| ('1'..='5').contains(&c))
+        && (value[1..].eq_ignore_ascii_case("xx") || value[1..].chars().all(|c| c.is_ascii_digit()));
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!("invalid status filter '{value}' (expected e.g. 404 or 5xx)"))
+    }
+}
+
+fn status_matches(filter: &str, status: u16) -> bool {
+    let code = status.to_string();
+    if filter[1..].eq_ignore_ascii_case("xx") {
+        code.starts_with(&filter[..1])
+    } else {
+        code == filter
+    }
+}
+
+pub struct Inspector {
+    ring: Mutex<VecDeque<RequestRecord>>,
+    next_id: AtomicU64,
+    /// Access-log lines for [`write_access_logs`], with the log to append
+    /// them to.
+    access_log: mpsc::Sender<(PathBuf, String)>,
+}
+
+impl Inspector {
+    /// An empty inspector and its access-log writer task; must be called
+    /// inside the runtime.
+    pub fn new() -> Arc<Self> {
+        let (access_log, rx) = mpsc::channel(ACCESS_LOG_BACKLOG);
+        tokio::spawn(write_access_logs(rx));
+        Arc::new(Self {
+            ring: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(0),
+            access_log,
+        })
+    }
+
+    /// Matching records, oldest first.
+    pub fn recent(&self, filter: &RequestFilter) -> Vec<RequestRecord> {
+        let ring = self.ring.lock().unwrap();
+        let mut out: Vec<RequestRecord> =
+            ring.iter().filter(|r| filter.matches(r)).cloned().collect();
+        if let Some(n) = filter.limit {
+            out.drain(..out.len().saturating_sub(n));
+        }
+        out
+    }
+
+    fn push(&self, record: RequestRecord) {
+        let mut ring = self.ring.lock().unwrap();
+        if ring.len() == CAPACITY {
+            ring.pop_front();
+        }
+        ring.push_back(record);
+    }
+
+    /// Start recording `req`: returns the request with its body tapped
+    /// and the handle that [`Inspector::finish`] completes.
+    pub fn begin<B>(self: &Arc<Self>, req: Request<B>, host: &str) -> (Request<Tap<B>>, Pending) {
+        let capture = Arc::new(Mutex::new(Capture::default()));
+        let pending = Pending {
+            inspector: self.clone(),
+            started: Instant::now(),
+            record: RequestRecord {
+                id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+                at: Utc::now(),
+                method: req.method().to_string(),
+                host: host.to_string(),
+                uri: req.uri().path_and_query().map_or("/", |p| p.as_str()).to_string(),
+                status: 0,
+                latency_ms: 0,
+                duration_ms: 0,
+                bytes: 0,
+                service: None,
+                upstream_port: None,
+                request_headers: header_pairs(req.headers()),
+                response_headers: Vec::new(),
+                request_body: CapturedBody::default(),
+                response_body: CapturedBody::default(),
+            },
+            request_body: capture.clone(),
+            access_log: false,
+        };
+        let req = req.map(|body| Tap { inner: body, capture, done: None });
+        (req, pending)
+    }
+
+    /// Record the response to a [`Pending`] request. The record is stored
+    /// when the returned response's body has been sent.
+    pub fn finish<B>(mut pending: Pending, resp: Response<B>) -> Response<Tap<B>> {
+        pending.record.latency_ms = pending.started.elapsed().as_millis() as u64;
+        pending.record.status = resp.status().as_u16();
+        pending.record.response_headers = header_pairs(resp.headers());
+        resp.map(|body| Tap {
+            inner: body,
+            capture: Arc::new(Mutex::new(Capture::default())),
+            done: Some(pending),
+        })
+    }
+}
+
+/// A request whose response is still on its way.
+pub struct Pending {
+    inspector: Arc<Inspector>,
+    started: Instant,
+    record: RequestRecord,
+    request_body: Arc<Mutex<Capture>>,
+    access_log: bool,
+}
+
+impl Pending {
+    /// Note the route that served the request.
+    pub fn route(&mut self, route: &RouteEntry) {
+        self.record.service = Some(format!("{}/{}", route.project_key, route.service_key));
+        self.record.upstream_port = Some(route.upstream_port);
+        self.access_log = route.access_log;
+    }
+
+    fn complete(mut self, response_body: &Capture) {
+        self.record.duration_ms = self.started.elapsed().as_millis() as u64;
+        self.record.bytes = response_body.len;
+        self.record.response_body = response_body.to_captured();
+        self.record.request_body = self.request_body.lock().unwrap().to_captured();
+        if self.access_log
+            && let Some((workspace, rest)) = self.record.service.as_deref().and_then(|s| s.split_once('/'))
+            && let Some((project, service)) = rest.split_once('/')
+        {
+            let line = (service_log_path(workspace, project, service), access_line(&self.record));
+            let _ = self.inspector.access_log.try_send(line);
+        }
+        self.inspector.push(self.record);
+    }
+}
+
+/// Append access-log lines in the order they were sent, a batch at a time
+/// on the blocking pool.
+async fn write_access_logs(mut rx: mpsc::Receiver<(PathBuf, String)>) {
+    let mut batch = Vec::new();
+    while rx.recv_many(&mut batch, 64).await > 0 {
+        let lines = std::mem::take(&mut batch);
+        let written = tokio::task::spawn_blocking(move || {
+            for (path, line) in &lines {
+                log_line::append_note(path, line);
+            }
+        })
+        .await;
+        if written.is_err() {
+            return;
+        }
+    }
+}
+
+/// `GET api.localhost/users?page=2 → 200 in 12ms, 1.2 KB`
+pub fn access_line(r: &RequestRecord) -> String {
+    format!(
+        "{} {}{} → {} in {}ms, {}",
+        r.method,
+        r.host,
+        r.uri,
+        r.status,
+        r.latency_ms,
+        format_size(r.bytes)
+    )
+}
+
+pub fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{bytes} B")
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (name.to_string(), String::from_utf8_lossy(value.as_bytes()).into_owned())
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Capture {
+    data: Vec<u8>,
+    len: u64,
+}
+
+impl Capture {
+    fn add(&mut self, chunk: &[u8]) {
+        self.len += chunk.len() as u64;
+        let room = BODY_LIMIT.saturating_sub(self.data.len());
+        self.data.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+
+    fn to_captured(&self) -> CapturedBody {
+        CapturedBody {
+            text: String::from_utf8_lossy(&self.data).into_owned(),
+            truncated: self.len > self.data.len() as u64,
+        }
+    }
+}
+
+/// A body passed through unchanged while its first bytes are copied
+/// aside. A response tap completes its [`Pending`] record when the body
+/// ends or is dropped.
+pub struct Tap<B> {
+    inner: B,
+    capture: Arc<Mutex<Capture>>,
+    done: Option<Pending>,
+}
+
+impl<B> Tap<B> {
+    fn complete(&mut self) {
+        if let Some(pending) = self.done.take() {
+            pending.complete(&self.capture.lock().unwrap());
+        }
+    }
+}
+
+impl<B> Body for Tap<B>
+where
+    B: Body<Data = Bytes> + Unpin,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        match &poll {
+            Poll::Ready(Some(Ok(frame))) => {
+                if let Some(data) = frame.data_ref() {
+                    self.capture.lock().unwrap().add(data.chunk());
+                }
+            }
+            Poll::Ready(None) | Poll::Ready(Some(Err(_))) => self.complete(),
+            Poll::Pending => {}
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for Tap<B> {
+    fn drop(&mut self) {
+        self.complete();
+    }
+}
+
+// ── `pm proxy requests` ──
+
+pub fn cmd_requests(
+    host: Option<String>,
+    status: Option<String>,
+    limit: usize,
+    verbose: bool,
+    json: bool,
+) -> Result<()> {
+    if let Some(status) = &status {
+        check_status_filter(status)?;
+    }
+    let records = control::fetch_requests(&RequestFilter { host, status, limit: Some(limit) })?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+        return Ok(());
+    }
+    if records.is_empty() {
+        println!("{} no matching requests", "—".dimmed());
+        return Ok(());
+    }
+    for record in &records {
+        println!("{}", format_record(record));
+        if verbose {
+            print_details(record);
+        }
+    }
+    Ok(())
+}
+
+fn format_record(r: &RequestRecord) -> String {
+    let at = r.at.with_timezone(&Local).format("%H:%M:%S");
+    let status = match r.status {
+        500.. => r.status.to_string().red(),
+        400.. => r.status.to_string().yellow(),
+        _ => r.status.to_string().green(),
+    };
+    format!(
+        "{}  {}  {:<6} {:<40}  {:>5}ms  {:>8}",
+        at.to_string().dimmed(),
+        status,
+        r.method,
+        format!("{}{}", r.host, r.uri),
+        r.latency_ms,
+        format_size(r.bytes)
+    )
+}
+
+fn print_details(r: &RequestRecord) {
+    let route = match (&r.service, r.upstream_port) {
+        (Some(service), Some(port)) => format!("{service} (port {port})"),
+        _ => "no route".to_string(),
+    };
+    println!("    {} {route}, done in {}ms", "→".dimmed(), r.duration_ms);
+    for (label, headers, body) in [
+        ("request", &r.request_headers, &r.request_body),
+        ("response", &r.response_headers, &r.response_body),
+    ] {
+        println!("    {}", label.bold());
+        for (name, value) in headers {
+            println!("      {}: {value}", name.dimmed());
+        }
+        if !body.text.is_empty() {
+            for line in body.text.lines() {
+                println!("      {line}");
+            }
+            if body.truncated {
+                println!("      {}", format!("… truncated at {BODY_LIMIT} bytes").dimmed());
+            }
+        }
+    }
+    println!();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, Full};
+
+    async fn roundtrip(inspector: &Arc<Inspector>, host: &str, status: u16, body: &'static str) {
+        let req = Request::builder()
+            .method("POST")
+            .uri("/api/users?page=2")
+            .header("content-type", "application/json")
+            .body(Full::new(Bytes::from_static(b"{\"name\":\"a\"}")))
+            .unwrap();
+        let (req, pending) = inspector.begin(req, host);
+        let sent = req.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&sent[..], b"{\"name\":\"a\"}");
+
+        let resp = Response::builder()
+            .status(status)
+            .body(Full::new(Bytes::from_static(body.as_bytes())))
+            .unwrap();
+        let resp = Inspector::finish(pending, resp);
+        let received = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&received[..], body.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn records_requests_with_bodies_and_filters_them() {
+        let inspector = Inspector::new();
+        roundtrip(&inspector, "api.localhost", 200, "ok").await;
+        roundtrip(&inspector, "web.localhost", 502, "bad gateway").await;
+
+        let all = inspector.recent(&RequestFilter::default());
+        assert_eq!(all.len(), 2);
+        let first = &all[0];
+        assert_eq!((first.id, first.method.as_str()), (1, "POST"));
+        assert_eq!(first.uri, "/api/users?page=2");
+        assert_eq!(first.request_body.text, "{\"name\":\"a\"}");
+        assert_eq!(first.response_body.text, "ok");
+        assert_eq!(first.bytes, 2);
+        assert!(first.request_headers.contains(&("content-type".into(), "application/json".into())));
+
+        let filter = RequestFilter::parse(Some("status=5xx")).unwrap();
+        let errors = inspector.recent(&filter);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].host, "web.localhost");
+        let filter = RequestFilter::parse(Some("host=api&limit=5")).unwrap();
+        assert_eq!(inspector.recent(&filter)[0].status, 200);
+        assert!(RequestFilter::parse(Some("status=6xx")).is_err());
+    }
+
+    #[tokio::test]
+    async fn ring_keeps_the_latest_requests_and_truncates_bodies() {
+        let inspector = Inspector::new();
+        for _ in 0..CAPACITY + 5 {
+            roundtrip(&inspector, "api.localhost", 200, "ok").await;
+        }
+        let all = inspector.recent(&RequestFilter::default());
+        assert_eq!(all.len(), CAPACITY);
+        assert_eq!(all[0].id, 6);
+
+        let mut capture = Capture::default();
+        capture.add(&vec![b'x'; BODY_LIMIT + 10]);
+        let captured = capture.to_captured();
+        assert_eq!(captured.text.len(), BODY_LIMIT);
+        assert!(captured.truncated);
+        assert_eq!(capture.len, BODY_LIMIT as u64 + 10);
+    }
+
+    #[tokio::test]
+    async fn access_line_summarizes_a_request() {
+        let inspector = Inspector::new();
+        roundtrip(&inspector, "api.localhost", 404, "missing").await;
+        let record = &inspector.recent(&RequestFilter::default())[0];
+        assert!(access_line(record).starts_with("POST api.localhost/api/users?page=2 → 404 in "));
+        assert!(access_line(record).ends_with("ms, 7 B"));
+        assert_eq!(format_size(1536), "1.5 KB");
+    }
+
+    #[test]
+    fn filter_queries_are_percent_encoded_both_ways() {
+        let filter = RequestFilter {
+            host: Some("a b&status=2xx.localhost".into()),
+            status: Some("5xx".into()),
+            limit: Some(3),
+        };
+        let query = filter.to_query();
+        assert_eq!(query, "host=a%20b%26status%3D2xx.localhost&status=5xx&limit=3");
+        assert_eq!(RequestFilter::parse(Some(&query)).unwrap(), filter);
+        let filter = RequestFilter::parse(Some("host=caf%C3%A9+bar")).unwrap();
+        assert_eq!(filter.host.as_deref(), Some("café bar"));
+        assert!(RequestFilter::parse(Some("host=%zz")).is_err());
+        assert!(RequestFilter::parse(Some("host=%4")).is_err());
+    }
+
+    #[tokio::test]
+    async fn access_log_writer_appends_lines_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        let (tx, rx) = mpsc::channel(ACCESS_LOG_BACKLOG);
+        let writer = tokio::spawn(write_access_logs(rx));
+        for n in 0..100 {
+            tx.send((path.clone(), format!("GET /{n}"))).await.unwrap();
+        }
+        drop(tx);
+        writer.await.unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<String> = log
+            .lines()
+            .map(|l| log_line::parse(l).unwrap().text.to_string())
+            .collect();
+        let expected: Vec<String> = (0..100).map(|n| format!("GET /{n}")).collect();
+        assert_eq!(lines, expected);
+    }
+}
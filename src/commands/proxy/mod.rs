@@ -7,7 +7,12 @@
 //! - **Reverse proxy on `127.0.0.1:7100`** routes by `Host` header to the
 //!   per-service upstream port, using `routes.json` as the registry.
 //! - **Control plane on `127.0.0.1:7101`** offers `/health`, `/status`,
-//!   `/reload`, `/stop` for explicit management.
+//!   `/reload`, `/stop` for explicit management, `/events` and `/requests`
+//!   to watch the daemon at work, `/services/spawn`, through which `pm run` hands
+//!   services to the daemon's supervisor, and per-service
+//!   start/stop/restart/logs for editors and other clients.
+//! - **Optional HTTPS on `dev.https_port`** terminates TLS for the same
+//!   routes using leaves minted by a local CA (see [`tls`]).
 //!
 //! [`design.md`]: ../../../openspec/changes/local-dev-orchestrator/design.md
 
@@ -16,7 +21,21 @@ pub mod control;
 #[cfg(unix)]
 pub mod daemon;
 #[cfg(unix)]
+pub mod events;
+#[cfg(unix)]
+pub mod inspect;
+#[cfg(unix)]
+mod pages;
+#[cfg(unix)]
 mod reverse;
+#[cfg(unix)]
+pub mod service_api;
+#[cfg(unix)]
+pub mod supervisor;
+#[cfg(unix)]
+pub mod tls;
+#[cfg(unix)]
+pub mod watch;
 
 use crate::cli::ProxyCommand;
 use anyhow::Result;
@@ -33,6 +52,14 @@ pub fn run(cmd: ProxyCommand) -> Result<()> {
             }
         }
         ProxyCommand::Stop => control::cmd_stop(),
+        ProxyCommand::Events { json } => events::cmd_events(
+            crate::config::load_config()?.dev.control_port,
+            json,
+        ),
+        ProxyCommand::Requests { host, status, limit, verbose, json } => {
+            inspect::cmd_requests(host, status, limit, verbose, json)
+        }
+        ProxyCommand::Trust { export } => tls::cmd_trust(export),
     }
 }
 
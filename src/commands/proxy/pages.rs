@@ -0,0 +1,386 @@
+//! Pages the proxy answers with itself: the route index and error pages.
+//!
+//! `http://localhost:7100/` (also `127.0.0.1` and `pm.localhost`, unless a
+//! service claims those names) lists every route, grouped by project,
+//! with whether its service is running. 404s for unknown hosts suggest
+//! similar hostnames, and 502s show the last lines of the service's log.
+//!
+//! Browsers get HTML; other clients (`curl`, scripts) get the same
+//! information as plain text, decided by the request's `Accept` header.
+
+use crate::commands::logs::{self, LineWriter};
+use crate::config::service_log_path;
+use crate::log_line;
+use crate::routes::RouteEntry;
+use crate::services::{self, ServiceStatus, ServicesData};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+/// Hostnames that show the index when no route claims them.
+const INDEX_HOSTS: [&str; 4] = ["localhost", "127.0.0.1", "[::1]", "pm.localhost"];
+
+/// Log lines shown on a 502 page.
+const LOG_TAIL_LINES: usize = 20;
+
+/// At most this many "did you mean" suggestions.
+const MAX_SUGGESTIONS: usize = 3;
+
+pub fn is_index_host(host: &str) -> bool {
+    INDEX_HOSTS.iter().any(|h| h.eq_ignore_ascii_case(host))
+}
+
+/// Whether the client prefers HTML (a browser) over plain text.
+pub fn wants_html(headers: &hyper::HeaderMap) -> bool {
+    headers
+        .get(hyper::header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// How a page links to a hostname: scheme-relative and on the port the
+/// request came in on, so links work over both HTTP and HTTPS.
+pub struct LinkBase {
+    /// `:7100`, or empty when the request had no port.
+    pub port_suffix: String,
+}
+
+impl LinkBase {
+    pub fn from_host_header(host_header: &str) -> Self {
+        let port_suffix = match host_header.rsplit_once(':') {
+            Some((_, port)) if port.chars().all(|c| c.is_ascii_digit()) => format!(":{port}"),
+            _ => String::new(),
+        };
+        Self { port_suffix }
+    }
+
+    fn url(&self, hostname: &str, path: &str) -> String {
+        format!("//{hostname}{}{path}", self.port_suffix)
+    }
+}
+
+/// State of a route's service as shown on the index.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Liveness {
+    Running,
+    Restarting,
+    Exited,
+    /// No `services.json` entry: the route is left over.
+    Stopped,
+}
+
+impl Liveness {
+    fn of(route: &RouteEntry, services: &ServicesData) -> Self {
+        let state = services
+            .projects
+            .get(&route.project_key)
+            .and_then(|s| s.get(&route.service_key));
+        match state {
+            None => Liveness::Stopped,
+            Some(s) if s.status == ServiceStatus::Restarting => Liveness::Restarting,
+            Some(s) if s.status == ServiceStatus::Running && services::pid_alive(s.pid) => {
+                Liveness::Running
+            }
+            Some(_) => Liveness::Exited,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Liveness::Running => "running",
+            Liveness::Restarting => "restarting",
+            Liveness::Exited => "exited",
+            Liveness::Stopped => "not running",
+        }
+    }
+}
+
+/// Routes grouped by project, each group sorted by service and hostname.
+fn group(routes: &[RouteEntry]) -> BTreeMap<&str, Vec<&RouteEntry>> {
+    let mut groups: BTreeMap<&str, Vec<&RouteEntry>> = BTreeMap::new();
+    for route in routes {
+        groups.entry(route.project_key.as_str()).or_default().push(route);
+    }
+    for routes in groups.values_mut() {
+        // `*.web.localhost` right after `web.localhost`.
+        routes.sort_by_key(|r| {
+            let wildcard = r.hostname.strip_prefix("*.");
+            (&r.service_key, wildcard.unwrap_or(&r.hostname), wildcard.is_some(), &r.path_prefix)
+        });
+    }
+    groups
+}
+
+fn route_path(route: &RouteEntry) -> &str {
+    route.path_prefix.as_deref().unwrap_or("/")
+}
+
+// ── Index ──
+
+pub fn index_html(routes: &[RouteEntry], services: &ServicesData, links: &LinkBase) -> String {
+    let mut body = String::new();
+    if routes.is_empty() {
+        body.push_str("<p class=\"empty\">No routes yet. Start services with <code>pm run</code>.</p>");
+    }
+    for (project, routes) in group(routes) {
+        let _ = write!(body, "<h2>{}</h2><table>", escape(project));
+        for route in routes {
+            let liveness = Liveness::of(route, services);
+            let path = route_path(route);
+            let host = if route.hostname.starts_with("*.") {
+                format!("{}{path}", escape(&route.hostname))
+            } else {
+                let url = links.url(&route.hostname, path);
+                format!("<a href=\"{}\">{}{}</a>", escape(&url), escape(&route.hostname), escape(path))
+            };
+            let _ = write!(
+                body,
+                "<tr><td>{}</td><td>{host}</td><td class=\"port\">:{}</td>\
+                 <td><span class=\"dot {}\"></span>{}</td></tr>",
+                escape(&route.service_key),
+                route.upstream_port,
+                liveness.label().replace(' ', "-"),
+                liveness.label(),
+            );
+        }
+        body.push_str("</table>");
+    }
+    page("pm routes", &body)
+}
+
+pub fn index_text(routes: &[RouteEntry], services: &ServicesData) -> String {
+    let mut out = String::new();
+    if routes.is_empty() {
+        out.push_str("No routes yet. Start services with `pm run`.\n");
+    }
+    for (project, routes) in group(routes) {
+        let _ = writeln!(out, "{project}");
+        for route in routes {
+            let _ = writeln!(
+                out,
+                "  {:<12} {:<40} :{:<6} {}",
+                route.service_key,
+                format!("{}{}", route.hostname, route_path(route)),
+                route.upstream_port,
+                Liveness::of(route, services).label()
+            );
+        }
+    }
+    out
+}
+
+// ── 404 ──
+
+/// Known hostnames close to `host` (by edit distance), closest first.
+pub fn suggestions<'a>(host: &str, known: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let host = host.to_ascii_lowercase();
+    let limit = (host.len() / 8).clamp(1, 3);
+    let mut scored: Vec<(usize, &str)> = known
+        .into_iter()
+        .filter(|k| !k.starts_with("*."))
+        .map(|k| (edit_distance(&host, k), k))
+        .filter(|(d, _)| *d <= limit)
+        .collect();
+    scored.sort();
+    scored.dedup_by(|a, b| a.1 == b.1);
+    scored.into_iter().take(MAX_SUGGESTIONS).map(|(_, k)| k.to_string()).collect()
+}
+
+pub fn not_found_html(host: &str, suggestions: &[String], links: &LinkBase) -> String {
+    let mut body = format!("<h1>No pm route for <code>{}</code></h1>", escape(host));
+    if !suggestions.is_empty() {
+        body.push_str("<p>Did you mean:</p><ul>");
+        for s in suggestions {
+            let url = links.url(s, "/");
+            let _ = write!(body, "<li><a href=\"{}\">{}</a></li>", escape(&url), escape(s));
+        }
+        body.push_str("</ul>");
+    }
+    let _ = write!(
+        body,
+        "<p><a href=\"{}\">All routes</a></p>",
+        escape(&links.url("localhost", "/"))
+    );
+    page("No pm route", &body)
+}
+
+pub fn not_found_text(host: &str, suggestions: &[String]) -> String {
+    let mut out = format!("No pm route for hostname '{host}'\n");
+    if !suggestions.is_empty() {
+        let _ = writeln!(out, "Did you mean {}?", suggestions.join(" or "));
+    }
+    out
+}
+
+// ── 502 ──
+
+/// The last lines of the route's service log, as shown by `pm logs`.
+pub fn log_tail(route: &RouteEntry) -> Vec<String> {
+    let Some((workspace, project)) = route.project_key.split_once('/') else {
+        return Vec::new();
+    };
+    let path: PathBuf = match services::get(workspace, project, &route.service_key) {
+        Ok(Some(state)) => state.log_path,
+        _ => service_log_path(workspace, project, &route.service_key),
+    };
+    let writer = LineWriter::new(&[], false, false, None);
+    match logs::read_history(&path, false, None, Some(LOG_TAIL_LINES), &writer) {
+        Ok((lines, _)) => lines.iter().map(|l| log_line::display(l, false)).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn bad_gateway_html(route: &RouteEntry, error: &str, log: &[String]) -> String {
+    let service = format!("{}/{}", route.project_key, route.service_key);
+    let mut body = format!(
+        "<h1>Bad gateway</h1><p><code>{}</code> did not answer on port {}: {}</p>",
+        escape(&service),
+        route.upstream_port,
+        escape(error)
+    );
+    if log.is_empty() {
+        body.push_str("<p>Its log is empty.</p>");
+    } else {
+        let _ = write!(body, "<h2>Last {} log lines</h2><pre>", log.len());
+        for line in log {
+            let _ = writeln!(body, "{}", escape(line));
+        }
+        body.push_str("</pre>");
+    }
+    page("Bad gateway", &body)
+}
+
+pub fn bad_gateway_text(route: &RouteEntry, error: &str, log: &[String]) -> String {
+    let mut out = format!("upstream error on port {}: {error}\n", route.upstream_port);
+    if !log.is_empty() {
+        let _ = writeln!(out, "\nLast log lines of {}/{}:", route.project_key, route.service_key);
+        for line in log {
+            let _ = writeln!(out, "  {line}");
+        }
+    }
+    out
+}
+
+// ── Helpers ──
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!doctype html><html><head><meta charset=\"utf-8\"><title>{}</title><style>\
+         body{{font:15px/1.5 system-ui,sans-serif;margin:2rem auto;max-width:60rem;padding:0 1rem;color:#222}}\
+         h1{{font-size:1.4rem}}h2{{font-size:1.05rem;margin-top:1.8rem;color:#555}}\
+         table{{border-collapse:collapse;width:100%}}td{{padding:.3rem .8rem .3rem 0;border-bottom:1px solid #eee}}\
+         a{{color:#0a58ca;text-decoration:none}}a:hover{{text-decoration:underline}}\
+         .port{{color:#888;font-family:monospace}}.empty{{color:#888}}\
+         pre{{background:#f6f6f6;padding:1rem;overflow-x:auto;font-size:13px}}\
+         .dot{{display:inline-block;width:.6rem;height:.6rem;border-radius:50%;margin-right:.4rem;background:#bbb}}\
+         .running{{background:#2da44e}}.restarting{{background:#d4a72c}}.exited{{background:#cf222e}}\
+         </style></head><body>{}</body></html>",
+        escape(title),
+        body
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Levenshtein distance, by bytes (hostnames are ASCII).
+fn edit_distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != cb);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(project: &str, service: &str, hostname: &str, port: u16) -> RouteEntry {
+        RouteEntry {
+            hostname: hostname.into(),
+            upstream_port: port,
+            project_key: project.into(),
+            service_key: service.into(),
+            path_prefix: None,
+            strip_prefix: false,
+            access_log: false,
+            rewrite_host: false,
+        }
+    }
+
+    #[test]
+    fn suggests_close_hostnames_first() {
+        let known = [
+            "back.api.work.localhost",
+            "front.api.work.localhost",
+            "*.back.api.work.localhost",
+            "front.blog.localhost",
+        ];
+        assert_eq!(
+            suggestions("bak.api.work.localhost", known),
+            vec!["back.api.work.localhost"]
+        );
+        assert_eq!(
+            suggestions("frnt.blog.localhost", known),
+            vec!["front.blog.localhost"]
+        );
+        assert!(suggestions("shop.localhost", known).is_empty());
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn index_groups_routes_by_project_and_escapes() {
+        let routes = vec![
+            route("work/api", "back", "back.api.work.localhost", 3001),
+            route("default/blog", "front", "front.blog.localhost", 3000),
+            route("work/api", "back", "*.back.api.work.localhost", 3001),
+            route("work/api", "<x>", "x.localhost", 3002),
+        ];
+        let text = index_text(&routes, &ServicesData::default());
+        let blog = text.find("default/blog").unwrap();
+        let api = text.find("work/api").unwrap();
+        assert!(blog < api, "{text}");
+        assert!(text.contains("back.api.work.localhost/"));
+        assert!(text.contains("not running"));
+
+        let html = index_html(&routes, &ServicesData::default(), &LinkBase::from_host_header("localhost:7100"));
+        assert!(html.contains("<a href=\"//back.api.work.localhost:7100/\">"), "{html}");
+        assert!(html.contains("<td>*.back.api.work.localhost/</td>"), "{html}");
+        assert!(html.contains("&lt;x&gt;") && !html.contains("<x>"));
+    }
+
+    #[test]
+    fn error_pages_carry_suggestions_and_log_tail() {
+        let text = not_found_text("bak.localhost", &["back.localhost".into()]);
+        assert_eq!(text, "No pm route for hostname 'bak.localhost'\nDid you mean back.localhost?\n");
+        let html = not_found_html("bak.localhost", &["back.localhost".into()], &LinkBase::from_host_header("bak.localhost"));
+        assert!(html.contains("<a href=\"//back.localhost/\">back.localhost</a>"));
+
+        let r = route("work/api", "back", "back.api.work.localhost", 3001);
+        let log = vec!["Compiling api".to_string(), "error[E0308]: mismatched types".to_string()];
+        let text = bad_gateway_text(&r, "connection refused", &log);
+        assert!(text.starts_with("upstream error on port 3001: connection refused\n"));
+        assert!(text.contains("Last log lines of work/api/back:\n  Compiling api\n"));
+        assert!(bad_gateway_html(&r, "connection refused", &log).contains("<pre>Compiling api\n"));
+    }
+}
@@ -1,33 +1,191 @@
 //! Reverse-proxy HTTP server.
 //!
-//! Reads `routes.json` (lazily, mtime-cached) and forwards incoming requests
-//! to the upstream port matching the `Host` header. Returns 404 for unknown
-//! hostnames.
+//! Forwards incoming requests to the upstream port matching the `Host`
+//! header, using an in-memory [`RouteTable`] that is swapped out whenever
+//! `routes.json` changes (see [`watch_routes`]). Returns 404 for unknown
+//! hostnames, and serves a route index on `localhost` and `pm.localhost`
+//! (see [`pages`]).
+//!
+//! Request and response bodies are streamed frame-by-frame rather than
+//! buffered, so Server-Sent Events, chunked SSR and large downloads reach the
+//! client as the upstream produces them. Backpressure is inherited from
+//! hyper: a slow client stops the proxy from polling the upstream body.
+//!
+//! HTTP upgrades (WebSocket, HMR sockets) are passed through: the `Upgrade`
+//! handshake is forwarded, and once the upstream answers `101 Switching
+//! Protocols` the client and upstream connections are spliced together
+//! until either side closes.
+//!
+//! Upstreams are told where a request came from with `X-Forwarded-For`,
+//! `-Host`, `-Proto` and `-Port`, RFC 7239 `Forwarded` and `Via`, so
+//! frameworks build absolute URLs for the proxy hostname rather than for
+//! `127.0.0.1:<port>`. Redirects that still point at the upstream port
+//! have their `Location` rewritten (see [`rewrite_location`]).
+//!
+//! While the supervisor is waiting to restart a crashed service, requests
+//! for it get `503 Service Unavailable` with `Retry-After` instead of 502.
+//!
+//! Route reloads, 404s for unknown hosts and 502s are published as
+//! [`events`](super::events), and every request is recorded by the
+//! [`inspector`](super::inspect).
 
+use crate::commands::proxy::events::{EventKind, Events};
+use crate::commands::proxy::inspect::{Inspector, Pending, Tap};
+use crate::commands::proxy::pages;
 use crate::config::routes_path;
-use crate::routes::{RoutesData, load_routes};
-use anyhow::Result;
+use crate::routes::{RouteEntry, RouteTable, load_routes};
+use crate::services::{self, ServiceStatus};
+use anyhow::{Context, Result};
 use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
 use http_body_util::{BodyExt, Full};
-use hyper::body::Incoming;
+use hyper::body::{Body, Incoming};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Request, Response, StatusCode};
 use hyper_util::rt::TokioIo;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use std::convert::Infallible;
-use std::sync::Arc;
-use std::time::SystemTime;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock, mpsc};
+use std::time::{Duration, SystemTime};
 use tokio::net::TcpListener;
-use tokio::sync::{Notify, RwLock};
+use tokio::sync::Notify;
+
+/// Body type returned to proxy clients: either a streamed upstream body or a
+/// locally generated error page.
+type ProxyBody = BoxBody<Bytes, hyper::Error>;
+
+/// `Via` pseudonym for this proxy (RFC 9110 §7.6.3).
+const VIA_PSEUDONYM: &str = "1.1 pm";
+
+/// The client end of a proxied connection, for the forwarding headers.
+#[derive(Debug, Clone, Copy)]
+struct Client {
+    addr: SocketAddr,
+    /// `https` on the TLS listener, `http` otherwise.
+    scheme: &'static str,
+    /// Port the client connected to.
+    port: u16,
+}
+
+/// Quiet period after a `routes.json` event before reloading: one write
+/// by the CLI (tmp file + rename) arrives as several events.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(20);
+
+/// How often `routes.json` is checked when it cannot be watched.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// State shared by the HTTP and HTTPS listeners and the control plane.
+pub struct Proxy {
+    /// Held for reading only for the length of a lookup; a reload builds
+    /// the new table first and takes the write lock just to swap it in.
+    routes: RwLock<RouteTable>,
+    events: Events,
+    inspector: Arc<Inspector>,
+}
+
+impl Proxy {
+    /// A proxy with no routes; call [`Proxy::reload`] to load them.
+    pub fn new(events: Events, inspector: Arc<Inspector>) -> Arc<Self> {
+        Arc::new(Self {
+            routes: RwLock::new(RouteTable::default()),
+            events,
+            inspector,
+        })
+    }
 
-pub async fn serve(port: u16, shutdown: Arc<Notify>) -> Result<()> {
+    /// Re-read `routes.json` and swap in the new table. On error (an
+    /// unreadable or invalid file) the current table stays in place.
+    pub fn reload(&self) -> Result<usize> {
+        Ok(self.install(RouteTable::from(load_routes()?)))
+    }
+
+    fn install(&self, table: RouteTable) -> usize {
+        let count = table.count();
+        *self.routes.write().unwrap() = table;
+        self.events.emit(EventKind::RoutesReloaded { routes: count });
+        count
+    }
+
+    pub fn route_count(&self) -> usize {
+        self.routes.read().unwrap().count()
+    }
+
+    /// Whether any route answers for `hostname`.
+    pub fn serves_host(&self, hostname: &str) -> bool {
+        self.routes.read().unwrap().serves_host(hostname)
+    }
+
+    pub fn inspector(&self) -> &Arc<Inspector> {
+        &self.inspector
+    }
+
+    fn lookup(&self, hostname: &str, path: &str) -> Option<RouteEntry> {
+        self.routes.read().unwrap().lookup(hostname, path).cloned()
+    }
+
+    fn reload_or_log(&self) {
+        if let Err(e) = self.reload() {
+            eprintln!("pm-daemon: routes reload error (keeping current routes): {e:#}");
+        }
+    }
+}
+
+/// Reload `proxy`'s routes whenever `routes.json` changes, until the
+/// returned watcher is dropped. The directory is watched rather than the
+/// file, since every write replaces the file (tmp + rename).
+pub fn watch_routes(proxy: Arc<Proxy>) -> Result<RecommendedWatcher> {
+    let path = routes_path();
+    let dir = path.parent().context("routes.json has no parent directory")?;
+    std::fs::create_dir_all(dir).ok();
+    let file_name = path.file_name().map(|n| n.to_os_string());
+
+    let (tx, rx) = mpsc::channel::<()>();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        let Ok(event) = res else { return };
+        if !event.kind.is_access() && event.paths.iter().any(|p| p.file_name() == file_name.as_deref()) {
+            let _ = tx.send(());
+        }
+    })
+    .context("starting routes.json watcher")?;
+    watcher
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("watching {}", dir.display()))?;
+
+    std::thread::spawn(move || {
+        // Ends when the watcher, and with it the sender, is dropped.
+        while rx.recv().is_ok() {
+            while rx.recv_timeout(RELOAD_DEBOUNCE).is_ok() {}
+            proxy.reload_or_log();
+        }
+    });
+    Ok(watcher)
+}
+
+/// Fallback for [`watch_routes`]: check the mtime of `routes.json` every
+/// [`POLL_INTERVAL`].
+pub async fn poll_routes(proxy: Arc<Proxy>) {
+    let mtime = || std::fs::metadata(routes_path()).and_then(|m| m.modified()).ok();
+    let mut seen: Option<SystemTime> = mtime();
+    let mut tick = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tick.tick().await;
+        let current = mtime();
+        if current != seen {
+            seen = current;
+            proxy.reload_or_log();
+        }
+    }
+}
+
+pub async fn serve(port: u16, proxy: Arc<Proxy>, shutdown: Arc<Notify>) -> Result<()> {
     let addr = format!("127.0.0.1:{port}");
     let listener = TcpListener::bind(&addr).await?;
-    let cache = Arc::new(RwLock::new(RoutesCache::new()));
     eprintln!("pm-daemon: proxy listening on http://{addr}");
 
     loop {
-        let (stream, _peer) = tokio::select! {
+        let (stream, peer) = tokio::select! {
             accept = listener.accept() => match accept {
                 Ok(s) => s,
                 Err(e) => {
@@ -41,30 +199,77 @@ pub async fn serve(port: u16, shutdown: Arc<Notify>) -> Result<()> {
             }
         };
 
-        let cache = cache.clone();
-        let io = TokioIo::new(stream);
-        tokio::spawn(async move {
-            let svc = service_fn(move |req: Request<Incoming>| {
-                let cache = cache.clone();
-                async move { Ok::<_, Infallible>(handle(req, cache).await) }
-            });
-            if let Err(e) = http1::Builder::new()
-                .serve_connection(io, svc)
-                .with_upgrades()
-                .await
-            {
-                // Connection errors are common (client disconnects); log only.
-                let msg = e.to_string();
-                if !msg.contains("incomplete message") {
-                    eprintln!("pm-daemon: connection error: {msg}");
+        let client = Client { addr: peer, scheme: "http", port };
+        tokio::spawn(serve_connection(TokioIo::new(stream), proxy.clone(), client));
+    }
+    Ok(())
+}
+
+/// HTTPS variant of [`serve`]: terminates TLS with the SNI-selected leaf
+/// certificate, then proxies exactly like the plain listener.
+pub async fn serve_tls(
+    port: u16,
+    tls_config: Arc<rustls::ServerConfig>,
+    proxy: Arc<Proxy>,
+    shutdown: Arc<Notify>,
+) -> Result<()> {
+    let addr = format!("127.0.0.1:{port}");
+    let listener = TcpListener::bind(&addr).await?;
+    let acceptor = tokio_rustls::TlsAcceptor::from(tls_config);
+    eprintln!("pm-daemon: proxy listening on https://{addr}");
+
+    loop {
+        let (stream, peer) = tokio::select! {
+            accept = listener.accept() => match accept {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("pm-daemon: TLS accept error: {e}");
+                    continue;
                 }
+            },
+            _ = shutdown.notified() => {
+                eprintln!("pm-daemon: TLS proxy shutting down");
+                break;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let proxy = proxy.clone();
+        let client = Client { addr: peer, scheme: "https", port };
+        tokio::spawn(async move {
+            match acceptor.accept(stream).await {
+                Ok(tls) => serve_connection(TokioIo::new(tls), proxy, client).await,
+                // Typically an untrusted CA on the client side, or an SNI
+                // name without a route.
+                Err(e) => eprintln!("pm-daemon: TLS handshake failed: {e}"),
             }
         });
     }
     Ok(())
 }
 
-async fn handle(req: Request<Incoming>, cache: Arc<RwLock<RoutesCache>>) -> Response<Full<Bytes>> {
+async fn serve_connection<I>(io: I, proxy: Arc<Proxy>, client: Client)
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let svc = service_fn(move |req: Request<Incoming>| {
+        let proxy = proxy.clone();
+        async move { Ok::<_, Infallible>(handle(req, proxy, client).await) }
+    });
+    if let Err(e) = http1::Builder::new()
+        .serve_connection(io, svc)
+        .with_upgrades()
+        .await
+    {
+        // Connection errors are common (client disconnects); log only.
+        let msg = e.to_string();
+        if !msg.contains("incomplete message") {
+            eprintln!("pm-daemon: connection error: {msg}");
+        }
+    }
+}
+
+async fn handle(req: Request<Incoming>, proxy: Arc<Proxy>, client: Client) -> Response<ProxyBody> {
     let host_header = req
         .headers()
         .get(hyper::header::HOST)
@@ -78,60 +283,379 @@ async fn handle(req: Request<Incoming>, cache: Arc<RwLock<RoutesCache>>) -> Resp
         }
     };
 
-    let upstream_port = {
-        let mut guard = cache.write().await;
-        guard.refresh_if_changed();
-        guard.lookup(&host)
-    };
+    let (req, mut pending) = proxy.inspector.begin(req, &host);
+    let resp = route(req, host, &proxy, &mut pending, client).await;
+    Inspector::finish(pending, resp).map(|body| body.boxed())
+}
 
-    let upstream_port = match upstream_port {
-        Some(p) => p,
+async fn route(
+    req: Request<Tap<Incoming>>,
+    host: String,
+    proxy: &Proxy,
+    pending: &mut Pending,
+    client: Client,
+) -> Response<ProxyBody> {
+    let html = pages::wants_html(req.headers());
+    let route = match proxy.lookup(&host, req.uri().path()) {
+        Some(r) => r,
+        None if pages::is_index_host(&host) && req.uri().path() == "/" => {
+            return index(&req, proxy, html).await;
+        }
         None => {
-            return error(
-                StatusCode::NOT_FOUND,
-                &format!("No pm route for hostname '{host}'"),
-            );
+            proxy.events.emit(EventKind::UnknownHost {
+                host: host.clone(),
+                path: req.uri().path().to_string(),
+            });
+            let suggestions = {
+                let routes = proxy.routes.read().unwrap();
+                pages::suggestions(&host, routes.entries().map(|e| e.hostname.as_str()))
+            };
+            return if html {
+                let links = pages::LinkBase::from_host_header(host_header(&req));
+                page(StatusCode::NOT_FOUND, pages::not_found_html(&host, &suggestions, &links))
+            } else {
+                error(StatusCode::NOT_FOUND, pages::not_found_text(&host, &suggestions).trim_end())
+            };
         }
     };
+    pending.route(&route);
+    let upstream_port = route.upstream_port;
+
+    let mut req = req;
+    let stripped_prefix = route.path_prefix.clone().filter(|_| route.strip_prefix);
+    if let Some(prefix) = stripped_prefix.as_deref() {
+        *req.uri_mut() = strip_path_prefix(req.uri(), prefix);
+    }
+    // The client's `Host`, port included, as the upstream should see it
+    // in links and redirects.
+    let public_host = host_header(&req).to_string();
+    add_forwarding_headers(req.headers_mut(), &client);
+    if route.rewrite_host {
+        req.headers_mut().insert(
+            hyper::header::HOST,
+            hyper::header::HeaderValue::from_str(&format!("127.0.0.1:{upstream_port}")).unwrap(),
+        );
+    }
 
     match forward(req, upstream_port).await {
-        Ok(resp) => resp,
-        Err(e) => error(
-            StatusCode::BAD_GATEWAY,
-            &format!("upstream error on port {upstream_port}: {e}"),
-        ),
+        Ok(mut resp) => {
+            rewrite_response_headers(
+                resp.headers_mut(),
+                upstream_port,
+                client.scheme,
+                &public_host,
+                stripped_prefix.as_deref(),
+            );
+            resp
+        }
+        Err(e) => match restarting_message(&route).await {
+            Some(msg) => unavailable(&msg),
+            None => {
+                let e = e.to_string();
+                proxy.events.emit(EventKind::UpstreamError {
+                    host,
+                    port: upstream_port,
+                    error: e.clone(),
+                });
+                bad_gateway(route, e, html).await
+            }
+        },
     }
 }
 
-async fn forward(
-    req: Request<Incoming>,
-    upstream_port: u16,
-) -> Result<Response<Full<Bytes>>> {
+/// The route index, for `localhost` and `pm.localhost` when no service
+/// claims them.
+async fn index<B>(req: &Request<B>, proxy: &Proxy, html: bool) -> Response<ProxyBody> {
+    let routes: Vec<RouteEntry> = proxy.routes.read().unwrap().entries().cloned().collect();
+    let services = tokio::task::spawn_blocking(|| services::load().unwrap_or_default())
+        .await
+        .expect("services task panicked");
+    if html {
+        let links = pages::LinkBase::from_host_header(host_header(req));
+        page(StatusCode::OK, pages::index_html(&routes, &services, &links))
+    } else {
+        Response::builder()
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(full(pages::index_text(&routes, &services)))
+            .unwrap()
+    }
+}
+
+/// 502 with the tail of the service's log, which usually says why it is
+/// not answering (a compile error, a crash on startup).
+async fn bad_gateway(route: RouteEntry, error: String, html: bool) -> Response<ProxyBody> {
+    let (route, log) = tokio::task::spawn_blocking(move || {
+        let log = pages::log_tail(&route);
+        (route, log)
+    })
+    .await
+    .expect("log tail task panicked");
+    if html {
+        page(StatusCode::BAD_GATEWAY, pages::bad_gateway_html(&route, &error, &log))
+    } else {
+        Response::builder()
+            .status(StatusCode::BAD_GATEWAY)
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(full(pages::bad_gateway_text(&route, &error, &log)))
+            .unwrap()
+    }
+}
+
+fn host_header<B>(req: &Request<B>) -> &str {
+    req.headers()
+        .get(hyper::header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+}
+
+/// When the supervisor is between restarts of the route's service, explain
+/// that instead of reporting a bare connection error.
+async fn restarting_message(route: &RouteEntry) -> Option<String> {
+    let (workspace, project) = route.project_key.split_once('/')?;
+    let (workspace, project, service) =
+        (workspace.to_string(), project.to_string(), route.service_key.clone());
+    let state = tokio::task::spawn_blocking(move || services::get(&workspace, &project, &service))
+        .await
+        .expect("services task panicked")
+        .ok()??;
+    if state.status != ServiceStatus::Restarting {
+        return None;
+    }
+    let exit = state.last_exit().unwrap_or_else(|| "an exit".to_string());
+    Some(format!(
+        "Service '{}' of {} is restarting after {exit}; try again shortly",
+        route.service_key, route.project_key
+    ))
+}
+
+async fn forward<B>(mut req: Request<B>, upstream_port: u16) -> Result<Response<ProxyBody>>
+where
+    B: Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
     use hyper::client::conn::http1::handshake;
 
     let stream = tokio::net::TcpStream::connect(("127.0.0.1", upstream_port)).await?;
     let io = TokioIo::new(stream);
     let (mut sender, conn) = handshake(io).await?;
     tokio::spawn(async move {
-        if let Err(e) = conn.await {
+        // `with_upgrades` keeps the raw IO reachable after a 101 so it can
+        // be spliced to the client; a no-op for ordinary requests.
+        if let Err(e) = conn.with_upgrades().await {
             // Upstream HTTP framing errors are usually transient.
             let _ = e;
         }
     });
 
-    // Strip hop-by-hop headers per RFC 7230 §6.1
+    // Claim the client-side upgrade before the request is consumed. hyper
+    // resolves it once we answer 101 on the client connection.
+    let upgrade_token = upgrade_protocol(req.headers());
+    let client_upgrade = upgrade_token
+        .is_some()
+        .then(|| hyper::upgrade::on(&mut req));
+
     let (mut parts, body) = req.into_parts();
-    parts.headers.remove(hyper::header::CONNECTION);
-    parts.headers.remove("proxy-connection");
-    parts.headers.remove(hyper::header::TRANSFER_ENCODING);
-    parts.headers.remove(hyper::header::UPGRADE);
-    parts.headers.remove("keep-alive");
+    strip_hop_by_hop(&mut parts.headers);
+    if let Some(protocol) = upgrade_token {
+        // Re-add the upgrade pair: it is hop-by-hop, but an upgrade has to
+        // be negotiated explicitly on each hop.
+        parts.headers.insert(
+            hyper::header::CONNECTION,
+            hyper::header::HeaderValue::from_static("upgrade"),
+        );
+        parts.headers.insert(hyper::header::UPGRADE, protocol);
+    }
     let outgoing = Request::from_parts(parts, body);
 
-    let resp = sender.send_request(outgoing).await?;
-    let (parts, body) = resp.into_parts();
-    let bytes = body.collect().await?.to_bytes();
-    Ok(Response::from_parts(parts, Full::new(bytes)))
+    let mut resp = sender.send_request(outgoing).await?;
+    // A 101 keeps its `Connection`/`Upgrade` pair: the client needs it to
+    // complete the same upgrade.
+    if resp.status() != StatusCode::SWITCHING_PROTOCOLS {
+        strip_hop_by_hop(resp.headers_mut());
+    }
+
+    if resp.status() == StatusCode::SWITCHING_PROTOCOLS
+        && let Some(client_upgrade) = client_upgrade
+    {
+        let upstream_upgrade = hyper::upgrade::on(&mut resp);
+        tokio::spawn(async move {
+            match tokio::try_join!(client_upgrade, upstream_upgrade) {
+                Ok((client, upstream)) => {
+                    let mut client = TokioIo::new(client);
+                    let mut upstream = TokioIo::new(upstream);
+                    // Either side closing ends the tunnel; errors are just
+                    // disconnects.
+                    let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+                }
+                Err(e) => {
+                    eprintln!("pm-daemon: upgrade on port {upstream_port} failed: {e}");
+                }
+            }
+        });
+    }
+
+    // Hand the upstream body straight back to hyper; frames are forwarded as
+    // they arrive instead of being collected first.
+    Ok(resp.map(|body| body.boxed()))
+}
+
+/// Fields that only describe one connection (RFC 9110 §7.6.1), besides
+/// the ones a message names in its own `Connection` header.
+const HOP_BY_HOP: [&str; 7] = [
+    "connection",
+    "proxy-connection",
+    "keep-alive",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Remove the hop-by-hop fields of a message before it is passed on:
+/// those named in `Connection`, then the [`HOP_BY_HOP`] ones.
+fn strip_hop_by_hop(headers: &mut hyper::HeaderMap) {
+    let listed: Vec<hyper::header::HeaderName> = headers
+        .get_all(hyper::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|token| hyper::header::HeaderName::from_bytes(token.trim().as_bytes()).ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+}
+
+/// Add `X-Forwarded-*`, `Forwarded` and `Via` for `client`. The lists
+/// (`X-Forwarded-For`, `Forwarded`, `Via`) are appended to, in case pm
+/// sits behind another proxy; the single-valued ones describe the hop the
+/// client made to pm and replace anything the client sent.
+fn add_forwarding_headers(headers: &mut hyper::HeaderMap, client: &Client) {
+    use hyper::header::{FORWARDED, HOST, HeaderValue, VIA};
+
+    let host = headers
+        .get(HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let ip = client.addr.ip();
+    // RFC 7239 §6: IPv6 nodes are bracketed and quoted.
+    let node = match ip {
+        std::net::IpAddr::V4(v4) => v4.to_string(),
+        std::net::IpAddr::V6(v6) => format!("\"[{v6}]\""),
+    };
+    let forwarded = format!("for={node};host=\"{host}\";proto={}", client.scheme);
+
+    let mut set = |name, value: String| {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    };
+    set(hyper::header::HeaderName::from_static("x-forwarded-host"), host.clone());
+    set(hyper::header::HeaderName::from_static("x-forwarded-proto"), client.scheme.to_string());
+    set(hyper::header::HeaderName::from_static("x-forwarded-port"), client.port.to_string());
+
+    append_list(headers, hyper::header::HeaderName::from_static("x-forwarded-for"), &ip.to_string());
+    append_list(headers, FORWARDED, &forwarded);
+    append_list(headers, VIA, VIA_PSEUDONYM);
+}
+
+/// Append `value` to a comma-separated list header, merging repeated
+/// fields into one.
+fn append_list(headers: &mut hyper::HeaderMap, name: hyper::header::HeaderName, value: &str) {
+    let mut items: Vec<&str> = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+    items.push(value);
+    if let Ok(value) = hyper::header::HeaderValue::from_str(&items.join(", ")) {
+        headers.insert(name, value);
+    }
+}
+
+/// Add this hop to the response's `Via` and point a `Location` at the
+/// upstream back at the hostname the client used.
+fn rewrite_response_headers(
+    headers: &mut hyper::HeaderMap,
+    upstream_port: u16,
+    scheme: &str,
+    public_host: &str,
+    stripped_prefix: Option<&str>,
+) {
+    use hyper::header::{HeaderValue, LOCATION, VIA};
+
+    append_list(headers, VIA, VIA_PSEUDONYM);
+    let rewritten = headers.get(LOCATION).and_then(|v| v.to_str().ok()).and_then(|location| {
+        rewrite_location(location, upstream_port, scheme, public_host, stripped_prefix)
+    });
+    if let Some(value) = rewritten.and_then(|l| HeaderValue::from_str(&l).ok()) {
+        headers.insert(LOCATION, value);
+    }
+}
+
+/// `http://127.0.0.1:3000/login?next=/` → `http://front.blog.localhost:7100/login?next=/`
+/// when the upstream listens on 3000. Only absolute (or scheme-relative)
+/// URLs naming a loopback address on the upstream's own port are
+/// rewritten; relative paths and other hosts are left alone.
+///
+/// Behind a route with `strip_path`, the upstream does not know about the
+/// `stripped_prefix` it is served under, so the prefix is put back:
+/// `/login` and `http://127.0.0.1:3000/login` both become `…/api/login`.
+fn rewrite_location(
+    location: &str,
+    upstream_port: u16,
+    scheme: &str,
+    public_host: &str,
+    stripped_prefix: Option<&str>,
+) -> Option<String> {
+    let prefix = stripped_prefix.map_or("", |p| p.trim_end_matches('/'));
+    if !prefix.is_empty() && location.starts_with('/') && !location.starts_with("//") {
+        return Some(format!("{prefix}{location}"));
+    }
+    let rest = ["http://", "https://", "//"]
+        .iter()
+        .find_map(|prefix| location.strip_prefix(prefix))?;
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (authority, tail) = rest.split_at(end);
+    let (host, port) = authority.rsplit_once(':')?;
+    let loopback = matches!(
+        host.to_ascii_lowercase().as_str(),
+        "127.0.0.1" | "localhost" | "0.0.0.0" | "[::1]" | "[::]"
+    );
+    (loopback && port.parse() == Ok(upstream_port))
+        .then(|| format!("{scheme}://{public_host}{prefix}{tail}"))
+}
+
+/// If the request asks for a protocol upgrade (`Connection: upgrade` plus an
+/// `Upgrade` header, e.g. WebSocket), return the requested protocol.
+fn upgrade_protocol(headers: &hyper::HeaderMap) -> Option<hyper::header::HeaderValue> {
+    let wants_upgrade = headers
+        .get_all(hyper::header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+    if !wants_upgrade {
+        return None;
+    }
+    headers.get(hyper::header::UPGRADE).cloned()
+}
+
+/// Remove `prefix` from the start of `uri`'s path, keeping the query. The
+/// result always starts with `/` (`/api` → `/`, `/api/users` → `/users`).
+fn strip_path_prefix(uri: &hyper::Uri, prefix: &str) -> hyper::Uri {
+    let path = uri.path();
+    let rest = path.strip_prefix(prefix).unwrap_or(path);
+    let rest = if rest.starts_with('/') { rest } else { "/" };
+    let path_and_query = match uri.query() {
+        Some(q) => format!("{rest}?{q}"),
+        None => rest.to_string(),
+    };
+    path_and_query.parse().unwrap_or_else(|_| uri.clone())
 }
 
 fn strip_port(host: &str) -> String {
@@ -141,58 +665,97 @@ fn strip_port(host: &str) -> String {
     }
 }
 
-fn error(status: StatusCode, msg: &str) -> Response<Full<Bytes>> {
+fn error(status: StatusCode, msg: &str) -> Response<ProxyBody> {
     Response::builder()
         .status(status)
         .header("content-type", "text/plain; charset=utf-8")
-        .body(Full::new(Bytes::from(format!("{}\n", msg))))
+        .body(full(format!("{}\n", msg)))
         .unwrap()
 }
 
-// ── routes.json mtime-cached lookup ──
-
-struct RoutesCache {
-    data: RoutesData,
-    mtime: Option<SystemTime>,
+fn page(status: StatusCode, html: String) -> Response<ProxyBody> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/html; charset=utf-8")
+        .body(full(html))
+        .unwrap()
 }
 
-impl RoutesCache {
-    fn new() -> Self {
-        Self {
-            data: RoutesData::default(),
-            mtime: None,
-        }
-    }
-
-    fn refresh_if_changed(&mut self) {
-        let path = routes_path();
-        let cur_mtime = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
-        if cur_mtime != self.mtime {
-            match load_routes() {
-                Ok(d) => {
-                    self.data = d;
-                    self.mtime = cur_mtime;
-                }
-                Err(e) => {
-                    // Tolerate mid-write states; keep previous cache.
-                    eprintln!("pm-daemon: routes reload error (ignored): {e}");
-                }
-            }
-        }
-    }
+fn unavailable(msg: &str) -> Response<ProxyBody> {
+    let mut resp = error(StatusCode::SERVICE_UNAVAILABLE, msg);
+    resp.headers_mut()
+        .insert(hyper::header::RETRY_AFTER, hyper::header::HeaderValue::from_static("1"));
+    resp
+}
 
-    fn lookup(&self, hostname: &str) -> Option<u16> {
-        self.data
-            .entries
-            .iter()
-            .find(|e| e.hostname.eq_ignore_ascii_case(hostname))
-            .map(|e| e.upstream_port)
-    }
+fn full(content: impl Into<Bytes>) -> ProxyBody {
+    Full::new(content.into())
+        .map_err(|never| match never {})
+        .boxed()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::oneshot;
+
+    fn numbered_routes(count: u16, port_base: u16) -> RouteTable {
+        let entries = (0..count)
+            .map(|i| RouteEntry {
+                hostname: format!("svc{i}.app.default.localhost"),
+                upstream_port: port_base + i,
+                project_key: "default/app".into(),
+                service_key: format!("svc{i}"),
+                path_prefix: None,
+                strip_prefix: false,
+                access_log: false,
+                rewrite_host: false,
+            })
+            .collect();
+        RouteTable::from(crate::routes::RoutesData { version: 1, entries })
+    }
+
+    /// Lookups from many threads while routes are being reloaded. Every
+    /// lookup must see a complete table (old or new, never a mix).
+    #[tokio::test]
+    async fn concurrent_lookups_during_reloads() {
+        const ROUTES: u16 = 500;
+        const THREADS: usize = 8;
+        const RELOADS: usize = 50;
+
+        let proxy = Proxy::new(Events::default(), Inspector::new());
+        proxy.install(numbered_routes(ROUTES, 10_000));
+        let stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let readers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let (proxy, stop) = (proxy.clone(), stop.clone());
+                std::thread::spawn(move || {
+                    let mut n = 0;
+                    while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                        let i = ((n * 7 + t) % ROUTES as usize) as u16;
+                        let route = proxy
+                            .lookup(&format!("svc{i}.app.default.localhost"), "/")
+                            .expect("every host is routed in both tables");
+                        assert!(route.upstream_port == 10_000 + i || route.upstream_port == 30_000 + i);
+                        n += 1;
+                    }
+                    assert!(proxy.lookup("unknown.localhost", "/").is_none());
+                })
+            })
+            .collect();
+
+        // Readers keep looking up for as long as tables are being swapped.
+        for reload in 0..RELOADS {
+            let base = if reload % 2 == 0 { 30_000 } else { 10_000 };
+            proxy.install(numbered_routes(ROUTES, base));
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
 
     #[test]
     fn strip_port_with_port() {
@@ -203,4 +766,318 @@ mod tests {
     fn strip_port_without_port() {
         assert_eq!(strip_port("api.work.localhost"), "api.work.localhost");
     }
+
+    /// Serve `forward` on an ephemeral port, bypassing the routes cache.
+    async fn spawn_forwarding_proxy(upstream_port: u16) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let svc = service_fn(move |req: Request<Incoming>| async move {
+                        Ok::<_, Infallible>(match forward(req, upstream_port).await {
+                            Ok(resp) => resp,
+                            Err(e) => error(StatusCode::BAD_GATEWAY, &e.to_string()),
+                        })
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), svc)
+                        .with_upgrades()
+                        .await;
+                });
+            }
+        });
+        port
+    }
+
+    async fn send_via_proxy(
+        proxy_port: u16,
+        req: Request<Full<Bytes>>,
+    ) -> Response<Incoming> {
+        let stream = tokio::net::TcpStream::connect(("127.0.0.1", proxy_port))
+            .await
+            .unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        sender.send_request(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn response_body_streams_before_upstream_finishes() {
+        // Raw chunked upstream: emits one chunk, then blocks until the test
+        // confirms the proxy already delivered it.
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (mut sock, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = sock.read(&mut buf).await.unwrap();
+            sock.write_all(
+                b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                  transfer-encoding: chunked\r\n\r\n5\r\nfirst\r\n",
+            )
+            .await
+            .unwrap();
+            let _ = release_rx.await;
+            sock.write_all(b"6\r\nsecond\r\n0\r\n\r\n").await.unwrap();
+        });
+
+        let proxy_port = spawn_forwarding_proxy(upstream_port).await;
+        let req = Request::get("/events")
+            .header("host", "front.blog.localhost")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let resp = send_via_proxy(proxy_port, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let mut body = resp.into_body();
+        let first = tokio::time::timeout(std::time::Duration::from_secs(5), body.frame())
+            .await
+            .expect("first chunk must arrive while the upstream is still open")
+            .unwrap()
+            .unwrap();
+        assert_eq!(first.into_data().unwrap(), Bytes::from_static(b"first"));
+
+        release_tx.send(()).unwrap();
+        let rest = body.collect().await.unwrap().to_bytes();
+        assert_eq!(rest, Bytes::from_static(b"second"));
+    }
+
+    #[test]
+    fn hop_by_hop_fields_are_stripped() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("connection", "keep-alive, X-Private".parse().unwrap());
+        headers.insert("x-private", "1".parse().unwrap());
+        headers.insert("keep-alive", "timeout=5".parse().unwrap());
+        headers.insert("te", "trailers".parse().unwrap());
+        headers.insert("trailer", "x-checksum".parse().unwrap());
+        headers.insert("transfer-encoding", "chunked".parse().unwrap());
+        headers.insert("content-type", "text/plain".parse().unwrap());
+        strip_hop_by_hop(&mut headers);
+        let left: Vec<&str> = headers.keys().map(|k| k.as_str()).collect();
+        assert_eq!(left, vec!["content-type"]);
+    }
+
+    #[tokio::test]
+    async fn hop_by_hop_fields_do_not_cross_the_proxy() {
+        // Raw upstream: echoes the request head it received as the body
+        // and adds hop-by-hop fields of its own.
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut sock, _) = upstream.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let n = sock.read(&mut buf).await.unwrap();
+            let head = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+            let resp = format!(
+                "HTTP/1.1 200 OK\r\nconnection: x-upstream-private\r\nx-upstream-private: 1\r\n\
+                 keep-alive: timeout=5\r\ncontent-length: {}\r\n\r\n{head}",
+                head.len()
+            );
+            sock.write_all(resp.as_bytes()).await.unwrap();
+        });
+
+        let proxy_port = spawn_forwarding_proxy(upstream_port).await;
+        let req = Request::get("/")
+            .header("host", "front.blog.localhost")
+            .header("connection", "x-client-private")
+            .header("x-client-private", "1")
+            .header("keep-alive", "300")
+            .header("te", "trailers")
+            .header("x-end-to-end", "1")
+            .body(Full::new(Bytes::new()))
+            .unwrap();
+        let resp = send_via_proxy(proxy_port, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get("x-upstream-private").is_none());
+        assert!(resp.headers().get("keep-alive").is_none());
+
+        let seen = resp.into_body().collect().await.unwrap().to_bytes();
+        let seen = String::from_utf8_lossy(&seen);
+        assert!(seen.contains("x-end-to-end: 1"), "{seen}");
+        for field in ["x-client-private", "keep-alive", "te:"] {
+            assert!(!seen.contains(field), "{field} reached the upstream: {seen}");
+        }
+    }
+
+    #[test]
+    fn strip_path_prefix_keeps_query_and_leading_slash() {
+        let uri: hyper::Uri = "/api/users?page=2".parse().unwrap();
+        assert_eq!(strip_path_prefix(&uri, "/api"), "/users?page=2");
+        let uri: hyper::Uri = "/api".parse().unwrap();
+        assert_eq!(strip_path_prefix(&uri, "/api"), "/");
+        let uri: hyper::Uri = "/api?x=1".parse().unwrap();
+        assert_eq!(strip_path_prefix(&uri, "/api"), "/?x=1");
+    }
+
+    #[test]
+    fn upgrade_protocol_requires_connection_token() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("upgrade", "websocket".parse().unwrap());
+        assert!(upgrade_protocol(&headers).is_none());
+
+        headers.insert("connection", "keep-alive, Upgrade".parse().unwrap());
+        assert_eq!(upgrade_protocol(&headers).unwrap(), "websocket");
+    }
+
+    #[test]
+    fn forwarding_headers_describe_the_client_hop() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert("host", "front.blog.localhost:7443".parse().unwrap());
+        headers.insert("x-forwarded-proto", "ftp".parse().unwrap());
+        headers.append("x-forwarded-for", "10.0.0.1".parse().unwrap());
+        headers.append("x-forwarded-for", "10.0.0.2".parse().unwrap());
+        let client = Client {
+            addr: "[::1]:51234".parse().unwrap(),
+            scheme: "https",
+            port: 7443,
+        };
+        add_forwarding_headers(&mut headers, &client);
+
+        assert_eq!(headers["x-forwarded-for"], "10.0.0.1, 10.0.0.2, ::1");
+        assert_eq!(headers["x-forwarded-host"], "front.blog.localhost:7443");
+        assert_eq!(headers["x-forwarded-proto"], "https");
+        assert_eq!(headers["x-forwarded-port"], "7443");
+        assert_eq!(
+            headers["forwarded"],
+            "for=\"[::1]\";host=\"front.blog.localhost:7443\";proto=https"
+        );
+        assert_eq!(headers["via"], "1.1 pm");
+    }
+
+    #[test]
+    fn location_is_rewritten_only_for_the_upstream_port() {
+        let host = "front.blog.localhost:7100";
+        assert_eq!(
+            rewrite_location("http://127.0.0.1:3000/login?next=/", 3000, "http", host, None).as_deref(),
+            Some("http://front.blog.localhost:7100/login?next=/")
+        );
+        assert_eq!(
+            rewrite_location("http://localhost:3000", 3000, "https", host, None).as_deref(),
+            Some("https://front.blog.localhost:7100")
+        );
+        assert_eq!(
+            rewrite_location("//[::1]:3000#top", 3000, "http", host, None).as_deref(),
+            Some("http://front.blog.localhost:7100#top")
+        );
+        assert_eq!(rewrite_location("/login", 3000, "http", host, None), None);
+        assert_eq!(rewrite_location("http://127.0.0.1:3001/", 3000, "http", host, None), None);
+        assert_eq!(rewrite_location("https://github.com/login", 3000, "http", host, None), None);
+    }
+
+    #[test]
+    fn location_keeps_the_stripped_path_prefix() {
+        let host = "blog.localhost:7100";
+        let api = Some("/api");
+        assert_eq!(
+            rewrite_location("http://127.0.0.1:3000/login?next=/", 3000, "http", host, api).as_deref(),
+            Some("http://blog.localhost:7100/api/login?next=/")
+        );
+        assert_eq!(
+            rewrite_location("http://localhost:3000", 3000, "http", host, Some("/api/")).as_deref(),
+            Some("http://blog.localhost:7100/api")
+        );
+        assert_eq!(rewrite_location("/login", 3000, "http", host, api).as_deref(), Some("/api/login"));
+        assert_eq!(rewrite_location("login", 3000, "http", host, api), None);
+        assert_eq!(rewrite_location("//github.com/login", 3000, "http", host, api), None);
+        assert_eq!(rewrite_location("http://127.0.0.1:3001/", 3000, "http", host, api), None);
+    }
+
+    /// Read from `sock` until the end of an HTTP head (`\r\n\r\n`).
+    async fn read_head(sock: &mut tokio::net::TcpStream) -> String {
+        let mut head = Vec::new();
+        let mut byte = [0u8; 1];
+        while !head.ends_with(b"\r\n\r\n") {
+            if sock.read(&mut byte).await.unwrap() == 0 {
+                break;
+            }
+            head.push(byte[0]);
+        }
+        String::from_utf8(head).unwrap()
+    }
+
+    #[tokio::test]
+    async fn websocket_upgrade_is_spliced_to_upstream() {
+        // Echo upstream: accepts the upgrade, then echoes raw bytes back.
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut sock, _) = upstream.accept().await.unwrap();
+            let head = read_head(&mut sock).await.to_ascii_lowercase();
+            assert!(head.contains("upgrade: websocket"), "{head}");
+            assert!(head.contains("connection: upgrade"), "{head}");
+            assert!(head.contains("sec-websocket-key: dghlihnhbxbszsbub25jzq=="));
+            sock.write_all(
+                b"HTTP/1.1 101 Switching Protocols\r\nconnection: upgrade\r\n\
+                  upgrade: websocket\r\n\r\n",
+            )
+            .await
+            .unwrap();
+            let mut buf = [0u8; 64];
+            loop {
+                let n = sock.read(&mut buf).await.unwrap();
+                if n == 0 {
+                    break;
+                }
+                sock.write_all(&buf[..n]).await.unwrap();
+            }
+        });
+
+        let proxy_port = spawn_forwarding_proxy(upstream_port).await;
+        let mut client = tokio::net::TcpStream::connect(("127.0.0.1", proxy_port))
+            .await
+            .unwrap();
+        client
+            .write_all(
+                b"GET /_next/webpack-hmr HTTP/1.1\r\nhost: front.blog.localhost\r\n\
+                  connection: Upgrade\r\nupgrade: websocket\r\n\
+                  sec-websocket-key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+                  sec-websocket-version: 13\r\n\r\n",
+            )
+            .await
+            .unwrap();
+        let head = read_head(&mut client).await;
+        assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+
+        for msg in [&b"ping"[..], &b"hmr-update"[..]] {
+            client.write_all(msg).await.unwrap();
+            let mut echoed = vec![0u8; msg.len()];
+            tokio::time::timeout(
+                std::time::Duration::from_secs(5),
+                client.read_exact(&mut echoed),
+            )
+            .await
+            .expect("echo should come back through the tunnel")
+            .unwrap();
+            assert_eq!(echoed, msg);
+        }
+    }
+
+    #[tokio::test]
+    async fn request_body_is_forwarded_to_upstream() {
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream_port = upstream.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = upstream.accept().await.unwrap();
+            let svc = service_fn(|req: Request<Incoming>| async move {
+                let echoed = req.into_body().collect().await?.to_bytes();
+                Ok::<_, hyper::Error>(Response::new(Full::new(echoed)))
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), svc)
+                .await;
+        });
+
+        let proxy_port = spawn_forwarding_proxy(upstream_port).await;
+        let req = Request::post("/upload")
+            .header("host", "back.api.work.localhost")
+            .body(Full::new(Bytes::from_static(b"payload")))
+            .unwrap();
+        let resp = send_via_proxy(proxy_port, req).await;
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, Bytes::from_static(b"payload"));
+    }
 }
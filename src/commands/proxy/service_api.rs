@@ -0,0 +1,336 @@
+//! Service management over the control plane.
+//!
+//! | method | path                                   | purpose                         |
+//! |--------|----------------------------------------|---------------------------------|
+//! | POST   | /services/{ws}/{project}/{svc}/start   | `pm run <svc>`                  |
+//! | POST   | /services/{ws}/{project}/{svc}/stop    | `pm stop <svc>`                 |
+//! | POST   | /services/{ws}/{project}/{svc}/restart | `pm restart <svc>`              |
+//! | GET    | /services/{ws}/{project}/{svc}/logs    | log lines, `?lines=N&follow=1`  |
+//!
+//! Start, stop and restart do what the CLI commands do, on a blocking
+//! thread since they wait for readiness probes and stop timeouts, and
+//! reply with the service's state afterwards. Starting builds each
+//! [`SpawnSpec`] with the same helper as `pm run` and hands it to the
+//! daemon's [`Supervisor`] directly; it does not start Docker containers
+//! or create databases, and reports failed readiness probes in the reply
+//! instead of on a terminal. Like every state-changing control route,
+//! they are refused to browser callers (see [`control`](super::control)).
+//! Stop works from `services.json` alone, so a service that has since
+//! been removed from `.proj.yaml` can still be stopped. Path segments are
+//! percent-decoded.
+//!
+//! A service needs a base environment, and the daemon has no shell. It
+//! uses the environment of the last `pm run` that started any service of
+//! the project through this daemon, so a restart from the API sees the
+//! same variables as one from the terminal. When there is none (the
+//! daemon was restarted since), it falls back to the daemon's own
+//! environment, i.e. that of the `pm` command that started the daemon.
+//! The `.proj.yaml` env layers and pm's port variables apply on top either
+//! way.
+//!
+//! Logs are
+//! returned in their on-disk format (`<timestamp> <stream> <text>`, see
+//! [`crate::log_line`]); with `follow=1` the response stays open and new
+//! lines are streamed as they are written, until the client disconnects.
+
+use crate::commands::logs::{self, LineWriter, LogSource};
+use crate::commands::proxy::control::ChannelBody;
+use crate::commands::proxy::inspect::decode_path_segment;
+use crate::commands::proxy::supervisor::{SpawnSpec, Supervisor};
+use crate::commands::readiness::{self, Outcome};
+use crate::commands::run::resolve_project;
+use crate::commands::{orchestrator, stop};
+use crate::config::service_log_path;
+use crate::error::PmError;
+use crate::models::Project;
+use crate::project::{load_proj_config, ProjConfig};
+use crate::services::{self as svc_state, ServiceState};
+use anyhow::{anyhow, bail, Context, Result};
+use bytes::Bytes;
+use hyper::StatusCode;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// Lines of history sent by `/logs` when `lines` is not given.
+const DEFAULT_LOG_LINES: usize = 100;
+
+/// A `/services/{ws}/{project}/{svc}/{action}` path, percent-decoded.
+#[derive(Debug, PartialEq)]
+pub struct ServicePath {
+    pub workspace: String,
+    pub project: String,
+    pub service: String,
+    pub action: String,
+}
+
+/// `None` unless `path` has exactly four non-empty segments under
+/// `/services/`. A segment that does not decode, or decodes to something
+/// that could leave pm's directories (`..`, a separator), also yields
+/// `None`.
+pub fn parse_path(path: &str) -> Option<ServicePath> {
+    let parts = path
+        .strip_prefix("/services/")?
+        .split('/')
+        .map(|p| {
+            let p = decode_path_segment(p).ok()?;
+            let safe = !matches!(p.as_str(), "" | "." | "..") && !p.contains(['/', '\\']);
+            safe.then_some(p)
+        })
+        .collect::<Option<Vec<String>>>()?;
+    let [workspace, project, service, action] = <[String; 4]>::try_from(parts).ok()?;
+    Some(ServicePath { workspace, project, service, action })
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServiceReply {
+    pub ok: bool,
+    /// The service's `services.json` entry; `None` once stopped.
+    pub state: Option<ServiceState>,
+    /// For `stop`: whether the service was running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped: Option<bool>,
+}
+
+/// A failed request: the status to answer with and why.
+pub struct ApiError(pub StatusCode, pub String);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(e: anyhow::Error) -> Self {
+        let status = match e.downcast_ref::<PmError>() {
+            Some(PmError::ProjectNotFound(_) | PmError::WorkspaceNotFound(_)) => {
+                StatusCode::NOT_FOUND
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, format!("{e:#}"))
+    }
+}
+
+/// Run `start`, `stop` or `restart` for one service.
+pub async fn lifecycle(
+    target: &ServicePath,
+    supervisor: Arc<Supervisor>,
+) -> Result<ServiceReply, ApiError> {
+    let action = target.action.clone();
+    let (workspace, project, service) = (
+        target.workspace.clone(),
+        target.project.clone(),
+        target.service.clone(),
+    );
+    tokio::task::spawn_blocking(move || -> Result<ServiceReply, ApiError> {
+        // Stopping goes by what `services.json` records, so a service
+        // removed from `.proj.yaml` while running can still be stopped.
+        if action == "stop" && svc_state::get(&workspace, &project, &service)?.is_some() {
+            let stopped = stop::stop_service(&workspace, &project, &service, false)?;
+            return Ok(ServiceReply { ok: true, state: None, stopped: Some(stopped) });
+        }
+        let (workspace, project_obj, project_dir) =
+            resolve_project(Some(format!("@{workspace}/{project}")))?;
+        let proj_config = load_proj_config(&project_dir)?;
+        if !proj_config.services.contains_key(&service) {
+            return Err(ApiError(
+                StatusCode::NOT_FOUND,
+                format!("service '{service}' is not defined in {workspace}/{project}"),
+            ));
+        }
+        let start = || {
+            start(&supervisor, &workspace, &project_obj, &project_dir, &proj_config, &service)
+        };
+        let mut stopped = None;
+        match action.as_str() {
+            "start" => start()?,
+            "stop" => stopped = Some(stop::stop_service(&workspace, &project, &service, false)?),
+            "restart" => {
+                stop::stop_service(&workspace, &project, &service, false)?;
+                start()?;
+            }
+            other => {
+                return Err(ApiError(StatusCode::NOT_FOUND, format!("unknown action '{other}'")));
+            }
+        }
+        Ok(ServiceReply {
+            ok: true,
+            state: svc_state::get(&workspace, &project, &service)?,
+            stopped,
+        })
+    })
+    .await
+    .map_err(|e| ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+}
+
+/// Start `service` and whatever it `depends_on` that is not running, in
+/// dependency order, waiting for readiness probes like `pm run` does.
+fn start(
+    supervisor: &Supervisor,
+    workspace: &str,
+    project: &Project,
+    project_dir: &Path,
+    proj_config: &ProjConfig,
+    service: &str,
+) -> Result<()> {
+    let services = orchestrator::pick_services(proj_config, Some(service))?;
+    orchestrator::allocate_all_ports(workspace, project, proj_config)?;
+    let shell_env = supervisor
+        .shell_env(workspace, &project.name)
+        .unwrap_or_else(|| std::env::vars().collect());
+    for (name, resolved) in &services {
+        if let Some(state) = svc_state::get(workspace, &project.name, name)?
+            && svc_state::pid_alive(state.pid)
+        {
+            continue;
+        }
+        let spec: SpawnSpec = orchestrator::spawn_spec(
+            workspace,
+            project,
+            project_dir,
+            proj_config,
+            name,
+            resolved,
+            shell_env.clone(),
+        )?;
+        let log_offset = std::fs::metadata(&spec.log_path).map(|m| m.len()).unwrap_or(0);
+        let (port, log_path) = (spec.port, spec.log_path.clone());
+        supervisor
+            .spawn(spec)
+            .with_context(|| format!("starting service '{name}'"))?;
+
+        let Some(probe) = &resolved.ready else { continue };
+        let label = format!("{}/{}", project.name, name);
+        let target = readiness::Target {
+            label: &label,
+            port,
+            log_path: &log_path,
+            log_offset,
+        };
+        match readiness::wait_quietly(&target, probe, || {
+            orchestrator::gone(workspace, &project.name, name)
+        }) {
+            Outcome::Ready(_) => {}
+            Outcome::TimedOut => bail!(
+                "{label} not ready after {}s ({})",
+                probe.timeout_secs,
+                probe.check.describe()
+            ),
+            Outcome::Exited(reason) => bail!("{label} exited before it was ready: {reason}"),
+        }
+    }
+    Ok(())
+}
+
+/// Options of `GET .../logs`.
+#[derive(Debug, Default, PartialEq)]
+pub struct LogQuery {
+    pub lines: Option<usize>,
+    pub follow: bool,
+}
+
+impl LogQuery {
+    pub fn parse(query: Option<&str>) -> Result<Self> {
+        let mut out = LogQuery::default();
+        for pair in query.unwrap_or("").split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key {
+                "lines" => {
+                    out.lines = Some(value.parse().map_err(|_| anyhow!("invalid lines '{value}'"))?)
+                }
+                "follow" => out.follow = matches!(value, "" | "1" | "true"),
+                _ => {}
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The log history of a service, followed by new lines when asked to.
+pub fn logs(target: &ServicePath, query: &LogQuery) -> Result<ChannelBody, ApiError> {
+    let path = log_path(target)?;
+    let writer = LineWriter::new(&[], false, false, None);
+    let limit = query.lines.unwrap_or(DEFAULT_LOG_LINES);
+    let (history, end) = logs::read_history(&path, false, None, Some(limit), &writer)?;
+
+    let (tx, rx) = mpsc::channel(64);
+    let follow = query.follow;
+    tokio::spawn(async move {
+        let mut chunk: String = history.iter().map(|l| format!("{l}\n")).collect();
+        let mut source = LogSource::new(String::new(), path, end);
+        loop {
+            if !chunk.is_empty()
+                && tx.send(Bytes::from(std::mem::take(&mut chunk))).await.is_err()
+            {
+                return;
+            }
+            if !follow {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if tx.is_closed() {
+                return;
+            }
+            match source.poll() {
+                Ok(lines) => chunk = lines.iter().map(|l| format!("{l}\n")).collect(),
+                Err(_) => return,
+            }
+        }
+    });
+    Ok(ChannelBody(rx))
+}
+
+fn log_path(target: &ServicePath) -> Result<PathBuf, ApiError> {
+    let path = match svc_state::get(&target.workspace, &target.project, &target.service)? {
+        Some(state) => state.log_path,
+        None => service_log_path(&target.workspace, &target.project, &target.service),
+    };
+    if !path.exists() {
+        return Err(ApiError(
+            StatusCode::NOT_FOUND,
+            format!(
+                "no logs for {}/{}/{}",
+                target.workspace, target.project, target.service
+            ),
+        ));
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_service_paths() {
+        assert_eq!(
+            parse_path("/services/work/api/back/restart"),
+            Some(ServicePath {
+                workspace: "work".into(),
+                project: "api".into(),
+                service: "back".into(),
+                action: "restart".into()
+            })
+        );
+        assert_eq!(
+            parse_path("/services/work/my%20api/back%2Bup/stop").map(|p| (p.project, p.service)),
+            Some(("my api".into(), "back+up".into()))
+        );
+        assert_eq!(parse_path("/services/work/%2E%2E/back/logs"), None);
+        assert_eq!(parse_path("/services/work/a%2Fb/back/logs"), None);
+        assert_eq!(parse_path("/services/work/api/back%zz/stop"), None);
+        assert_eq!(parse_path("/services/spawn"), None);
+        assert_eq!(parse_path("/services/work/api/back"), None);
+        assert_eq!(parse_path("/services/work//back/stop"), None);
+        assert_eq!(parse_path("/services/work/api/back/stop/extra"), None);
+    }
+
+    #[test]
+    fn parses_log_queries() {
+        assert_eq!(LogQuery::parse(None).unwrap(), LogQuery::default());
+        assert_eq!(
+            LogQuery::parse(Some("follow=1&lines=20")).unwrap(),
+            LogQuery { lines: Some(20), follow: true }
+        );
+        assert!(!LogQuery::parse(Some("follow=0")).unwrap().follow);
+        assert!(LogQuery::parse(Some("lines=many")).is_err());
+    }
+}
@@ -0,0 +1,715 @@
+//! Service supervision inside the daemon.
+//!
+//! `pm run` no longer spawns services itself: it resolves everything the
+//! process needs (program, arguments, environment, working directory, log
+//! file) into a [`SpawnSpec`] and hands it to the daemon over the control
+//! plane. The daemon spawns the child, keeps its handle, and reacts when
+//! it exits:
+//!
+//! - the exit code (or fatal signal) is recorded in `services.json`;
+//! - if the service's [`RestartPolicy`] asks for it, the process is
+//!   started again after an exponential backoff, with the route left in
+//!   place so the proxy answers `503` in the meantime;
+//! - otherwise (or after [`MAX_QUICK_RESTARTS`] crashes in a row) the
+//!   entry is marked `exited` and its routes are removed.
+//!
+//! Services with `watch:` are also restarted when their files change (see
+//! [`super::watch`]), including after they have exited.
+//!
+//! An exit whose `services.json` entry is gone, or now points at another
+//! PID, was caused by `pm stop` / a newer `pm run` and is left alone.
+//!
+//! The child's stdout and stderr are pipes into two `pm __log-pipe`
+//! processes, which write each line to the service log with a timestamp
+//! and stream tag (see [`crate::log_line`]). They run in their own
+//! session, not the daemon's, so a service keeps logging through a daemon
+//! restart or crash instead of dying of `SIGPIPE`, and exit once every
+//! process holding the pipes has closed them.
+//!
+//! Services run in their own session too, but only the daemon that
+//! spawned them can supervise them: after a daemon restart,
+//! already-running services are no longer restarted on crash.
+
+use crate::commands::proxy::events::{EventKind, Events};
+use crate::commands::proxy::watch::Watch;
+use crate::log_line::{self, Stream};
+use crate::project::{RestartPolicy, WatchConfig};
+use crate::routes;
+use crate::services::{self as svc_state, ServiceState, ServiceStatus};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
+
+/// A run that lasts at least this long resets the crash-loop counter.
+const STABLE_AFTER: Duration = Duration::from_secs(30);
+/// Give up after this many consecutive exits that happen before
+/// [`STABLE_AFTER`].
+pub const MAX_QUICK_RESTARTS: u32 = 10;
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(30);
+/// How long to wait for the log pipes to drain after the process exits.
+/// Background grandchildren may hold the pipes open indefinitely.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Everything the daemon needs to start (and later restart) a service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpawnSpec {
+    pub workspace: String,
+    pub project: String,
+    pub service: String,
+    pub program: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    /// The complete environment of the child; the daemon's own
+    /// environment is not inherited.
+    pub env: HashMap<String, String>,
+    /// The environment of the `pm` command that asked for the spawn,
+    /// before pm's additions. The daemon keeps the latest one per project
+    /// as the base for services it starts itself (the control API).
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub shell_env: HashMap<String, String>,
+    pub log_path: PathBuf,
+    pub port: u16,
+    /// Original command line, recorded in `services.json`.
+    pub dev_cmd: String,
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Recorded in `services.json` for `pm stop`.
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout_secs: u64,
+    #[serde(default)]
+    pub path_prefix: Option<String>,
+    #[serde(default)]
+    pub strip_prefix: bool,
+    #[serde(default)]
+    pub access_log: bool,
+    #[serde(default)]
+    pub rewrite_host: bool,
+    /// Extra hostnames to route (see `ServiceDef::hostnames`).
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+    #[serde(default)]
+    pub wildcard: bool,
+    /// Restart on file changes under `cwd`.
+    #[serde(default)]
+    pub watch: Option<WatchConfig>,
+    /// `.gitignore` files whose patterns `watch` respects.
+    #[serde(default)]
+    pub ignore_files: Vec<PathBuf>,
+}
+
+fn default_stop_timeout() -> u64 {
+    crate::project::DEFAULT_STOP_TIMEOUT_SECS
+}
+
+impl SpawnSpec {
+    fn key(&self) -> String {
+        format!("{}/{}/{}", self.workspace, self.project, self.service)
+    }
+
+    fn route_options(&self) -> routes::RouteOptions {
+        routes::RouteOptions {
+            path_prefix: self.path_prefix.clone(),
+            strip_prefix: self.strip_prefix,
+            access_log: self.access_log,
+            rewrite_host: self.rewrite_host,
+            hostnames: self.hostnames.clone(),
+            wildcard: self.wildcard,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SpawnReply {
+    pub pid: u32,
+}
+
+/// Builds the process that copies one output stream of a service into its
+/// log file, reading the stream on stdin.
+pub type LoggerCommand = fn(Stream, &Path) -> Result<std::process::Command>;
+
+/// Owns one monitor task per supervised service.
+pub struct Supervisor {
+    monitors: Mutex<HashMap<String, JoinHandle<()>>>,
+    /// Latest [`SpawnSpec::shell_env`] per `workspace/project`.
+    shell_envs: Mutex<HashMap<String, HashMap<String, String>>>,
+    events: Events,
+    logger: LoggerCommand,
+}
+
+impl Supervisor {
+    /// A supervisor whose services log through `pm __log-pipe`.
+    pub fn new(events: Events) -> Self {
+        Self {
+            monitors: Mutex::new(HashMap::new()),
+            shell_envs: Mutex::new(HashMap::new()),
+            events,
+            logger: log_pipe_command,
+        }
+    }
+
+    /// Start `spec` and supervise it. Errors (e.g. the program does not
+    /// exist) are returned to the caller instead of being retried.
+    pub fn spawn(&self, spec: SpawnSpec) -> Result<u32> {
+        routes::check_available(&spec.workspace, &spec.project, &spec.service, &spec.route_options())?;
+        let running = start_child(&spec, self.logger)?;
+        let pid = running.child.id().context("spawned service has no pid")?;
+        record_running(&spec, pid, 0)?;
+        if !spec.shell_env.is_empty() {
+            self.shell_envs
+                .lock()
+                .unwrap()
+                .insert(format!("{}/{}", spec.workspace, spec.project), spec.shell_env.clone());
+        }
+        let key = spec.key();
+        self.events.emit(EventKind::ServiceSpawned {
+            service: key.clone(),
+            pid,
+            port: spec.port,
+        });
+        let handle = tokio::spawn(monitor(spec, self.logger, running, pid, self.events.clone()));
+        // A previous monitor for the same service can only be waiting out
+        // a backoff (a live process makes `pm run` a no-op); drop it.
+        if let Some(old) = self.monitors.lock().unwrap().insert(key, handle) {
+            old.abort();
+        }
+        Ok(pid)
+    }
+
+    /// The shell environment a project's services were last started from,
+    /// if any were started through this daemon.
+    pub fn shell_env(&self, workspace: &str, project: &str) -> Option<HashMap<String, String>> {
+        self.shell_envs.lock().unwrap().get(&format!("{workspace}/{project}")).cloned()
+    }
+}
+
+/// A started child and a task that ends when its log pipes have drained.
+struct Running {
+    child: Child,
+    output: JoinHandle<()>,
+}
+
+/// What woke a monitor up.
+enum Event {
+    Exited(std::io::Result<ExitStatus>),
+    Changed(Vec<PathBuf>),
+}
+
+async fn monitor(
+    spec: SpawnSpec,
+    logger: LoggerCommand,
+    mut running: Running,
+    mut pid: u32,
+    events: Events,
+) {
+    let mut watch = start_watch(&spec).await;
+    let mut restarts = 0u32;
+    let mut quick_exits = 0u32;
+    let mut started = Instant::now();
+    loop {
+        let event = tokio::select! {
+            status = running.child.wait() => Event::Exited(status),
+            Some(changed) = next_change(&mut watch) => Event::Changed(changed),
+        };
+
+        let reason = match event {
+            Event::Changed(changed) => {
+                if !still_owned(&spec, pid) {
+                    return;
+                }
+                let note = format!("{}; restarting", describe_changes(&changed));
+                eprintln!("pm-daemon: {} (pid {pid}) {note}", spec.key());
+                log_line::append_note(&spec.log_path, &note);
+                blocking(&spec, |spec| {
+                    let _ = svc_state::update(&spec.workspace, &spec.project, &spec.service, |s| {
+                        s.status = ServiceStatus::Restarting;
+                    });
+                })
+                .await;
+                stop_group(&mut running.child, pid, Duration::from_secs(spec.stop_timeout_secs))
+                    .await;
+                let _ = tokio::time::timeout(DRAIN_TIMEOUT, &mut running.output).await;
+                // A file-triggered restart is not a crash.
+                quick_exits = 0;
+                "file change".to_string()
+            }
+            Event::Exited(status) => {
+                let status = match status {
+                    Ok(status) => status,
+                    Err(e) => {
+                        eprintln!("pm-daemon: waiting on {} (pid {pid}): {e}", spec.key());
+                        return;
+                    }
+                };
+                // Let the last lines reach the log before the exit note.
+                let _ = tokio::time::timeout(DRAIN_TIMEOUT, &mut running.output).await;
+                if !still_owned(&spec, pid) {
+                    // Stopped on purpose (`pm stop`).
+                    events.emit(EventKind::ServiceExited {
+                        service: spec.key(),
+                        pid,
+                        exit: describe_exit(status),
+                        restarting: false,
+                    });
+                    return;
+                }
+
+                quick_exits = if started.elapsed() < STABLE_AFTER { quick_exits + 1 } else { 0 };
+                let wanted = spec.restart.should_restart(status.success());
+                let restart = wanted && quick_exits <= MAX_QUICK_RESTARTS;
+                let delay = backoff(quick_exits);
+                blocking(&spec, move |spec| record_exit(spec, status, restart)).await;
+
+                let reason = describe_exit(status);
+                let note = if restart {
+                    format!("{reason}; restarting in {}ms", delay.as_millis())
+                } else if wanted {
+                    format!("{reason}; crashed {quick_exits} times in a row, giving up")
+                } else {
+                    format!("{reason}; restart policy is '{}'", spec.restart.as_str())
+                };
+                eprintln!("pm-daemon: {} (pid {pid}) {note}", spec.key());
+                log_line::append_note(&spec.log_path, &format!("process {note}"));
+                events.emit(EventKind::ServiceExited {
+                    service: spec.key(),
+                    pid,
+                    exit: reason.clone(),
+                    restarting: restart,
+                });
+
+                let reason = if restart {
+                    tokio::time::sleep(delay).await;
+                    restarts += 1;
+                    reason
+                } else {
+                    // A watched service that crashed (say, on a compile
+                    // error) comes back with the next edit.
+                    if watch.is_none() {
+                        return;
+                    }
+                    log_line::append_note(&spec.log_path, "waiting for file changes");
+                    let Some(changed) = next_change(&mut watch).await else { return };
+                    if still_owned(&spec, pid) {
+                        log_line::append_note(
+                            &spec.log_path,
+                            &format!("{}; restarting", describe_changes(&changed)),
+                        );
+                    }
+                    quick_exits = 0;
+                    "file change".to_string()
+                };
+                // `pm stop` in the meantime removes the entry; honour it.
+                if !still_owned(&spec, pid) {
+                    return;
+                }
+                reason
+            }
+        };
+
+        running = match start_child(&spec, logger) {
+            Ok(running) => running,
+            Err(e) => {
+                eprintln!("pm-daemon: restarting {}: {e:#}", spec.key());
+                log_line::append_note(&spec.log_path, &format!("restart failed: {e:#}"));
+                blocking(&spec, mark_exited).await;
+                return;
+            }
+        };
+        pid = running.child.id().unwrap_or_default();
+        started = Instant::now();
+        if let Err(e) = blocking(&spec, move |spec| record_running(spec, pid, restarts)).await {
+            eprintln!("pm-daemon: recording restart of {}: {e:#}", spec.key());
+        }
+        events.emit(EventKind::ServiceRestarted {
+            service: spec.key(),
+            pid,
+            reason,
+        });
+    }
+}
+
+async fn start_watch(spec: &SpawnSpec) -> Option<Watch> {
+    let config = spec.watch.clone()?;
+    let (root, ignore_files) = (spec.cwd.clone(), spec.ignore_files.clone());
+    // Walking the tree and adding a watch per directory is blocking work
+    // that grows with the repo; keep it off the runtime's workers.
+    let started =
+        tokio::task::spawn_blocking(move || Watch::start(&root, &config, &ignore_files)).await;
+    match started.unwrap_or_else(|e| Err(anyhow::anyhow!("watcher setup panicked: {e}"))) {
+        Ok(watch) => Some(watch),
+        Err(e) => {
+            eprintln!("pm-daemon: watching files for {}: {e:#}", spec.key());
+            log_line::append_note(&spec.log_path, &format!("file watching disabled: {e:#}"));
+            None
+        }
+    }
+}
+
+/// The next burst of changes; never resolves for unwatched services.
+async fn next_change(watch: &mut Option<Watch>) -> Option<Vec<PathBuf>> {
+    match watch {
+        Some(watch) => watch.next_change().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn describe_changes(changed: &[PathBuf]) -> String {
+    match changed {
+        [] => "files changed".to_string(),
+        [one] => format!("{} changed", one.display()),
+        [first, rest @ ..] => format!(
+            "{} and {} more file{} changed",
+            first.display(),
+            rest.len(),
+            if rest.len() == 1 { "" } else { "s" }
+        ),
+    }
+}
+
+/// SIGTERM the service's process group and give it `timeout` to exit
+/// (leader and stragglers alike) before SIGKILL.
+async fn stop_group(child: &mut Child, pid: u32, timeout: Duration) {
+    use nix::sys::signal::{killpg, Signal};
+    use nix::unistd::Pid;
+
+    let group = Pid::from_raw(pid as i32);
+    let deadline = tokio::time::Instant::now() + timeout;
+    let _ = killpg(group, Signal::SIGTERM);
+    if tokio::time::timeout_at(deadline, child.wait()).await.is_err() {
+        let _ = killpg(group, Signal::SIGKILL);
+        let _ = child.wait().await;
+    }
+    while killpg(group, None).is_ok() {
+        if tokio::time::Instant::now() >= deadline {
+            let _ = killpg(group, Signal::SIGKILL);
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+fn start_child(spec: &SpawnSpec, logger: LoggerCommand) -> Result<Running> {
+    let _ = crate::log_rotation::rotate_if_needed(
+        &spec.log_path,
+        crate::log_rotation::MAX_BYTES,
+        crate::log_rotation::KEEP,
+    );
+    // The write ends go to the child; the read ends only to the loggers,
+    // so the daemon holds neither once both are spawned.
+    let (out_read, out_write) = std::io::pipe().context("creating stdout pipe")?;
+    let (err_read, err_write) = std::io::pipe().context("creating stderr pipe")?;
+    let loggers = [
+        spawn_logger(logger, Stream::Stdout, out_read, &spec.log_path)?,
+        spawn_logger(logger, Stream::Stderr, err_read, &spec.log_path)?,
+    ];
+
+    let mut cmd = Command::new(&spec.program);
+    cmd.args(&spec.args)
+        .current_dir(&spec.cwd)
+        .env_clear()
+        .envs(&spec.env)
+        .stdin(Stdio::null())
+        .stdout(out_write)
+        .stderr(err_write);
+    unsafe {
+        cmd.pre_exec(|| {
+            // Own session: the service survives a daemon restart and can
+            // be signalled as a group.
+            nix::unistd::setsid()
+                .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+            Ok(())
+        });
+    }
+    let child = cmd.spawn().with_context(|| {
+        format!("spawning service '{}' (cmd: {})", spec.service, spec.dev_cmd)
+    })?;
+    // Drop our copies of the write ends, or the loggers never see EOF.
+    drop(cmd);
+    let output = tokio::spawn(async move {
+        for mut logger in loggers {
+            let _ = logger.wait().await;
+        }
+    });
+    Ok(Running { child, output })
+}
+
+/// Start a `logger` process copying `pipe` into the log. It gets a
+/// session of its own, so neither the daemon's exit nor signals to the
+/// service's process group end it; it exits at end of input.
+fn spawn_logger(
+    logger: LoggerCommand,
+    stream: Stream,
+    pipe: std::io::PipeReader,
+    log_path: &Path,
+) -> Result<Child> {
+    let mut cmd = logger(stream, log_path)?;
+    cmd.stdin(pipe).stdout(Stdio::null()).stderr(Stdio::null());
+    unsafe {
+        cmd.pre_exec(|| {
+            nix::unistd::setsid()
+                .map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+            Ok(())
+        });
+    }
+    Command::from(cmd)
+        .spawn()
+        .with_context(|| format!("starting the {} logger for {}", stream.as_str(), log_path.display()))
+}
+
+/// `pm __log-pipe <stream> <log>`, run from this executable.
+fn log_pipe_command(stream: Stream, log_path: &Path) -> Result<std::process::Command> {
+    let exe = std::env::current_exe().context("locating current exe")?;
+    let mut cmd = std::process::Command::new(exe);
+    cmd.arg("__log-pipe").arg(stream.as_str()).arg(log_path);
+    Ok(cmd)
+}
+
+/// Run `f` on the blocking pool: updating `services.json` and
+/// `routes.json` can wait up to [`crate::lock::LOCK_TIMEOUT`] for another
+/// pm process, which must not stall a runtime worker.
+async fn blocking<T: Send + 'static>(
+    spec: &SpawnSpec,
+    f: impl FnOnce(&SpawnSpec) -> T + Send + 'static,
+) -> T {
+    let spec = spec.clone();
+    match tokio::task::spawn_blocking(move || f(&spec)).await {
+        Ok(out) => out,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// Whether `services.json` still attributes this service to `pid`.
+fn still_owned(spec: &SpawnSpec, pid: u32) -> bool {
+    matches!(
+        svc_state::get(&spec.workspace, &spec.project, &spec.service),
+        Ok(Some(state)) if state.pid == pid
+    )
+}
+
+fn record_running(spec: &SpawnSpec, pid: u32, restart_count: u32) -> Result<()> {
+    let previous = svc_state::get(&spec.workspace, &spec.project, &spec.service)?
+        .filter(|_| restart_count > 0);
+    svc_state::upsert(
+        &spec.workspace,
+        &spec.project,
+        &spec.service,
+        ServiceState {
+            pid,
+            port: spec.port,
+            started_at: Utc::now(),
+            log_path: spec.log_path.clone(),
+            dev_cmd: spec.dev_cmd.clone(),
+            status: ServiceStatus::Running,
+            restart_count,
+            last_exit_code: previous.as_ref().and_then(|s| s.last_exit_code),
+            last_exit_signal: previous.as_ref().and_then(|s| s.last_exit_signal),
+            stop_timeout_secs: Some(spec.stop_timeout_secs),
+        },
+    )?;
+    routes::register_service(
+        &spec.workspace,
+        &spec.project,
+        &spec.service,
+        spec.port,
+        &spec.route_options(),
+    )
+}
+
+fn record_exit(spec: &SpawnSpec, status: ExitStatus, restarting: bool) {
+    let result = svc_state::update(&spec.workspace, &spec.project, &spec.service, |state| {
+        state.status = if restarting {
+            ServiceStatus::Restarting
+        } else {
+            ServiceStatus::Exited
+        };
+        state.last_exit_code = status.code();
+        state.last_exit_signal = status.signal();
+    });
+    if let Err(e) = result {
+        eprintln!("pm-daemon: recording exit of {}: {e:#}", spec.key());
+    }
+    if !restarting {
+        let _ = routes::unregister_service(&spec.workspace, &spec.project, &spec.service);
+    }
+}
+
+fn mark_exited(spec: &SpawnSpec) {
+    let _ = svc_state::update(&spec.workspace, &spec.project, &spec.service, |state| {
+        state.status = ServiceStatus::Exited;
+    });
+    let _ = routes::unregister_service(&spec.workspace, &spec.project, &spec.service);
+}
+
+fn describe_exit(status: ExitStatus) -> String {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => format!("exited with code {code}"),
+        (None, Some(sig)) => format!("was killed by signal {sig}"),
+        (None, None) => "exited".to_string(),
+    }
+}
+
+/// Delay before the `n`th consecutive quick restart: 0.5s, 1s, 2s, …
+/// capped at 30s.
+fn backoff(n: u32) -> Duration {
+    let factor = 1u32 << n.saturating_sub(1).min(16);
+    BACKOFF_BASE.saturating_mul(factor).min(BACKOFF_MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        assert_eq!(backoff(0), Duration::from_millis(500));
+        assert_eq!(backoff(1), Duration::from_millis(500));
+        assert_eq!(backoff(2), Duration::from_secs(1));
+        assert_eq!(backoff(4), Duration::from_secs(4));
+        assert_eq!(backoff(7), Duration::from_secs(30));
+        assert_eq!(backoff(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn spawn_spec_defaults_optional_fields() {
+        let json = r#"{"workspace":"default","project":"api","service":"back",
+            "program":"cargo","cwd":"/tmp","env":{},"log_path":"/tmp/x.log",
+            "port":3000,"dev_cmd":"cargo run"}"#;
+        let spec: SpawnSpec = serde_json::from_str(json).unwrap();
+        assert!(spec.args.is_empty());
+        assert_eq!(spec.restart, RestartPolicy::Never);
+        assert_eq!(spec.stop_timeout_secs, crate::project::DEFAULT_STOP_TIMEOUT_SECS);
+        assert_eq!(spec.key(), "default/api/back");
+        assert!(spec.watch.is_none());
+    }
+
+    #[test]
+    fn describes_changed_files() {
+        let paths = |names: &[&str]| names.iter().map(PathBuf::from).collect::<Vec<_>>();
+        assert_eq!(describe_changes(&paths(&["src/main.rs"])), "src/main.rs changed");
+        assert_eq!(
+            describe_changes(&paths(&["a.rs", "b.rs"])),
+            "a.rs and 1 more file changed"
+        );
+        assert_eq!(
+            describe_changes(&paths(&["a.rs", "b.rs", "c.rs"])),
+            "a.rs and 2 more files changed"
+        );
+    }
+
+    fn sh_spec(dir: &Path, script: &str) -> SpawnSpec {
+        SpawnSpec {
+            workspace: "default".into(),
+            project: "api".into(),
+            service: "back".into(),
+            program: "/bin/sh".into(),
+            args: vec!["-c".into(), script.into()],
+            cwd: dir.to_path_buf(),
+            env: HashMap::new(),
+            shell_env: HashMap::new(),
+            log_path: dir.join("svc.log"),
+            port: 3000,
+            dev_cmd: "sh".into(),
+            restart: RestartPolicy::Never,
+            stop_timeout_secs: 10,
+            path_prefix: None,
+            strip_prefix: false,
+            access_log: false,
+            rewrite_host: false,
+            hostnames: Vec::new(),
+            wildcard: false,
+            watch: None,
+            ignore_files: Vec::new(),
+        }
+    }
+
+    /// Stand-in for `pm __log-pipe`, which the test binary does not have:
+    /// re-enter the harness and run just [`log_pipe_entry`].
+    fn test_logger(stream: Stream, log_path: &Path) -> Result<std::process::Command> {
+        let mut cmd = std::process::Command::new(std::env::current_exe()?);
+        cmd.args(["--exact", "commands::proxy::supervisor::tests::log_pipe_entry", "--test-threads=1"])
+            .env("PM_TEST_LOG_PIPE_STREAM", stream.as_str())
+            .env("PM_TEST_LOG_PIPE_PATH", log_path);
+        Ok(cmd)
+    }
+
+    /// The body of [`test_logger`]'s processes; a no-op otherwise.
+    #[test]
+    fn log_pipe_entry() {
+        let (Ok(stream), Ok(path)) = (
+            std::env::var("PM_TEST_LOG_PIPE_STREAM"),
+            std::env::var("PM_TEST_LOG_PIPE_PATH"),
+        ) else {
+            return;
+        };
+        let failed = log_line::run_pipe(&stream, Path::new(&path)).is_err();
+        std::process::exit(i32::from(failed));
+    }
+
+    #[tokio::test]
+    async fn start_child_uses_only_the_given_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut spec = sh_spec(
+            dir.path(),
+            "echo \"$PM_TEST_VAR:${HOME:-unset}\"; echo oops >&2; exit 3",
+        );
+        spec.env = HashMap::from([("PM_TEST_VAR".to_string(), "hello".to_string())]);
+        let mut running = start_child(&spec, test_logger).unwrap();
+        let status = running.child.wait().await.unwrap();
+        running.output.await.unwrap();
+        assert_eq!(status.code(), Some(3));
+        assert_eq!(describe_exit(status), "exited with code 3");
+
+        let log = std::fs::read_to_string(&spec.log_path).unwrap();
+        let mut lines: Vec<_> = log.lines().map(|l| log_line::parse(l).unwrap()).collect();
+        lines.sort_by_key(|l| l.stream.as_str());
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0].stream, lines[0].text), (Stream::Stderr, "oops"));
+        assert_eq!((lines[1].stream, lines[1].text), (Stream::Stdout, "hello:unset"));
+    }
+
+    /// A daemon restart (here: the runtime that spawned the service shutting
+    /// down) must leave the service running and its output reaching the log.
+    #[test]
+    fn service_keeps_logging_after_the_daemon_exits() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = sh_spec(
+            dir.path(),
+            "i=0; while [ $i -lt 200 ]; do echo tick $i; echo tock $i >&2; i=$((i+1)); sleep 0.02; done",
+        );
+        let count_lines = || {
+            std::fs::read_to_string(&spec.log_path)
+                .map(|log| log.lines().count())
+                .unwrap_or(0)
+        };
+
+        let daemon = tokio::runtime::Runtime::new().unwrap();
+        let pid = daemon.block_on(async {
+            let running = start_child(&spec, test_logger).unwrap();
+            running.child.id().unwrap()
+        });
+        std::thread::sleep(Duration::from_millis(200));
+        daemon.shutdown_timeout(Duration::from_secs(1));
+        let at_exit = count_lines();
+        assert!(at_exit > 0);
+
+        std::thread::sleep(Duration::from_millis(500));
+        let group = nix::unistd::Pid::from_raw(pid as i32);
+        let alive = nix::sys::signal::killpg(group, None).is_ok();
+        let _ = nix::sys::signal::killpg(group, nix::sys::signal::Signal::SIGKILL);
+        assert!(alive, "the service died with the daemon");
+        let later = count_lines();
+        assert!(later > at_exit + 10, "log stopped growing: {at_exit} -> {later}");
+        let log = std::fs::read_to_string(&spec.log_path).unwrap();
+        assert!(log.lines().all(|l| log_line::parse(l).is_some()), "{log}");
+        assert!(log.contains(" err tock "));
+    }
+}
@@ -0,0 +1,410 @@
+//! Local HTTPS termination for `*.localhost` routes.
+//!
+//! The daemon can optionally listen on `dev.https_port` with TLS. Certificates
+//! come from a local root CA that pm generates on first use and stores under
+//! `~/.config/pm/tls/`:
+//!
+//! ```text
+//! tls/ca.pem      root certificate (safe to share / import into trust stores)
+//! tls/ca-key.pem  root private key (0600, never leaves the machine)
+//! ```
+//!
+//! Leaf certificates are minted in memory on demand, one per SNI hostname,
+//! and only for hostnames the proxy currently routes. Nothing is fetched from
+//! or sent to the network: the user opts in to trusting the CA via
+//! `pm proxy trust`.
+
+use crate::config::tls_dir;
+use crate::lock;
+use super::reverse::Proxy;
+use anyhow::{Context, Result};
+use chrono::{Datelike, Duration as ChronoDuration, Utc};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType,
+    ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose,
+};
+use rustls::ServerConfig;
+use rustls::crypto::ring as ring_provider;
+use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const CA_COMMON_NAME: &str = "pm local development CA";
+const CA_ORGANIZATION: &str = "pm (local only)";
+/// Leaf lifetime. Kept under the 398-day ceiling browsers apply to TLS
+/// server certificates.
+const LEAF_VALIDITY_DAYS: i64 = 397;
+const CA_VALIDITY_DAYS: i64 = 3650;
+
+/// The on-disk root CA, loaded into memory for signing leaves.
+pub struct LocalCa {
+    key: KeyPair,
+    /// Re-derived from `key` and the fixed CA subject. Leaves only embed the
+    /// issuer's name and key identifier, so they chain to `ca.pem` on disk.
+    cert: Certificate,
+    cert_pem: String,
+}
+
+impl LocalCa {
+    /// Load the CA from `~/.config/pm/tls/`, generating it on first use.
+    pub fn load_or_create() -> Result<Self> {
+        Self::load_or_create_in(&tls_dir())
+    }
+
+    /// Creation happens under the key file's lock, so concurrent first runs
+    /// agree on one CA instead of interleaving two key/cert pairs.
+    pub fn load_or_create_in(dir: &Path) -> Result<Self> {
+        let cert_path = ca_cert_path_in(dir);
+        let key_path = ca_key_path_in(dir);
+        if let Some(ca) = Self::load_in(&cert_path, &key_path)? {
+            return Ok(ca);
+        }
+
+        fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+        let _lock = lock::lock(&key_path)?;
+        // Another process may have created it while we waited for the lock.
+        if let Some(ca) = Self::load_in(&cert_path, &key_path)? {
+            return Ok(ca);
+        }
+        // A key without its certificate keeps its identity: re-issue the
+        // certificate rather than replacing a key the user may already trust.
+        let key = if key_path.exists() {
+            read_key(&key_path)?
+        } else {
+            let key = KeyPair::generate()?;
+            lock::write_atomic_private(&key_path, &key.serialize_pem())?;
+            key
+        };
+        let mut params = ca_params();
+        set_validity(&mut params, CA_VALIDITY_DAYS);
+        let cert = params.self_signed(&key)?;
+        let cert_pem = cert.pem();
+        lock::write_atomic(&cert_path, &cert_pem)?;
+        Ok(Self {
+            key,
+            cert,
+            cert_pem,
+        })
+    }
+
+    /// The CA on disk, if both halves of it are there.
+    fn load_in(cert_path: &Path, key_path: &Path) -> Result<Option<Self>> {
+        if !(cert_path.exists() && key_path.exists()) {
+            return Ok(None);
+        }
+        let key = read_key(key_path)?;
+        let cert_pem = fs::read_to_string(cert_path)
+            .with_context(|| format!("reading {}", cert_path.display()))?;
+        let cert = ca_params().self_signed(&key)?;
+        Ok(Some(Self {
+            key,
+            cert,
+            cert_pem,
+        }))
+    }
+
+    pub fn cert_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /// Mint a leaf certificate for a single hostname, signed by this CA.
+    pub fn issue(&self, hostname: &str) -> Result<CertifiedKey> {
+        let leaf_key = KeyPair::generate()?;
+        let mut params = CertificateParams::new(vec![hostname.to_string()])?;
+        let mut dn = DistinguishedName::new();
+        dn.push(DnType::CommonName, hostname);
+        params.distinguished_name = dn;
+        params.is_ca = IsCa::ExplicitNoCa;
+        params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+        params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+        params.use_authority_key_identifier_extension = true;
+        set_validity(&mut params, LEAF_VALIDITY_DAYS);
+        let leaf = params.signed_by(&leaf_key, &self.cert, &self.key)?;
+
+        let key_der = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(leaf_key.serialize_der()));
+        let signing_key = ring_provider::sign::any_supported_type(&key_der)
+            .map_err(|e| anyhow::anyhow!("loading leaf key for {hostname}: {e}"))?;
+        Ok(CertifiedKey::new(vec![leaf.der().clone()], signing_key))
+    }
+}
+
+fn ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    let mut dn = DistinguishedName::new();
+    dn.push(DnType::CommonName, CA_COMMON_NAME);
+    dn.push(DnType::OrganizationName, CA_ORGANIZATION);
+    params.distinguished_name = dn;
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params
+}
+
+/// Valid from yesterday (tolerates clock skew) for `days` days.
+fn set_validity(params: &mut CertificateParams, days: i64) {
+    let from = Utc::now() - ChronoDuration::days(1);
+    let until = from + ChronoDuration::days(days);
+    params.not_before = rcgen::date_time_ymd(from.year(), from.month() as u8, from.day() as u8);
+    params.not_after = rcgen::date_time_ymd(until.year(), until.month() as u8, until.day() as u8);
+}
+
+fn read_key(path: &Path) -> Result<KeyPair> {
+    let pem = fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    KeyPair::from_pem(&pem).with_context(|| format!("parsing {}", path.display()))
+}
+
+pub fn ca_cert_path() -> PathBuf {
+    ca_cert_path_in(&tls_dir())
+}
+
+fn ca_cert_path_in(dir: &Path) -> PathBuf {
+    dir.join("ca.pem")
+}
+
+fn ca_key_path_in(dir: &Path) -> PathBuf {
+    dir.join("ca-key.pem")
+}
+
+// ── SNI certificate selection ──
+
+/// Chooses (and lazily mints) the leaf certificate for the SNI hostname of
+/// each handshake. Leaves are cached for the daemon's lifetime.
+pub struct SniResolver {
+    ca: LocalCa,
+    cache: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+    allow: Box<dyn Fn(&str) -> bool + Send + Sync>,
+}
+
+impl std::fmt::Debug for SniResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SniResolver").finish_non_exhaustive()
+    }
+}
+
+impl SniResolver {
+    pub fn new(ca: LocalCa, allow: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            ca,
+            cache: Mutex::new(HashMap::new()),
+            allow: Box::new(allow),
+        }
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let hostname = client_hello.server_name()?.to_ascii_lowercase();
+        let mut cache = self.cache.lock().ok()?;
+        if let Some(key) = cache.get(&hostname) {
+            return Some(key.clone());
+        }
+        if !(self.allow)(&hostname) {
+            eprintln!("pm-daemon: TLS: no route for SNI '{hostname}', refusing handshake");
+            return None;
+        }
+        match self.ca.issue(&hostname) {
+            Ok(key) => {
+                let key = Arc::new(key);
+                cache.insert(hostname, key.clone());
+                Some(key)
+            }
+            Err(e) => {
+                eprintln!("pm-daemon: TLS: issuing certificate for '{hostname}' failed: {e}");
+                None
+            }
+        }
+    }
+}
+
+/// Only hostnames under `.localhost` that the proxy currently routes
+/// (exactly or through a wildcard) get a certificate, plus `pm.localhost`
+/// for the route index.
+fn hostname_is_routed(hostname: &str, serves_host: impl Fn(&str) -> bool) -> bool {
+    if hostname == "pm.localhost" {
+        return true;
+    }
+    hostname.ends_with(".localhost") && serves_host(hostname)
+}
+
+/// Build the rustls server config used by the daemon's HTTPS listener.
+/// Hostnames are checked against the proxy's in-memory route table, which
+/// the routes watcher keeps current.
+pub fn server_config(ca: LocalCa, proxy: Arc<Proxy>) -> Result<Arc<ServerConfig>> {
+    build_server_config(SniResolver::new(ca, move |hostname| {
+        hostname_is_routed(hostname, |h| proxy.serves_host(h))
+    }))
+}
+
+fn build_server_config(resolver: SniResolver) -> Result<Arc<ServerConfig>> {
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring_provider::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(resolver));
+    // The proxy speaks HTTP/1.1 only.
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+// ── CLI: `pm proxy trust` ──
+
+/// Print (or export) the root CA so the user can add it to their trust
+/// store. pm never modifies system trust stores itself.
+pub fn cmd_trust(export: Option<String>) -> Result<()> {
+    let ca = LocalCa::load_or_create()?;
+    match export {
+        Some(target) => {
+            let target = crate::path::expand_path(&target);
+            fs::write(&target, ca.cert_pem())
+                .with_context(|| format!("writing {}", target.display()))?;
+            eprintln!("exported pm local CA to {}", target.display());
+        }
+        None => print!("{}", ca.cert_pem()),
+    }
+
+    let path = ca_cert_path();
+    eprintln!();
+    eprintln!("CA certificate: {}", path.display());
+    eprintln!("Trust it (once) to get green padlocks on https://*.localhost:");
+    eprintln!(
+        "  macOS:  sudo security add-trusted-cert -d -r trustRoot \
+         -k /Library/Keychains/System.keychain {}",
+        path.display()
+    );
+    eprintln!(
+        "  Linux:  sudo cp {} /usr/local/share/ca-certificates/pm-local-ca.crt \
+         && sudo update-ca-certificates",
+        path.display()
+    );
+    eprintln!("  Firefox keeps its own store: Settings → Certificates → Import.");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::RootCertStore;
+    use rustls::pki_types::ServerName;
+    use tempfile::TempDir;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn ca_is_persisted_and_reloaded() {
+        let dir = TempDir::new().unwrap();
+        let first = LocalCa::load_or_create_in(dir.path()).unwrap();
+        assert!(ca_cert_path_in(dir.path()).exists());
+        let key_meta = fs::metadata(ca_key_path_in(dir.path())).unwrap();
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(key_meta.permissions().mode() & 0o777, 0o600);
+
+        let second = LocalCa::load_or_create_in(dir.path()).unwrap();
+        assert_eq!(first.cert_pem(), second.cert_pem());
+        assert_eq!(first.key.serialize_der(), second.key.serialize_der());
+    }
+
+    #[test]
+    fn missing_certificate_is_reissued_for_the_existing_key() {
+        let dir = TempDir::new().unwrap();
+        let first = LocalCa::load_or_create_in(dir.path()).unwrap();
+        fs::remove_file(ca_cert_path_in(dir.path())).unwrap();
+
+        let second = LocalCa::load_or_create_in(dir.path()).unwrap();
+        assert_eq!(first.key.serialize_der(), second.key.serialize_der());
+        assert!(ca_cert_path_in(dir.path()).exists());
+    }
+
+    #[test]
+    fn concurrent_first_runs_agree_on_one_ca() {
+        const RUNS: usize = 4;
+        let dir = TempDir::new().unwrap();
+        let barrier = Arc::new(std::sync::Barrier::new(RUNS));
+        let runs: Vec<_> = (0..RUNS)
+            .map(|_| {
+                let (dir, barrier) = (dir.path().to_path_buf(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    LocalCa::load_or_create_in(&dir).unwrap().key.serialize_der()
+                })
+            })
+            .collect();
+        let keys: Vec<_> = runs.into_iter().map(|r| r.join().unwrap()).collect();
+        assert!(keys.iter().all(|k| *k == keys[0]));
+
+        let on_disk = LocalCa::load_or_create_in(dir.path()).unwrap();
+        assert_eq!(on_disk.key.serialize_der(), keys[0]);
+    }
+
+    async fn handshake(
+        ca: &LocalCa,
+        server_config: Arc<ServerConfig>,
+        sni: &'static str,
+    ) -> std::io::Result<Vec<u8>> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let acceptor = tokio_rustls::TlsAcceptor::from(server_config);
+            if let Ok(mut tls) = acceptor.accept(stream).await {
+                let _ = tls.write_all(b"hello").await;
+                let _ = tls.shutdown().await;
+            }
+        });
+
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.cert.der().clone()).unwrap();
+        let client_config =
+            rustls::ClientConfig::builder_with_provider(Arc::new(ring_provider::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let stream = tokio::net::TcpStream::connect(addr).await?;
+        let mut tls = connector
+            .connect(ServerName::try_from(sni).unwrap(), stream)
+            .await?;
+        let mut buf = Vec::new();
+        tls.read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+
+    #[tokio::test]
+    async fn leaf_for_sni_hostname_chains_to_local_ca() {
+        let dir = TempDir::new().unwrap();
+        let ca = LocalCa::load_or_create_in(dir.path()).unwrap();
+        // A second handle on the same on-disk CA signs the leaves, as the
+        // daemon and the CLI do in practice.
+        let signer = LocalCa::load_or_create_in(dir.path()).unwrap();
+        let config = build_server_config(SniResolver::new(signer, |h| {
+            h == "front.blog.localhost"
+        }))
+        .unwrap();
+
+        let body = handshake(&ca, config, "front.blog.localhost").await.unwrap();
+        assert_eq!(body, b"hello");
+    }
+
+    #[tokio::test]
+    async fn unrouted_sni_hostname_is_refused() {
+        let dir = TempDir::new().unwrap();
+        let ca = LocalCa::load_or_create_in(dir.path()).unwrap();
+        let signer = LocalCa::load_or_create_in(dir.path()).unwrap();
+        let config = build_server_config(SniResolver::new(signer, |_| false)).unwrap();
+
+        assert!(handshake(&ca, config, "evil.localhost").await.is_err());
+    }
+
+    #[test]
+    fn only_localhost_names_are_considered_routed() {
+        assert!(!hostname_is_routed("example.com", |_| true));
+        assert!(!hostname_is_routed("evil.localhost", |_| false));
+        assert!(hostname_is_routed("front.blog.localhost", |_| true));
+        assert!(hostname_is_routed("pm.localhost", |_| false));
+    }
+}
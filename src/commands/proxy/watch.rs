@@ -0,0 +1,217 @@
+//! File watching for services with `watch:`.
+//!
+//! Directories under the service `dir` are watched one by one (not
+//! recursively), skipping everything `.gitignore` excludes, so
+//! `node_modules/` or `target/` never cost an inotify watch. Directories
+//! created later are picked up as their events arrive.
+//!
+//! A change counts when a file is created, written, renamed or removed,
+//! matches `include` (if any), does not match `exclude`, and is not
+//! ignored by the project's or the service's `.gitignore`. Changes are
+//! debounced: [`Watch::next_change`] returns once no further change has
+//! arrived for `debounce_ms`.
+
+use crate::project::WatchConfig;
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+pub struct Watch {
+    watcher: RecommendedWatcher,
+    events: UnboundedReceiver<PathBuf>,
+    filter: Filter,
+    debounce: Duration,
+}
+
+impl Watch {
+    /// Start watching `root`. `ignore_files` are `.gitignore` files whose
+    /// patterns apply relative to their own directory.
+    pub fn start(root: &Path, config: &WatchConfig, ignore_files: &[PathBuf]) -> Result<Self> {
+        let filter = Filter::new(root, config, ignore_files)?;
+        let (tx, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let Ok(event) = res else { return };
+            let relevant = match event.kind {
+                EventKind::Create(_) | EventKind::Remove(_) => true,
+                EventKind::Modify(kind) => !matches!(kind, ModifyKind::Metadata(_)),
+                _ => false,
+            };
+            if relevant {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+        })
+        .context("starting file watcher")?;
+
+        let walker = ignore::WalkBuilder::new(root)
+            .hidden(false)
+            .require_git(false)
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .build();
+        for entry in walker.flatten() {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            if is_dir && filter.accepts(entry.path(), true) {
+                watcher
+                    .watch(entry.path(), RecursiveMode::NonRecursive)
+                    .with_context(|| format!("watching {}", entry.path().display()))?;
+            }
+        }
+
+        Ok(Self {
+            watcher,
+            events,
+            filter,
+            debounce: Duration::from_millis(config.debounce_ms),
+        })
+    }
+
+    /// Wait for the next burst of relevant changes and return the changed
+    /// paths (relative to the root, sorted). `None` if the watcher stopped.
+    pub async fn next_change(&mut self) -> Option<Vec<PathBuf>> {
+        let mut changed = BTreeSet::new();
+        loop {
+            let path = if changed.is_empty() {
+                self.events.recv().await?
+            } else {
+                match tokio::time::timeout(self.debounce, self.events.recv()).await {
+                    Ok(Some(path)) => path,
+                    Ok(None) => return None,
+                    Err(_) => return Some(changed.into_iter().collect()),
+                }
+            };
+            if path.is_dir() {
+                if self.filter.accepts(&path, true) {
+                    let _ = self.watcher.watch(&path, RecursiveMode::NonRecursive);
+                }
+                continue;
+            }
+            if self.filter.accepts(&path, false)
+                && let Ok(relative) = path.strip_prefix(&self.filter.root)
+            {
+                changed.insert(relative.to_path_buf());
+            }
+        }
+    }
+}
+
+struct Filter {
+    root: PathBuf,
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    gitignores: Vec<Gitignore>,
+}
+
+impl Filter {
+    fn new(root: &Path, config: &WatchConfig, ignore_files: &[PathBuf]) -> Result<Self> {
+        let include = if config.include.is_empty() {
+            None
+        } else {
+            Some(glob_set(&config.include)?)
+        };
+        let mut gitignores = Vec::new();
+        for file in ignore_files.iter().filter(|f| f.exists()) {
+            let base = file.parent().unwrap_or(root);
+            let mut builder = GitignoreBuilder::new(base);
+            if let Some(err) = builder.add(file) {
+                return Err(err).with_context(|| format!("reading {}", file.display()));
+            }
+            gitignores.push(builder.build()?);
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            include,
+            exclude: glob_set(&config.exclude)?,
+            gitignores,
+        })
+    }
+
+    /// Whether `path` may trigger a restart (files) or should be watched
+    /// (directories, which `include` does not apply to).
+    fn accepts(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.components().any(|c| c.as_os_str() == ".git") {
+            return false;
+        }
+        let ignored = self.gitignores.iter().any(|gi| {
+            path.starts_with(gi.path())
+                && gi.matched_path_or_any_parents(path, is_dir).is_ignore()
+        });
+        if ignored || self.exclude.is_match(relative) {
+            return false;
+        }
+        is_dir || self.include.as_ref().is_none_or(|set| set.is_match(relative))
+    }
+}
+
+fn glob_set(globs: &[String]) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob).with_context(|| format!("invalid watch glob '{glob}'"))?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(include: &[&str], exclude: &[&str]) -> WatchConfig {
+        WatchConfig {
+            include: include.iter().map(|s| s.to_string()).collect(),
+            exclude: exclude.iter().map(|s| s.to_string()).collect(),
+            debounce_ms: 50,
+        }
+    }
+
+    #[test]
+    fn filter_applies_globs_and_gitignore() {
+        let project = tempfile::tempdir().unwrap();
+        std::fs::write(project.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        let root = project.path().join("backend");
+        let ignore_files = [project.path().join(".gitignore")];
+        let filter =
+            Filter::new(&root, &config(&["src/**", "Cargo.toml"], &["**/*.snap"]), &ignore_files)
+                .unwrap();
+
+        assert!(filter.accepts(&root.join("src/main.rs"), false));
+        assert!(filter.accepts(&root.join("Cargo.toml"), false));
+        assert!(!filter.accepts(&root.join("README.md"), false), "not included");
+        assert!(!filter.accepts(&root.join("src/x.snap"), false), "excluded");
+        assert!(!filter.accepts(&root.join("src/debug.log"), false), "gitignored file");
+        assert!(!filter.accepts(&root.join("target/debug/app"), false), "gitignored dir");
+        assert!(!filter.accepts(&root.join("target"), true));
+        assert!(filter.accepts(&root.join("docs"), true), "include does not apply to dirs");
+        assert!(!filter.accepts(&root.join(".git/index"), false));
+        assert!(!filter.accepts(project.path().join("other/x.rs").as_path(), false));
+    }
+
+    #[tokio::test]
+    async fn reports_debounced_changes_to_matching_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join(".gitignore"), "*.tmp\n").unwrap();
+        let mut watch = Watch::start(&root, &config(&[], &[]), &[root.join(".gitignore")]).unwrap();
+
+        std::fs::write(root.join("scratch.tmp"), "ignored").unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+        let changed = tokio::time::timeout(Duration::from_secs(5), watch.next_change())
+            .await
+            .expect("no change reported")
+            .unwrap();
+        assert_eq!(
+            changed,
+            vec![PathBuf::from("src/lib.rs"), PathBuf::from("src/main.rs")]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! `pm ps` — services started by `pm run`, across all workspaces.
+//!
+//! Reads `services.json`, checks every recorded PID and prints one row per
+//! service: project, service, pid, port, uptime, status and proxy URL.
+//! Entries whose process is gone are shown once (as `exited` with the last
+//! exit status, or `dead` when pm never saw the exit) and then pruned;
+//! entries the daemon is about to restart are kept.
+//!
+//! `--stats` adds CPU%, resident memory, thread count and listening TCP
+//! ports for each service's process tree (see [`crate::proc_stats`]), and
+//! flags services that listen somewhere other than their allocated port.
+
+use crate::config::load_config;
+use crate::proc_stats::{self, ServiceStats};
+use crate::routes;
+use crate::services::{self as svc_state, ServiceState, ServiceStatus};
+use anyhow::Result;
+use chrono::Utc;
+use colored::Colorize;
+use std::collections::HashMap;
+
+struct Row {
+    project: String,
+    service: String,
+    pid: u32,
+    port: u16,
+    uptime: String,
+    status: String,
+    status_color: &'static str,
+    url: String,
+    stats: Option<ServiceStats>,
+}
+
+pub fn run(stats: bool) -> Result<()> {
+    let data = svc_state::load()?;
+    let proxy_port = load_config().map(|c| c.dev.proxy_port).unwrap_or(7100);
+
+    let mut stats: HashMap<(String, String, String), ServiceStats> = if stats {
+        proc_stats::collect(&data, proc_stats::SAMPLE_INTERVAL)?
+            .into_iter()
+            .map(|s| ((s.workspace.clone(), s.project.clone(), s.service.clone()), s))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let with_stats = !stats.is_empty();
+
+    let mut rows: Vec<Row> = Vec::new();
+    for (key, services) in &data.projects {
+        let (workspace, project) = key.split_once('/').unwrap_or(("default", key));
+        for (service, state) in services {
+            let mut row = row(workspace, project, service, state, proxy_port);
+            row.stats = stats
+                .remove(&(workspace.to_string(), project.to_string(), service.clone()))
+                .filter(|s| s.processes > 0);
+            rows.push(row);
+        }
+    }
+    rows.sort_by(|a, b| (&a.project, &a.service).cmp(&(&b.project, &b.service)));
+
+    // Re-reads the file under its lock: `data` is stale by now.
+    let pruned = svc_state::prune()?;
+
+    if rows.is_empty() {
+        println!("{} no services running", "—".dimmed());
+        return Ok(());
+    }
+
+    let width = |f: fn(&Row) -> usize, header: &str| {
+        rows.iter().map(f).max().unwrap_or(0).max(header.len())
+    };
+    let project_w = width(|r| r.project.len(), "PROJECT");
+    let service_w = width(|r| r.service.len(), "SERVICE");
+    let uptime_w = width(|r| r.uptime.len(), "UPTIME");
+    let status_w = width(|r| r.status.len(), "STATUS");
+    let listen_w = width(|r| listen_column(r).len(), "LISTEN");
+
+    let tail_header = if with_stats {
+        format!("{:>6}   {:>7}   {:>4}   {:<listen_w$}   URL", "CPU", "MEM", "THR", "LISTEN")
+    } else {
+        "URL".to_string()
+    };
+    println!(
+        "{}",
+        format!(
+            "  {:<project_w$}   {:<service_w$}   {:>7}   {:>5}   {:<uptime_w$}   {:<status_w$}   {}",
+            "PROJECT", "SERVICE", "PID", "PORT", "UPTIME", "STATUS", tail_header,
+        )
+        .dimmed()
+    );
+    for r in &rows {
+        let status = format!("{:<status_w$}", r.status);
+        let status = match r.status_color {
+            "green" => status.green(),
+            "yellow" => status.yellow(),
+            _ => status.red(),
+        };
+        let stats = if with_stats {
+            let listen = format!("{:<listen_w$}", listen_column(r));
+            let listen = if r.stats.as_ref().is_some_and(|s| s.port_mismatch) {
+                listen.yellow()
+            } else {
+                listen.normal()
+            };
+            match &r.stats {
+                Some(s) => format!(
+                    "{:>6}   {:>7}   {:>4}   {}   ",
+                    format!("{:.1}%", s.cpu_percent),
+                    format_bytes(s.rss_bytes),
+                    s.threads,
+                    listen
+                ),
+                None => format!("{:>6}   {:>7}   {:>4}   {}   ", "-", "-", "-", listen),
+            }
+        } else {
+            String::new()
+        };
+        println!(
+            "  {:<project_w$}   {:<service_w$}   {:>7}   {:>5}   {:<uptime_w$}   {}   {}{}",
+            r.project,
+            r.service.bold(),
+            r.pid,
+            r.port,
+            r.uptime,
+            status,
+            stats,
+            r.url.cyan(),
+        );
+    }
+
+    let mismatched: Vec<&Row> = rows
+        .iter()
+        .filter(|r| r.stats.as_ref().is_some_and(|s| s.port_mismatch))
+        .collect();
+    if !mismatched.is_empty() {
+        println!();
+    }
+    for r in mismatched {
+        println!(
+            "{} {}/{} listens on {}, not its allocated port {}; the proxy cannot reach it",
+            "!".yellow(),
+            r.project,
+            r.service,
+            listen_column(r),
+            r.port
+        );
+    }
+    if pruned > 0 {
+        println!();
+        println!(
+            "({} stale {} pruned)",
+            pruned,
+            if pruned == 1 { "entry" } else { "entries" }
+        );
+    }
+    Ok(())
+}
+
+fn row(workspace: &str, project: &str, service: &str, state: &ServiceState, proxy_port: u16) -> Row {
+    let alive = svc_state::pid_alive(state.pid);
+    let (status, status_color) = if state.status == ServiceStatus::Restarting {
+        ("restarting".to_string(), "yellow")
+    } else if alive {
+        ("running".to_string(), "green")
+    } else if let Some(exit) = state.last_exit() {
+        (format!("exited ({exit})"), "red")
+    } else {
+        ("dead".to_string(), "red")
+    };
+    let uptime = if alive {
+        format_uptime((Utc::now() - state.started_at).num_seconds())
+    } else {
+        "-".to_string()
+    };
+    let url = if alive || state.status == ServiceStatus::Restarting {
+        let host = routes::default_workspace_alias(workspace, project, service)
+            .unwrap_or_else(|| routes::canonical_hostname(workspace, project, service));
+        format!("http://{host}:{proxy_port}/")
+    } else {
+        String::new()
+    };
+    Row {
+        project: format!("{workspace}/{project}"),
+        service: service.to_string(),
+        pid: state.pid,
+        port: state.port,
+        uptime,
+        status,
+        status_color,
+        url,
+        stats: None,
+    }
+}
+
+fn listen_column(row: &Row) -> String {
+    match &row.stats {
+        Some(s) if !s.listening.is_empty() => {
+            s.listening.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(",")
+        }
+        _ => "-".to_string(),
+    }
+}
+
+/// Compact binary size: `512K`, `42.1M`, `1.3G`.
+fn format_bytes(bytes: u64) -> String {
+    const K: f64 = 1024.0;
+    let b = bytes as f64;
+    if b < K * K {
+        format!("{:.0}K", b / K)
+    } else if b < K * K * K {
+        format!("{:.1}M", b / K / K)
+    } else {
+        format!("{:.1}G", b / K / K / K)
+    }
+}
+
+/// Compact uptime: `42s`, `12m 5s`, `3h 12m`, `2d 4h`.
+fn format_uptime(secs: i64) -> String {
+    let secs = secs.max(0);
+    let (d, h, m, s) = (secs / 86_400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+    if d > 0 {
+        format!("{d}d {h}h")
+    } else if h > 0 {
+        format!("{h}h {m}m")
+    } else if m > 0 {
+        format!("{m}m {s}s")
+    } else {
+        format!("{s}s")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uptime_uses_two_largest_units() {
+        assert_eq!(format_uptime(-3), "0s");
+        assert_eq!(format_uptime(42), "42s");
+        assert_eq!(format_uptime(12 * 60 + 5), "12m 5s");
+        assert_eq!(format_uptime(3 * 3600 + 12 * 60 + 9), "3h 12m");
+        assert_eq!(format_uptime(2 * 86_400 + 4 * 3600 + 59), "2d 4h");
+    }
+
+    #[test]
+    fn bytes_use_binary_units() {
+        assert_eq!(format_bytes(0), "0K");
+        assert_eq!(format_bytes(512 * 1024), "512K");
+        assert_eq!(format_bytes(42 * 1024 * 1024 + 100 * 1024), "42.1M");
+        assert_eq!(format_bytes(1024 * 1024 * 1024 * 3 / 2), "1.5G");
+    }
+}
@@ -0,0 +1,340 @@
+//! Readiness probes run by `pm run` after a service has been spawned.
+//!
+//! A service with a `ready:` block is only reported as running once its
+//! probe succeeds:
+//!
+//! - `tcp`: a connection to the allocated port is accepted;
+//! - `http`: `GET <path>` on the allocated port answers with the expected
+//!   status (any 2xx/3xx when none is given);
+//! - `log`: a line written to the service log *after* the spawn matches
+//!   the regex.
+//!
+//! While waiting, a spinner is drawn on stderr (plain single line when
+//! stderr is not a terminal). On timeout or early exit the caller prints
+//! the tail of the service log via [`report_failure`].
+
+use crate::commands::proxy::control::parse_status;
+use crate::log_line;
+use crate::project::{ReadyCheck, ReadyProbe};
+use colored::Colorize;
+use regex::Regex;
+use std::fs::File;
+use std::io::{IsTerminal, Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const SPINNER: [&str; 10] = ["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+const TICK: Duration = Duration::from_millis(100);
+/// Probe every this many spinner ticks.
+const TICKS_PER_PROBE: u32 = 3;
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+/// Log lines shown when a service fails to become ready.
+pub const FAILURE_LOG_LINES: usize = 20;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Ready(Duration),
+    TimedOut,
+    /// The service went down while we were waiting; carries the reason.
+    Exited(String),
+}
+
+/// Where a probe looks: the allocated port and the service log, of which
+/// only the bytes past `log_offset` (written since the spawn) count.
+pub struct Target<'a> {
+    pub label: &'a str,
+    pub port: u16,
+    pub log_path: &'a Path,
+    pub log_offset: u64,
+}
+
+/// Block until `probe` succeeds, the timeout elapses, or `gone` reports
+/// that the service stopped for good.
+pub fn wait_until_ready(
+    target: &Target,
+    probe: &ReadyProbe,
+    gone: impl Fn() -> Option<String>,
+) -> Outcome {
+    wait(target, probe, gone, true)
+}
+
+/// [`wait_until_ready`] without the progress line, for the daemon.
+pub fn wait_quietly(
+    target: &Target,
+    probe: &ReadyProbe,
+    gone: impl Fn() -> Option<String>,
+) -> Outcome {
+    wait(target, probe, gone, false)
+}
+
+fn wait(
+    target: &Target,
+    probe: &ReadyProbe,
+    gone: impl Fn() -> Option<String>,
+    show: bool,
+) -> Outcome {
+    let timeout = Duration::from_secs(probe.timeout_secs);
+    let started = Instant::now();
+    let interactive = show && std::io::stderr().is_terminal();
+    let mut log = match &probe.check {
+        ReadyCheck::Log { pattern } => Regex::new(pattern)
+            .ok()
+            .map(|re| LogScanner::new(target.log_path, target.log_offset, re)),
+        _ => None,
+    };
+    if show && !interactive {
+        eprintln!(
+            "  {} waiting for {} ({})",
+            "…".dimmed(),
+            target.label,
+            probe.check.describe()
+        );
+    }
+
+    let mut tick = 0u32;
+    let outcome = loop {
+        if tick.is_multiple_of(TICKS_PER_PROBE) {
+            let ok = match &probe.check {
+                ReadyCheck::Tcp => tcp_ready(target.port),
+                ReadyCheck::Http { path, status } => http_ready(target.port, path, *status),
+                ReadyCheck::Log { .. } => log.as_mut().is_some_and(LogScanner::matched),
+            };
+            if ok {
+                break Outcome::Ready(started.elapsed());
+            }
+            if let Some(reason) = gone() {
+                break Outcome::Exited(reason);
+            }
+        }
+        if started.elapsed() >= timeout {
+            break Outcome::TimedOut;
+        }
+        if interactive {
+            eprint!(
+                "\r  {} waiting for {} ({}) {}s",
+                SPINNER[tick as usize % SPINNER.len()].cyan(),
+                target.label,
+                probe.check.describe(),
+                started.elapsed().as_secs()
+            );
+            let _ = std::io::stderr().flush();
+        }
+        std::thread::sleep(TICK);
+        tick += 1;
+    };
+    if interactive {
+        eprint!("\r\x1b[2K");
+    }
+    outcome
+}
+
+/// Print why the service is not ready, followed by its latest log lines.
+pub fn report_failure(target: &Target, probe: &ReadyProbe, outcome: &Outcome) {
+    let why = match outcome {
+        Outcome::Exited(reason) => format!("stopped before becoming ready ({reason})"),
+        _ => format!(
+            "not ready after {}s ({})",
+            probe.timeout_secs,
+            probe.check.describe()
+        ),
+    };
+    eprintln!("  {} {} {}", "✗".red(), target.label, why);
+    let lines = last_log_lines(target.log_path, target.log_offset, FAILURE_LOG_LINES);
+    if lines.is_empty() {
+        eprintln!("    {}", "(no log output)".dimmed());
+        return;
+    }
+    eprintln!(
+        "    {}",
+        format!("last log lines ({}):", target.log_path.display()).dimmed()
+    );
+    for line in lines {
+        eprintln!("    {} {}", "│".dimmed(), line);
+    }
+}
+
+fn tcp_ready(port: u16) -> bool {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT).is_ok()
+}
+
+fn http_ready(port: u16, path: &str, expected: Option<u16>) -> bool {
+    let addr = SocketAddr::from(([127, 0, 0, 1], port));
+    let Ok(mut stream) = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(2)));
+    let req = format!("GET {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    if stream.write_all(req.as_bytes()).is_err() {
+        return false;
+    }
+    // The status line is all we need.
+    let mut buf = [0u8; 64];
+    let mut len = 0;
+    while len < buf.len() {
+        match stream.read(&mut buf[len..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => len += n,
+        }
+    }
+    match parse_status(&buf[..len]) {
+        Some(status) => match expected {
+            Some(code) => status.as_u16() == code,
+            None => status.is_success() || status.is_redirection(),
+        },
+        None => false,
+    }
+}
+
+/// Incrementally reads lines appended to a log file.
+struct LogScanner {
+    path: PathBuf,
+    offset: u64,
+    partial: String,
+    regex: Regex,
+}
+
+impl LogScanner {
+    fn new(path: &Path, offset: u64, regex: Regex) -> Self {
+        Self { path: path.to_path_buf(), offset, partial: String::new(), regex }
+    }
+
+    fn matched(&mut self) -> bool {
+        let Ok(mut file) = File::open(&self.path) else { return false };
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        if len < self.offset {
+            // Rotated underneath us.
+            self.offset = 0;
+            self.partial.clear();
+        }
+        let mut fresh = Vec::new();
+        if file.seek(SeekFrom::Start(self.offset)).is_err() || file.read_to_end(&mut fresh).is_err() {
+            return false;
+        }
+        self.offset += fresh.len() as u64;
+        self.partial.push_str(&String::from_utf8_lossy(&fresh));
+        // Only complete lines are matched; keep the trailing fragment.
+        let complete = match self.partial.rfind('\n') {
+            Some(idx) => self.partial.drain(..=idx).collect::<String>(),
+            None => return false,
+        };
+        complete
+            .lines()
+            .any(|line| self.regex.is_match(log_line::message(line)))
+    }
+}
+
+/// The last `n` lines written to `path` past `offset` (or from the start
+/// when the file has been rotated since).
+pub fn last_log_lines(path: &Path, offset: u64, n: usize) -> Vec<String> {
+    let Ok(mut file) = File::open(path) else { return Vec::new() };
+    let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+    let start = if offset <= len { offset } else { 0 };
+    let mut content = Vec::new();
+    if file.seek(SeekFrom::Start(start)).is_err() || file.read_to_end(&mut content).is_err() {
+        return Vec::new();
+    }
+    let text = String::from_utf8_lossy(&content);
+    let lines: Vec<&str> = text.lines().collect();
+    lines[lines.len().saturating_sub(n)..]
+        .iter()
+        .map(|l| log_line::display(l, false))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn probe(check: ReadyCheck, timeout_secs: u64) -> ReadyProbe {
+        ReadyProbe { check, timeout_secs }
+    }
+
+    fn target<'a>(port: u16, log_path: &'a Path, log_offset: u64) -> Target<'a> {
+        Target { label: "api/back", port, log_path, log_offset }
+    }
+
+    #[test]
+    fn tcp_probe_succeeds_once_port_listens() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let outcome = wait_until_ready(
+            &target(port, Path::new("/nonexistent"), 0),
+            &probe(ReadyCheck::Tcp, 5),
+            || None,
+        );
+        assert!(matches!(outcome, Outcome::Ready(_)));
+    }
+
+    #[test]
+    fn http_probe_checks_expected_status() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0u8; 512];
+                let n = stream.read(&mut buf).unwrap();
+                let head = String::from_utf8_lossy(&buf[..n]).to_string();
+                let reply = if head.starts_with("GET /healthz ") {
+                    "HTTP/1.1 204 No Content\r\n\r\n"
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                };
+                let _ = stream.write_all(reply.as_bytes());
+            }
+        });
+        assert!(http_ready(port, "/healthz", Some(204)));
+        assert!(http_ready(port, "/healthz", None));
+        assert!(!http_ready(port, "/healthz", Some(200)));
+        assert!(!http_ready(port, "/missing", None));
+    }
+
+    #[test]
+    fn log_probe_ignores_output_before_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(&path, "Listening on :3000 (previous run)\n").unwrap();
+        let offset = std::fs::metadata(&path).unwrap().len();
+        let mut scanner = LogScanner::new(&path, offset, Regex::new("Listening on").unwrap());
+        assert!(!scanner.matched());
+
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "compiling...\nListening").unwrap();
+        assert!(!scanner.matched(), "partial line must not match yet");
+        writeln!(file, " on :3000").unwrap();
+        assert!(scanner.matched());
+    }
+
+    #[test]
+    fn log_probe_matches_text_without_timestamp_prefix() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(&path, "2026-10-18T09:12:01.000Z out Listening on :3000\n").unwrap();
+        let mut scanner = LogScanner::new(&path, 0, Regex::new("^Listening on").unwrap());
+        assert!(scanner.matched());
+    }
+
+    #[test]
+    fn exit_is_reported_before_timeout() {
+        let outcome = wait_until_ready(
+            &target(1, Path::new("/nonexistent"), 0),
+            &probe(ReadyCheck::Tcp, 30),
+            || Some("exit code 1".to_string()),
+        );
+        assert_eq!(outcome, Outcome::Exited("exit code 1".to_string()));
+    }
+
+    #[test]
+    fn last_log_lines_returns_tail_since_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(&path, "old\na\nb\nc\n").unwrap();
+        assert_eq!(last_log_lines(&path, 4, 2), vec!["b", "c"]);
+        assert_eq!(last_log_lines(&path, 4, 10), vec!["a", "b", "c"]);
+        // Offset past the end (log rotated): read from the start.
+        assert_eq!(last_log_lines(&path, 1000, 1), vec!["c"]);
+    }
+}
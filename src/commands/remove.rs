@@ -1,6 +1,6 @@
 use crate::history::record_project_event;
 use crate::models::HistoryAction;
-use crate::state::{find_project, load_state, project_path, save_state};
+use crate::state::{find_project, load_state, project_path, update_state};
 use anyhow::Result;
 use colored::Colorize;
 use std::fs;
@@ -11,7 +11,7 @@ pub fn run(project: String, yes: bool, force: bool, recursive: bool) -> Result<(
         return Err(anyhow::anyhow!("-r requires -f flag"));
     }
 
-    let (mut config, mut manifest) = load_state()?;
+    let (config, manifest) = load_state()?;
     let project_data = find_project(&manifest, &project)?.clone();
     let path = project_path(&config, &manifest, &project_data)?;
 
@@ -19,6 +19,9 @@ pub fn run(project: String, yes: bool, force: bool, recursive: bool) -> Result<(
         return Ok(());
     }
 
+    // Each branch locks the state only for its own update, so the prompt
+    // and the deletion do not hold up other pm commands.
+
     if recursive {
         if !force {
             return Err(anyhow::anyhow!("-r requires -f flag"));
@@ -28,31 +31,37 @@ pub fn run(project: String, yes: bool, force: bool, recursive: bool) -> Result<(
         if path.exists() {
             fs::remove_dir_all(&path)?;
         }
-        manifest
-            .projects
-            .retain(|candidate| candidate.name != project);
-        clear_current_project(&mut config, &project);
-        save_state(&config, &manifest)?;
+        update_state(|config, manifest| {
+            manifest
+                .projects
+                .retain(|candidate| candidate.name != project);
+            clear_current_project(config, &project);
+            Ok(())
+        })?;
         println!("{} Deleted '{}' and its files", "✓".green(), project.cyan());
     } else if force {
         record_project_event(&project_data, &path, HistoryAction::Trashed)?;
-        if let Some(project_data) = manifest
-            .projects
-            .iter_mut()
-            .find(|candidate| candidate.name == project)
-        {
-            project_data.workspace = ".trash".to_string();
-        }
-        clear_current_project(&mut config, &project);
-        save_state(&config, &manifest)?;
+        update_state(|config, manifest| {
+            if let Some(project_data) = manifest
+                .projects
+                .iter_mut()
+                .find(|candidate| candidate.name == project)
+            {
+                project_data.workspace = ".trash".to_string();
+            }
+            clear_current_project(config, &project);
+            Ok(())
+        })?;
         println!("{} Moved '{}' to trash", "✓".green(), project.cyan());
     } else {
         record_project_event(&project_data, &path, HistoryAction::Unregistered)?;
-        manifest
-            .projects
-            .retain(|candidate| candidate.name != project);
-        clear_current_project(&mut config, &project);
-        save_state(&config, &manifest)?;
+        update_state(|config, manifest| {
+            manifest
+                .projects
+                .retain(|candidate| candidate.name != project);
+            clear_current_project(config, &project);
+            Ok(())
+        })?;
         println!(
             "{} Unregistered '{}' (files kept at {})",
             "✓".green(),
@@ -2,7 +2,7 @@ use crate::cli::{RepoCommand, RepoSpecCommand};
 use crate::config::{list_repo_specs, load_repo_spec};
 use crate::models::RepoSpecMetadata;
 use crate::state::{
-    detect_current_project, find_project, find_project_mut, load_state, save_state,
+    detect_current_project, find_project, find_project_mut, load_state, lock_state, save_state,
 };
 use anyhow::{anyhow, Result};
 use chrono::Utc;
@@ -55,6 +55,7 @@ fn track(project_name: String, spec_id: String, version: Option<String>) -> Resu
     let spec = load_repo_spec(&spec_id)?;
     let tracked_version = version.unwrap_or_else(|| spec.version.clone());
 
+    let _lock = lock_state()?;
     let (config, mut manifest) = load_state()?;
     let project = find_project_mut(&mut manifest, &project_name)?;
 
@@ -98,10 +99,10 @@ fn status(project_name: Option<String>) -> Result<()> {
             println!("{} {}", "Version:".dimmed(), metadata.version);
             println!("{} {}", "Applied:".dimmed(), metadata.applied_at);
 
-            if let Ok(spec) = load_repo_spec(&metadata.id) {
-                if spec.version != metadata.version {
-                    println!("{} {}", "Current spec:".dimmed(), spec.version.yellow());
-                }
+            if let Ok(spec) = load_repo_spec(&metadata.id)
+                && spec.version != metadata.version
+            {
+                println!("{} {}", "Current spec:".dimmed(), spec.version.yellow());
             }
         }
         None => {
@@ -0,0 +1,44 @@
+//! `pm restart [service] [project]` — stop services and start them again.
+//!
+//! Stopping goes through the same path as `pm stop` (process-group signal,
+//! `services.json` and route cleanup); starting goes through the
+//! orchestrator, so the new processes pick up the current `.project.yaml`,
+//! environment and ports. Without a service, the whole project is
+//! restarted. With one, only that service is: dependencies that are not
+//! running are started, dependents are left alone.
+
+use crate::commands::orchestrator;
+use crate::commands::run::resolve_project;
+use crate::commands::stop;
+use crate::project::load_proj_config;
+use anyhow::{anyhow, Result};
+
+pub fn run(service: Option<String>, project: Option<String>) -> Result<()> {
+    let (workspace, project_obj, project_dir) = resolve_project(project)?;
+    let proj_config = load_proj_config(&project_dir)?;
+
+    match service.as_deref() {
+        Some(name) => {
+            if !proj_config.services.contains_key(name) {
+                return Err(anyhow!(
+                    "service '{}' is not defined in {}'s .project.yaml",
+                    name,
+                    project_obj.name
+                ));
+            }
+            stop::stop_service(&workspace, &project_obj.name, name, true)?;
+        }
+        None => {
+            stop::stop_project(&workspace, &project_obj.name, &project_dir)?;
+        }
+    }
+
+    orchestrator::start(
+        &workspace,
+        &project_obj,
+        &project_dir,
+        &proj_config,
+        service.as_deref(),
+        false,
+    )
+}
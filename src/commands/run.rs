@@ -17,26 +17,37 @@
 //! pm run <project>        # all services in a specific project
 //! ```
 //!
+//! `--attach` keeps orchestrator mode in the foreground, streaming every
+//! service's output until Ctrl+C stops them.
+//!
 //! Disambiguation rule: if `--` was passed, run legacy mode unconditionally.
 //! Otherwise consult the project's `.proj.yaml`. If the first positional
 //! matches a service key in `.proj.yaml.services`, treat it as a service;
 //! else treat it as a project name.
 
-use crate::config::load_ports;
+use crate::config::{load_config, load_ports};
+use crate::dotenv;
 use crate::error::PmError;
 use crate::models::Project;
 use crate::project as proj;
+use crate::routes;
 use crate::state::{detect_current_project, load_state, parse_target, project_path};
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-pub fn run(positional: Vec<String>, command: Vec<String>) -> Result<()> {
+pub fn run(positional: Vec<String>, command: Vec<String>, attach: bool) -> Result<()> {
     // Legacy mode: presence of `--` (i.e. `command` is non-empty) takes
     // precedence over orchestrator semantics. This guarantees v0.3.0
     // grammar continues to work unchanged.
     if !command.is_empty() {
+        if attach {
+            return Err(anyhow!(
+                "--attach applies to services; `pm run -- <cmd>` already runs in the foreground"
+            ));
+        }
         return run_legacy(positional.into_iter().next(), command);
     }
 
@@ -68,12 +79,13 @@ pub fn run(positional: Vec<String>, command: Vec<String>) -> Result<()> {
             &project_dir,
             proj_config.as_ref().unwrap(),
             target_service.as_deref(),
+            attach,
         )
     }
 
     #[cfg(not(unix))]
     {
-        let _ = (workspace, project, project_dir, target_service);
+        let _ = (workspace, project, project_dir, target_service, attach);
         Err(anyhow!(
             "Orchestrator mode requires Unix (macOS/Linux) in v0.4.0. \
              Use `pm run -- <command>` for stateless mode on this platform."
@@ -85,7 +97,23 @@ pub fn run(positional: Vec<String>, command: Vec<String>) -> Result<()> {
 
 fn run_legacy(project_name: Option<String>, command: Vec<String>) -> Result<()> {
     let (workspace, project, project_dir) = resolve_project(project_name)?;
-    let env = build_port_env(&workspace, &project)?;
+    let mut env = build_port_env(&workspace, &project)?;
+    let ports = service_ports(&workspace, &project)?;
+    let proxy_port = load_config().map(|c| c.dev.proxy_port).unwrap_or(7100);
+    env.extend(service_discovery_env(&workspace, &project.name, &ports, proxy_port));
+    // Project-level `env` / `env_file` apply here too, so ad-hoc commands
+    // (migrations, REPLs) see what the services see.
+    if let Ok(proj_config) = proj::load_proj_config(&project_dir) {
+        apply_env_layers(
+            &mut env,
+            &[EnvLayer {
+                base_dir: &project_dir,
+                env_file: &proj_config.env_file,
+                env: &proj_config.env,
+            }],
+            &ports,
+        )?;
+    }
 
     if command.is_empty() {
         return Err(anyhow!("No command provided"));
@@ -155,14 +183,13 @@ pub fn resolve_project(project_name: Option<String>) -> Result<(String, Project,
     let project = match project_name {
         Some(target) => {
             let (workspace_name, project_name) = parse_target(target);
-            if let Some(workspace_name) = &workspace_name {
-                if !manifest
+            if let Some(workspace_name) = &workspace_name
+                && !manifest
                     .workspaces
                     .iter()
                     .any(|workspace| &workspace.name == workspace_name)
-                {
-                    return Err(PmError::WorkspaceNotFound(workspace_name.clone()).into());
-                }
+            {
+                return Err(PmError::WorkspaceNotFound(workspace_name.clone()).into());
             }
 
             manifest
@@ -236,6 +263,105 @@ pub fn build_port_env(workspace: &str, project: &Project) -> Result<HashMap<Stri
     Ok(env)
 }
 
+/// Allocated ports of a project's services, keyed by service name. Backs
+/// `${port:<service>}` interpolation.
+pub fn service_ports(workspace: &str, project: &Project) -> Result<HashMap<String, u16>> {
+    let ports = load_ports()?;
+    Ok(ports
+        .projects
+        .get(&format!("{workspace}/{}", project.name))
+        .map(|p| p.services.iter().map(|(name, s)| (name.clone(), s.port)).collect())
+        .unwrap_or_default())
+}
+
+/// `PM_SERVICE_<NAME>_PORT`, `PM_SERVICE_<NAME>_URL` (direct, on
+/// loopback) and `PM_SERVICE_<NAME>_PROXY_URL` (the `*.localhost` route)
+/// for every service in `ports`, so services can find their siblings.
+/// `<NAME>` is the service key uppercased with non-alphanumerics as `_`.
+pub fn service_discovery_env(
+    workspace: &str,
+    project: &str,
+    ports: &HashMap<String, u16>,
+    proxy_port: u16,
+) -> HashMap<String, String> {
+    let mut env = HashMap::new();
+    for (service, port) in ports {
+        let name: String = service
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        let host = routes::default_workspace_alias(workspace, project, service)
+            .unwrap_or_else(|| routes::canonical_hostname(workspace, project, service));
+        env.insert(format!("PM_SERVICE_{name}_PORT"), port.to_string());
+        env.insert(format!("PM_SERVICE_{name}_URL"), format!("http://127.0.0.1:{port}"));
+        env.insert(
+            format!("PM_SERVICE_{name}_PROXY_URL"),
+            format!("http://{host}:{proxy_port}"),
+        );
+    }
+    env
+}
+
+/// An `env_file:` + `env:` pair from `.proj.yaml` (project or service level).
+pub struct EnvLayer<'a> {
+    /// Directory the `env_file` paths are relative to.
+    pub base_dir: &'a Path,
+    pub env_file: &'a [String],
+    pub env: &'a HashMap<String, String>,
+}
+
+/// Merge `.proj.yaml` environment layers into `env`, which already holds
+/// the pm-provided values. Later layers win; within a layer the `env_file`s
+/// load in order and `env:` goes last.
+///
+/// Values are interpolated against everything merged before them, then
+/// the caller's own environment; `${port:<service>}` comes from `ports`.
+/// Keys of one `env:` map cannot reference each other (map order is not
+/// meaningful), but can reference any earlier layer.
+pub fn apply_env_layers(
+    env: &mut HashMap<String, String>,
+    layers: &[EnvLayer],
+    ports: &HashMap<String, u16>,
+) -> Result<()> {
+    for layer in layers {
+        for file in layer.env_file {
+            let path = layer.base_dir.join(file);
+            let content = fs::read_to_string(&path)
+                .with_context(|| format!("reading env_file {}", path.display()))?;
+            let entries =
+                dotenv::parse(&content).with_context(|| format!("parsing {}", path.display()))?;
+            for entry in entries {
+                let value = if entry.literal {
+                    entry.value
+                } else {
+                    interpolate_env(&entry.value, env, ports)
+                        .with_context(|| format!("{} in {}", entry.key, path.display()))?
+                };
+                env.insert(entry.key, value);
+            }
+        }
+
+        let mut resolved = Vec::with_capacity(layer.env.len());
+        for (key, value) in layer.env {
+            let value = interpolate_env(value, env, ports).with_context(|| format!("env.{key}"))?;
+            resolved.push((key.clone(), value));
+        }
+        env.extend(resolved);
+    }
+    Ok(())
+}
+
+fn interpolate_env(
+    value: &str,
+    env: &HashMap<String, String>,
+    ports: &HashMap<String, u16>,
+) -> Result<String> {
+    dotenv::interpolate(value, |name| match name.strip_prefix("port:") {
+        Some(service) => ports.get(service).map(|p| p.to_string()),
+        None => env.get(name).cloned().or_else(|| std::env::var(name).ok()),
+    })
+}
+
 /// Build the local Postgres database name for a project, in the form
 /// `{workspace}_{project}`. Non-`[a-z0-9_]` characters are replaced with
 /// `_`, and the entire result is lowercased.
@@ -292,6 +418,76 @@ mod tests {
         assert_eq!(name, "work_api");
     }
 
+    #[test]
+    fn discovery_env_covers_every_sibling() {
+        let ports = HashMap::from([("back".to_string(), 3001), ("front-end".to_string(), 3000)]);
+        let env = service_discovery_env("work", "api", &ports, 7100);
+        assert_eq!(env["PM_SERVICE_BACK_PORT"], "3001");
+        assert_eq!(env["PM_SERVICE_BACK_URL"], "http://127.0.0.1:3001");
+        assert_eq!(env["PM_SERVICE_BACK_PROXY_URL"], "http://back.api.work.localhost:7100");
+        assert_eq!(env["PM_SERVICE_FRONT_END_PORT"], "3000");
+        assert_eq!(env.len(), 6);
+
+        let env = service_discovery_env("default", "blog", &ports, 7100);
+        assert_eq!(env["PM_SERVICE_BACK_PROXY_URL"], "http://back.blog.localhost:7100");
+    }
+
+    #[test]
+    fn env_layers_merge_in_order_and_interpolate() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join(".env"),
+            "RUST_LOG=info\nAPI=http://127.0.0.1:${port:back}\nRAW='${NOPE}'\n",
+        )
+        .unwrap();
+        let mut env = HashMap::from([(
+            "DATABASE_URL".to_string(),
+            "postgres://127.0.0.1:5432/work_api".to_string(),
+        )]);
+        let project_env = HashMap::from([("RUST_LOG".to_string(), "debug".to_string())]);
+        let service_env = HashMap::from([
+            ("TEST_DB".to_string(), "${DATABASE_URL}_test".to_string()),
+            ("RUST_LOG".to_string(), "${RUST_LOG},hyper=warn".to_string()),
+        ]);
+        let ports = HashMap::from([("back".to_string(), 3001)]);
+        apply_env_layers(
+            &mut env,
+            &[
+                EnvLayer { base_dir: dir.path(), env_file: &[".env".to_string()], env: &project_env },
+                EnvLayer { base_dir: dir.path(), env_file: &[], env: &service_env },
+            ],
+            &ports,
+        )
+        .unwrap();
+        assert_eq!(env["RUST_LOG"], "debug,hyper=warn");
+        assert_eq!(env["API"], "http://127.0.0.1:3001");
+        assert_eq!(env["RAW"], "${NOPE}");
+        assert_eq!(env["TEST_DB"], "postgres://127.0.0.1:5432/work_api_test");
+    }
+
+    #[test]
+    fn env_layers_report_missing_files_and_variables() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut env = HashMap::new();
+        let none = HashMap::new();
+        let err = apply_env_layers(
+            &mut env,
+            &[EnvLayer { base_dir: dir.path(), env_file: &["missing.env".to_string()], env: &none }],
+            &HashMap::new(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("missing.env"));
+
+        let bad = HashMap::from([("A".to_string(), "${PM_SURELY_UNDEFINED_VAR}".to_string())]);
+        let err = apply_env_layers(
+            &mut env,
+            &[EnvLayer { base_dir: dir.path(), env_file: &[], env: &bad }],
+            &HashMap::new(),
+        )
+        .unwrap_err();
+        assert!(format!("{err:#}").contains("env.A"));
+    }
+
     #[test]
     fn first_service_token_finds_match() {
         let mut services = HashMap::new();
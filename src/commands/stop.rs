@@ -1,26 +1,35 @@
 //! `pm stop [service] [project]` — terminate spawned services.
 //!
-//! Sends SIGTERM to the recorded PID(s) and removes the corresponding
-//! entries from `services.json` and `routes.json`. If the service is
-//! already gone, treats the operation as a successful no-op.
+//! Services run in their own session, so the recorded PID is also the
+//! process group of everything the service started (`pnpm` → `node`,
+//! `cargo run` → binary). The whole group gets SIGTERM; whatever is still
+//! alive after the service's `stop_timeout` is SIGKILLed and reported.
+//! Afterwards the service's port is checked, so a stray listener does not
+//! go unnoticed. The corresponding entries are removed from
+//! `services.json` and `routes.json`. If the service is already gone,
+//! treats the operation as a successful no-op.
+//!
+//! Stopping a whole project goes in reverse `depends_on` order, so
+//! dependents shut down before the services they rely on.
 
 use crate::commands::run::resolve_project;
+use crate::project::{load_proj_config, service_start_order, DEFAULT_STOP_TIMEOUT_SECS};
 use crate::routes;
 use crate::services as svc_state;
 #[cfg(not(unix))]
 use anyhow::anyhow;
 use anyhow::Result;
 use colored::Colorize;
+use std::path::Path;
 use std::time::Duration;
 
 pub fn run(service: Option<String>, project: Option<String>) -> Result<()> {
-    let (workspace, project_obj, _project_dir) = resolve_project(project)?;
+    let (workspace, project_obj, project_dir) = resolve_project(project)?;
     let project_name = project_obj.name.clone();
 
-    let targets = match service {
-        Some(s) => match svc_state::remove(&workspace, &project_name, &s)? {
-            Some(state) => vec![(s, state)],
-            None => {
+    match service {
+        Some(s) => {
+            if !stop_service(&workspace, &project_name, &s, true)? {
                 println!(
                     "{} no running service '{}' in {}/{}",
                     "—".dimmed(),
@@ -28,72 +37,320 @@ pub fn run(service: Option<String>, project: Option<String>) -> Result<()> {
                     workspace,
                     project_name
                 );
-                return Ok(());
             }
-        },
+        }
         None => {
-            let removed = svc_state::remove_project(&workspace, &project_name)?;
-            if removed.is_empty() {
+            let count = stop_project(&workspace, &project_name, &project_dir)?;
+            if count == 0 {
                 println!(
                     "{} no running services in {}/{}",
                     "—".dimmed(),
                     workspace,
                     project_name
                 );
-                return Ok(());
+            } else if count > 1 {
+                println!("({count} services stopped)");
             }
-            removed.into_iter().collect()
         }
-    };
+    }
+
+    Ok(())
+}
+
+/// Stop one service if it is recorded as running. Returns whether there
+/// was anything to stop. With `show`, reports the stop on stdout; the
+/// daemon's control API passes `false`.
+pub fn stop_service(workspace: &str, project: &str, service: &str, show: bool) -> Result<bool> {
+    match svc_state::remove(workspace, project, service)? {
+        Some(state) => {
+            let report = finish_stop(workspace, project, service, &state)?;
+            if show {
+                print_stopped(workspace, project, service, &state, &report);
+            }
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Stop every recorded service of a project, dependents first. Returns
+/// how many were stopped.
+pub fn stop_project(workspace: &str, project: &str, project_dir: &Path) -> Result<usize> {
+    let removed = svc_state::remove_project(workspace, project)?;
+    let targets = in_stop_order(removed.into_iter().collect(), &start_order(project_dir));
+    for (key, state) in &targets {
+        let report = finish_stop(workspace, project, key, state)?;
+        print_stopped(workspace, project, key, state, &report);
+    }
+    Ok(targets.len())
+}
+
+/// Terminate a service already removed from `services.json` and drop its
+/// routes. Removing the entry first tells the daemon's supervisor not to
+/// restart it.
+fn finish_stop(
+    workspace: &str,
+    project: &str,
+    service: &str,
+    state: &svc_state::ServiceState,
+) -> Result<StopReport> {
+    let report = terminate(state.pid, Duration::from_secs(stop_timeout(state)), state.port)?;
+    // Remove the route entries (canonical + default-workspace alias).
+    let _ = routes::unregister_service(workspace, project, service);
+    Ok(report)
+}
 
-    let count = targets.len();
-    for (key, state) in targets {
-        terminate(state.pid)?;
-        // Remove the route entries (canonical + default-workspace alias).
-        let _ = routes::unregister_service(&workspace, &project_name, &key);
+fn stop_timeout(state: &svc_state::ServiceState) -> u64 {
+    state.stop_timeout_secs.unwrap_or(DEFAULT_STOP_TIMEOUT_SECS)
+}
+
+fn print_stopped(
+    workspace: &str,
+    project: &str,
+    service: &str,
+    state: &svc_state::ServiceState,
+    report: &StopReport,
+) {
+    println!(
+        "{} stopped {}/{}/{} (pid {})",
+        "✓".green(),
+        workspace,
+        project,
+        service,
+        state.pid
+    );
+    if !report.force_killed.is_empty() {
         println!(
-            "{} stopped {}/{}/{} (pid {})",
-            "✓".green(),
-            workspace,
-            project_name,
-            key,
-            state.pid
+            "  {} force-killed after {}s: {}",
+            "!".yellow(),
+            stop_timeout(state),
+            report.force_killed.join(", ")
         );
     }
-    if count > 1 {
-        println!("({count} services stopped)");
+    if report.port_in_use {
+        println!(
+            "  {} port {} is still in use after stopping {}",
+            "!".yellow(),
+            state.port,
+            service
+        );
     }
+}
 
-    Ok(())
+/// Dependency order from `.proj.yaml`, or empty when it cannot be read
+/// (the project may have been edited since `pm run`).
+fn start_order(project_dir: &Path) -> Vec<String> {
+    load_proj_config(project_dir)
+        .ok()
+        .and_then(|config| service_start_order(&config.services).ok())
+        .unwrap_or_default()
+}
+
+/// Reverse of `start_order`. Services no longer in `.proj.yaml` have no
+/// known dependents and go first; ties are alphabetical.
+fn in_stop_order<T>(mut targets: Vec<(String, T)>, start_order: &[String]) -> Vec<(String, T)> {
+    targets.sort_by(|(a, _), (b, _)| {
+        let rank = |name: &String| start_order.iter().position(|s| s == name);
+        match (rank(a), rank(b)) {
+            (Some(x), Some(y)) => y.cmp(&x),
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, None) => a.cmp(b),
+        }
+    });
+    targets
+}
+
+/// Outcome of [`terminate`].
+#[derive(Debug, Default)]
+struct StopReport {
+    /// `<pid> <command>` of every process that ignored SIGTERM.
+    force_killed: Vec<String>,
+    /// Something still accepts connections on the service's port.
+    port_in_use: bool,
 }
 
 #[cfg(unix)]
-fn terminate(pid: u32) -> Result<()> {
-    use nix::sys::signal::{kill, Signal};
+fn terminate(pid: u32, timeout: Duration, port: u16) -> Result<StopReport> {
+    use nix::sys::signal::{kill, killpg, Signal};
     use nix::unistd::Pid;
 
-    let nix_pid = Pid::from_raw(pid as i32);
-    if !svc_state::pid_alive(pid) {
-        // Already gone.
-        return Ok(());
-    }
+    let leader = Pid::from_raw(pid as i32);
+    let mut report = StopReport::default();
 
-    // SIGTERM first; give it 2s to land before escalating to SIGKILL.
-    let _ = kill(nix_pid, Signal::SIGTERM);
-    let start = std::time::Instant::now();
-    while start.elapsed() < Duration::from_millis(2000) {
-        if !svc_state::pid_alive(pid) {
-            return Ok(());
+    // The daemon starts services with setsid(), so the PID is also the
+    // group ID, and the group outlives its leader: `pnpm dev` may be gone
+    // while the server it started still holds the port. Signal the group
+    // whenever it has members; a PID that never led a group only gets the
+    // single-PID treatment.
+    let group = killpg(leader, None).is_ok().then_some(leader);
+    let send = |signal: Option<Signal>| match group {
+        Some(pgid) => killpg(pgid, signal),
+        None => kill(leader, signal),
+    };
+    let alive = || send(None).is_ok();
+    let wait_gone = |limit: Duration| {
+        let start = std::time::Instant::now();
+        while start.elapsed() < limit {
+            if !alive() {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(50));
         }
-        std::thread::sleep(Duration::from_millis(50));
+        !alive()
+    };
+
+    if alive() {
+        let _ = send(Some(Signal::SIGTERM));
+    }
+    if !wait_gone(timeout) {
+        report.force_killed = match group {
+            Some(pgid) => group_members(pgid.as_raw()),
+            None => vec![pid.to_string()],
+        };
+        let _ = send(Some(Signal::SIGKILL));
+        wait_gone(Duration::from_secs(1));
     }
 
-    // Last resort.
-    let _ = kill(nix_pid, Signal::SIGKILL);
-    Ok(())
+    // Sockets can outlive the process for a moment; allow a short grace.
+    let start = std::time::Instant::now();
+    report.port_in_use = loop {
+        if !port_in_use(port) {
+            break false;
+        }
+        if start.elapsed() > Duration::from_secs(1) {
+            break true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    };
+    Ok(report)
 }
 
 #[cfg(not(unix))]
-fn terminate(_pid: u32) -> Result<()> {
+fn terminate(_pid: u32, _timeout: Duration, _port: u16) -> Result<StopReport> {
     Err(anyhow!("pm stop signaling is Unix-only in v0.4.0"))
 }
+
+fn port_in_use(port: u16) -> bool {
+    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
+    std::net::TcpStream::connect_timeout(&addr, Duration::from_millis(200)).is_ok()
+}
+
+/// `<pid> <command>` for the members of process group `pgid`, via `ps`
+/// (works on both Linux and macOS).
+fn group_members(pgid: i32) -> Vec<String> {
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-o", "pid=", "-o", "pgid=", "-o", "comm="])
+        .output();
+    match output {
+        Ok(out) => parse_group_members(&String::from_utf8_lossy(&out.stdout), pgid),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn parse_group_members(ps_output: &str, pgid: i32) -> Vec<String> {
+    ps_output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let pid = fields.next()?;
+            let group: i32 = fields.next()?.parse().ok()?;
+            let command = fields.collect::<Vec<_>>().join(" ");
+            (group == pgid).then(|| format!("{pid} {command}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stop_order_reverses_start_order() {
+        let start: Vec<String> = ["db", "back", "front"].map(String::from).to_vec();
+        let targets = vec![
+            ("back".to_string(), ()),
+            ("db".to_string(), ()),
+            ("gone".to_string(), ()),
+            ("front".to_string(), ()),
+        ];
+        let names: Vec<String> = in_stop_order(targets, &start)
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(names, vec!["gone", "front", "back", "db"]);
+    }
+
+    #[test]
+    fn parses_ps_group_members() {
+        let ps = "    1     1 /sbin/init\n  420   400 node\n  400   400 pnpm dev\n  999   998 sh\n";
+        assert_eq!(parse_group_members(ps, 400), vec!["420 node", "400 pnpm dev"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminate_kills_the_whole_process_group() {
+        use std::os::unix::process::CommandExt;
+
+        // A leader that ignores SIGTERM, with a grandchild that does too.
+        let mut cmd = std::process::Command::new("/bin/sh");
+        cmd.args(["-c", "trap '' TERM; sh -c 'trap \"\" TERM; sleep 30' & sleep 30"]);
+        unsafe {
+            cmd.pre_exec(|| {
+                nix::unistd::setsid().map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+                Ok(())
+            });
+        }
+        let mut child = cmd.spawn().unwrap();
+        let pid = child.id();
+        // Reap the leader as soon as it dies, like the daemon does.
+        let reaper = std::thread::spawn(move || child.wait());
+        std::thread::sleep(Duration::from_millis(200));
+
+        let report = terminate(pid, Duration::from_millis(300), 1).unwrap();
+        assert!(!report.force_killed.is_empty());
+        assert!(
+            report.force_killed.iter().any(|p| p.starts_with(&pid.to_string())),
+            "{:?}",
+            report.force_killed
+        );
+        reaper.join().unwrap().unwrap();
+        // Orphaned grandchildren are reaped by init, which may take a moment.
+        let pgid = nix::unistd::Pid::from_raw(pid as i32);
+        let start = std::time::Instant::now();
+        while nix::sys::signal::killpg(pgid, None).is_ok() {
+            assert!(start.elapsed() < Duration::from_secs(3), "group still alive");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn terminate_reaches_children_after_the_leader_exited() {
+        use std::os::unix::process::CommandExt;
+
+        // `pnpm dev` style: the leader starts a server and exits first.
+        let mut cmd = std::process::Command::new("/bin/sh");
+        cmd.args(["-c", "sleep 30 & exit 0"]);
+        unsafe {
+            cmd.pre_exec(|| {
+                nix::unistd::setsid().map_err(|e| std::io::Error::from_raw_os_error(e as i32))?;
+                Ok(())
+            });
+        }
+        let mut child = cmd.spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let pgid = nix::unistd::Pid::from_raw(pid as i32);
+        assert!(!svc_state::pid_alive(pid));
+        assert!(nix::sys::signal::killpg(pgid, None).is_ok(), "the child should still run");
+
+        let report = terminate(pid, Duration::from_secs(2), 1).unwrap();
+        assert!(report.force_killed.is_empty(), "{:?}", report.force_killed);
+        let start = std::time::Instant::now();
+        while nix::sys::signal::killpg(pgid, None).is_ok() {
+            assert!(start.elapsed() < Duration::from_secs(3), "group still alive");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+}
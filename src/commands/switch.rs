@@ -1,38 +1,30 @@
 use crate::error::PmError;
 use crate::restore::{can_prompt, prompt_yes_no, restore_project};
-use crate::state::{find_project_mut, load_state, parse_target, project_path, save_state};
+use crate::state::{
+    find_project, find_project_mut, load_state, parse_target, project_path, update_state,
+};
 use anyhow::Result;
 use chrono::Utc;
 
 pub fn run(target: String) -> Result<()> {
-    let (mut config, mut manifest) = load_state()?;
+    let (config, manifest) = load_state()?;
     let (workspace_name, project_name) = parse_target(target);
 
-    if let Some(workspace_name) = workspace_name {
-        if !manifest
+    if let Some(workspace_name) = workspace_name
+        && !manifest
             .workspaces
             .iter()
             .any(|ws| ws.name == workspace_name)
-        {
-            return Err(PmError::WorkspaceNotFound(workspace_name).into());
-        }
-        config.current_workspace = workspace_name;
+    {
+        return Err(PmError::WorkspaceNotFound(workspace_name).into());
     }
 
-    let project_snapshot = {
-        let project = find_project_mut(&mut manifest, &project_name)?;
-        project.last_accessed = Utc::now();
-        project.access_count += 1;
-        config.current_workspace = project.workspace.clone();
-        config.current_project = Some(project.name.clone());
-        project.clone()
-    };
-    let initial_path = project_path(&config, &manifest, &project_snapshot)?;
+    let project = find_project(&manifest, &project_name)?.clone();
+    let initial_path = project_path(&config, &manifest, &project)?;
 
     let final_path = if initial_path.exists() {
         initial_path
     } else {
-        let project = find_project_mut(&mut manifest, &project_name)?.clone();
         if project.remote.is_none() {
             return Err(PmError::ProjectMissing(project.name.clone()).into());
         }
@@ -55,7 +47,15 @@ pub fn run(target: String) -> Result<()> {
         restore_project(&config, &manifest, &project)?
     };
 
-    save_state(&config, &manifest)?;
+    // Locked only for the update: restoring above may clone.
+    update_state(|config, manifest| {
+        let project = find_project_mut(manifest, &project_name)?;
+        project.last_accessed = Utc::now();
+        project.access_count += 1;
+        config.current_workspace = project.workspace.clone();
+        config.current_project = Some(project.name.clone());
+        Ok(())
+    })?;
     println!("{}", final_path.display());
     Ok(())
 }
@@ -50,15 +50,15 @@ pub fn run(workspace: Option<String>, yes: bool, jobs: usize) -> Result<()> {
         }
 
         if is_git_repo(&path.display().to_string()) {
-            if let Some(remote) = &project.remote {
-                if !remote_matches(&path, remote) {
-                    println!(
-                        "{} '{}' has remote mismatch at {}",
-                        "✗".red(),
-                        project.name,
-                        path.display()
-                    );
-                }
+            if let Some(remote) = &project.remote
+                && !remote_matches(&path, remote)
+            {
+                println!(
+                    "{} '{}' has remote mismatch at {}",
+                    "✗".red(),
+                    project.name,
+                    path.display()
+                );
             }
         } else {
             println!(
@@ -111,11 +111,11 @@ pub fn run(workspace: Option<String>, yes: bool, jobs: usize) -> Result<()> {
                     break;
                 };
 
-                if let Some(parent) = task.path.parent() {
-                    if let Err(err) = std::fs::create_dir_all(parent) {
-                        messages.push(format!("✗ '{}' failed: {}", task.name, err));
-                        continue;
-                    }
+                if let Some(parent) = task.path.parent()
+                    && let Err(err) = std::fs::create_dir_all(parent)
+                {
+                    messages.push(format!("✗ '{}' failed: {}", task.name, err));
+                    continue;
                 }
 
                 match clone_repo(&task.remote, &task.path) {
@@ -69,7 +69,7 @@ pub fn run() -> Result<()> {
     // Atomic-ish replace: rename old, copy new, remove old
     let backup = current_exe.with_extension("old");
     fs::rename(&current_exe, &backup)?;
-    match fs::copy(&new_bin, &current_exe) {
+    match fs::copy(new_bin, &current_exe) {
         Ok(_) => {
             let _ = fs::remove_file(&backup);
             // Preserve execute permission
@@ -1,9 +1,10 @@
 use crate::error::PmError;
-use crate::state::{load_state, save_state};
+use crate::state::{load_state, lock_state, save_state};
 use anyhow::Result;
 use colored::Colorize;
 
 pub fn run(workspace: String) -> Result<()> {
+    let _lock = lock_state()?;
     let (mut config, manifest) = load_state()?;
 
     if !manifest.workspaces.iter().any(|ws| ws.name == workspace) {
@@ -3,8 +3,8 @@ use crate::error::PmError;
 use crate::git::set_git_config;
 use crate::models::Workspace;
 use crate::state::{
-    find_project, find_workspace, find_workspace_mut, load_state, normalized_workspace_root,
-    project_path, save_state,
+    find_project, find_workspace, find_workspace_mut, load_state, lock_state,
+    normalized_workspace_root, project_path, save_state, update_state,
 };
 use anyhow::Result;
 use colored::Colorize;
@@ -39,7 +39,7 @@ pub fn run(cmd: WorkspaceCommand) -> Result<()> {
 
 fn list() -> Result<()> {
     let (config, manifest) = load_state()?;
-    println!("  {:<12} {:<8} {}", "NAME", "PROJECTS", "ROOT");
+    println!("  {:<12} {:<8} ROOT", "NAME", "PROJECTS");
 
     for ws in &manifest.workspaces {
         if ws.is_system() {
@@ -66,6 +66,7 @@ fn list() -> Result<()> {
 }
 
 fn new(name: String, root: Option<String>) -> Result<()> {
+    let _lock = lock_state()?;
     let (mut config, mut manifest) = load_state()?;
     let name_regex = Regex::new(r"^[a-zA-Z][a-zA-Z0-9_-]*$")?;
     if !name_regex.is_match(&name) {
@@ -90,7 +91,7 @@ fn new(name: String, root: Option<String>) -> Result<()> {
 }
 
 fn remove(name: String, force: bool, recursive: bool) -> Result<()> {
-    let (mut config, mut manifest) = load_state()?;
+    let (config, manifest) = load_state()?;
     if name == "default" {
         return Err(PmError::CannotRemoveDefault.into());
     }
@@ -139,32 +140,36 @@ fn remove(name: String, force: bool, recursive: bool) -> Result<()> {
                 }
             }
         }
-        manifest
-            .projects
-            .retain(|project| project.workspace != name);
-    } else if force {
-        manifest
-            .projects
-            .retain(|project| project.workspace != name);
-    } else {
-        for project in &mut manifest.projects {
-            if project.workspace == name {
-                project.workspace = ".trash".to_string();
+    }
+
+    // Locked only now, so the prompt and the deletion do not hold up
+    // other pm commands.
+    update_state(|config, manifest| {
+        if recursive || force {
+            manifest
+                .projects
+                .retain(|project| project.workspace != name);
+        } else {
+            for project in &mut manifest.projects {
+                if project.workspace == name {
+                    project.workspace = ".trash".to_string();
+                }
             }
         }
-    }
 
-    manifest.workspaces.retain(|ws| ws.name != name);
-    if config.current_workspace == name {
-        config.current_workspace = "default".to_string();
-        config.current_project = None;
-    }
-    save_state(&config, &manifest)?;
+        manifest.workspaces.retain(|ws| ws.name != name);
+        if config.current_workspace == name {
+            config.current_workspace = "default".to_string();
+            config.current_project = None;
+        }
+        Ok(())
+    })?;
     println!("{} Removed workspace '{}'", "✓".green(), name.cyan());
     Ok(())
 }
 
 fn move_projects(projects: Vec<String>, workspace: String) -> Result<()> {
+    let _lock = lock_state()?;
     let (config, mut manifest) = load_state()?;
     let _ = find_workspace(&manifest, &workspace)?;
 
@@ -194,6 +199,7 @@ fn config(
     list: bool,
     unset: Option<String>,
 ) -> Result<()> {
+    let _lock = lock_state()?;
     let (config, mut manifest) = load_state()?;
     let ws = find_workspace_mut(&mut manifest, &workspace)?;
 
@@ -288,6 +294,7 @@ fn root(command: WorkspaceRootCommand) -> Result<()> {
 }
 
 fn set_root(workspace: String, path: String) -> Result<()> {
+    let _lock = lock_state()?;
     let (config, mut manifest) = load_state()?;
     let ws = find_workspace_mut(&mut manifest, &workspace)?;
     ws.root = Some(normalized_workspace_root(&path)?);
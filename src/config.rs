@@ -1,4 +1,5 @@
 use crate::error::PmError;
+use crate::lock;
 use crate::models::{
     Config, HistoryData, LegacyProjectsData, LegacyWorkspacesData, Manifest, PortsData, Project,
     RepoSpec, SharedInfra, Workspace,
@@ -69,10 +70,19 @@ pub fn services_state_path() -> PathBuf {
     config_dir().join("services.json")
 }
 
+pub fn tls_dir() -> PathBuf {
+    config_dir().join("tls")
+}
+
 pub fn logs_dir() -> PathBuf {
     config_dir().join("logs")
 }
 
+/// Log file of an orchestrated service; rotated copies sit next to it.
+pub fn service_log_path(workspace: &str, project: &str, service: &str) -> PathBuf {
+    logs_dir().join(format!("{workspace}_{project}_{service}.log"))
+}
+
 pub fn repo_specs_dir() -> PathBuf {
     config_dir().join("repo-specs")
 }
@@ -107,9 +117,7 @@ pub fn load_config() -> Result<Config> {
 }
 
 pub fn save_config(config: &Config) -> Result<()> {
-    let content = serde_json::to_string_pretty(config)?;
-    fs::write(config_path(), content)?;
-    Ok(())
+    lock::write_atomic(&config_path(), &serde_json::to_string_pretty(config)?)
 }
 
 // ──────────────────────────────────────────────
@@ -147,9 +155,7 @@ pub fn load_ports() -> Result<PortsData> {
 }
 
 pub fn save_ports(ports: &PortsData) -> Result<()> {
-    let content = serde_json::to_string_pretty(ports)?;
-    fs::write(ports_path(), content)?;
-    Ok(())
+    lock::write_atomic(&ports_path(), &serde_json::to_string_pretty(ports)?)
 }
 
 fn migrate_ports_v1_to_v2(raw: &Value) -> Result<PortsData> {
@@ -238,9 +244,7 @@ pub fn load_manifest() -> Result<Manifest> {
 }
 
 pub fn save_manifest(manifest: &Manifest) -> Result<()> {
-    let content = serde_json::to_string_pretty(manifest)?;
-    fs::write(manifest_path(), content)?;
-    Ok(())
+    lock::write_atomic(&manifest_path(), &serde_json::to_string_pretty(manifest)?)
 }
 
 pub fn load_history() -> Result<HistoryData> {
@@ -255,9 +259,7 @@ pub fn load_history() -> Result<HistoryData> {
 }
 
 pub fn save_history(history: &HistoryData) -> Result<()> {
-    let content = serde_json::to_string_pretty(history)?;
-    fs::write(history_path(), content)?;
-    Ok(())
+    lock::write_atomic(&history_path(), &serde_json::to_string_pretty(history)?)
 }
 
 pub fn save_all(config: &Config, manifest: &Manifest) -> Result<()> {
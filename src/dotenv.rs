@@ -0,0 +1,217 @@
+//! `.env` parsing and `${…}` interpolation for service environments.
+//!
+//! The accepted `.env` syntax is the common subset understood by
+//! docker-compose and the dotenv libraries:
+//!
+//! ```text
+//! # comment
+//! export RUST_LOG=debug          # `export ` prefix is ignored
+//! API_URL=http://localhost:${port:back}
+//! GREETING="hello\nworld"        # double quotes: \n \t \" \\ escapes, may span lines
+//! RAW='${not interpolated}'      # single quotes: literal
+//! ```
+//!
+//! Interpolation replaces `${NAME}` with a variable and `${port:svc}` with
+//! the allocated port of sibling service `svc`; `$$` is a literal `$`. A
+//! `$` not followed by `{` or `$` is kept as is, so values such as
+//! passwords rarely need escaping. Unknown names are an error rather than
+//! silently becoming empty.
+
+use anyhow::{Result, anyhow};
+
+/// One `KEY=value` line. `literal` values (single-quoted) must not be
+/// interpolated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub literal: bool,
+}
+
+/// Parse the contents of a `.env` file.
+pub fn parse(content: &str) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut lines = content.lines().enumerate();
+    while let Some((idx, raw)) = lines.next() {
+        let line_no = idx + 1;
+        let line = raw.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").map(str::trim_start).unwrap_or(line);
+        let (key, rest) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow!("line {line_no}: expected KEY=value"))?;
+        let key = key.trim();
+        if !is_valid_name(key) {
+            return Err(anyhow!("line {line_no}: invalid variable name '{key}'"));
+        }
+        let rest = rest.trim_start();
+
+        let (value, literal) = if let Some(body) = rest.strip_prefix('\'') {
+            let end = body
+                .find('\'')
+                .ok_or_else(|| anyhow!("line {line_no}: unterminated single quote"))?;
+            (body[..end].to_string(), true)
+        } else if let Some(body) = rest.strip_prefix('"') {
+            // Double-quoted values may continue on following lines.
+            let mut text = body.to_string();
+            loop {
+                if let Some(value) = unescape_double_quoted(&text) {
+                    break (value, false);
+                }
+                match lines.next() {
+                    Some((_, next)) => {
+                        text.push('\n');
+                        text.push_str(next);
+                    }
+                    None => return Err(anyhow!("line {line_no}: unterminated double quote")),
+                }
+            }
+        } else {
+            // Unquoted: an inline comment needs whitespace before `#`.
+            let value = match rest.find(" #").or_else(|| rest.find("\t#")) {
+                Some(idx) => &rest[..idx],
+                None => rest,
+            };
+            (value.trim_end().to_string(), false)
+        };
+        entries.push(Entry { key: key.to_string(), value, literal });
+    }
+    Ok(entries)
+}
+
+/// Unescape a double-quoted body up to its closing quote; `None` when the
+/// closing quote has not been seen yet.
+fn unescape_double_quoted(body: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut chars = body.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                // Keep `\$` escaped so interpolation sees a literal `$`.
+                '$' => out.push_str("$$"),
+                other @ ('"' | '\\') => out.push(other),
+                other => {
+                    out.push('\\');
+                    out.push(other);
+                }
+            },
+            c => out.push(c),
+        }
+    }
+    None
+}
+
+/// Replace `${NAME}` / `${port:svc}` references using `lookup`.
+pub fn interpolate(value: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String> {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(idx) = rest.find('$') {
+        out.push_str(&rest[..idx]);
+        let after = &rest[idx + 1..];
+        if let Some(tail) = after.strip_prefix('$') {
+            out.push('$');
+            rest = tail;
+        } else if let Some(body) = after.strip_prefix('{') {
+            let end = body
+                .find('}')
+                .ok_or_else(|| anyhow!("unterminated '${{' in '{value}'"))?;
+            let name = &body[..end];
+            let resolved = lookup(name).ok_or_else(|| match name.strip_prefix("port:") {
+                Some(svc) => anyhow!("${{{name}}}: service '{svc}' has no allocated port"),
+                None => anyhow!("${{{name}}} is not defined"),
+            })?;
+            out.push_str(&resolved);
+            rest = &body[end + 1..];
+        } else {
+            out.push('$');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, value: &str, literal: bool) -> Entry {
+        Entry { key: key.into(), value: value.into(), literal }
+    }
+
+    #[test]
+    fn parses_common_dotenv_syntax() {
+        let content = r#"
+# comment
+export RUST_LOG=debug
+EMPTY=
+URL=http://x/#frag   # trailing comment
+RAW='${HOME} # kept'
+MSG="a \"b\"\tc\n"
+MULTI="line one
+line two"
+"#;
+        assert_eq!(
+            parse(content).unwrap(),
+            vec![
+                entry("RUST_LOG", "debug", false),
+                entry("EMPTY", "", false),
+                entry("URL", "http://x/#frag", false),
+                entry("RAW", "${HOME} # kept", true),
+                entry("MSG", "a \"b\"\tc\n", false),
+                entry("MULTI", "line one\nline two", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(parse("NOEQUALS").is_err());
+        assert!(parse("1BAD=x").is_err());
+        assert!(parse("A='open").is_err());
+        let err = parse("A=ok\nB=\"open\n").unwrap_err().to_string();
+        assert!(err.contains("line 2"), "{err}");
+    }
+
+    #[test]
+    fn interpolates_variables_and_ports() {
+        let lookup = |name: &str| match name {
+            "DATABASE_URL" => Some("postgres://db".to_string()),
+            "port:back" => Some("3001".to_string()),
+            _ => None,
+        };
+        assert_eq!(
+            interpolate("${DATABASE_URL}?x=1", lookup).unwrap(),
+            "postgres://db?x=1"
+        );
+        assert_eq!(
+            interpolate("http://localhost:${port:back}/api", lookup).unwrap(),
+            "http://localhost:3001/api"
+        );
+        assert_eq!(interpolate("pa$$word $x", lookup).unwrap(), "pa$word $x");
+        assert!(interpolate("${MISSING}", lookup).is_err());
+        let err = interpolate("${port:front}", lookup).unwrap_err().to_string();
+        assert!(err.contains("service 'front'"), "{err}");
+        assert!(interpolate("${OPEN", lookup).is_err());
+    }
+
+    #[test]
+    fn escaped_dollar_in_double_quotes_stays_literal() {
+        let entries = parse(r#"A="\${HOME}""#).unwrap();
+        assert_eq!(interpolate(&entries[0].value, |_| None).unwrap(), "${HOME}");
+    }
+}
@@ -77,6 +77,12 @@ pub enum PmError {
     #[error("Git error: {0}")]
     Git(#[from] git2::Error),
 
+    #[error("Hostname '{0}' is already routed to {1}; stop it or change `hostnames:`")]
+    HostnameInUse(String, String),
+
+    #[error("Timed out waiting for the lock on {0}; is another pm process stuck?")]
+    LockTimeout(String),
+
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
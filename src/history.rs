@@ -1,4 +1,5 @@
-use crate::config::{load_history, save_history};
+use crate::config::{history_path, load_history, save_history};
+use crate::lock;
 use crate::models::{HistoryAction, HistoryData, HistoryEntry, HistoryProjectSnapshot, Project};
 use crate::path::collapse_path;
 use chrono::Utc;
@@ -9,6 +10,7 @@ pub fn record_project_event(
     resolved_path: &Path,
     action: HistoryAction,
 ) -> anyhow::Result<()> {
+    let _lock = lock::lock(&history_path())?;
     let mut history = load_history()?;
     history.entries.push(HistoryEntry {
         timestamp: Utc::now(),
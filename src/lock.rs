@@ -0,0 +1,282 @@
+//! Cross-process locking for pm's state files.
+//!
+//! Several pm processes often touch the same files at once: two `pm run`s
+//! started by a script both allocate ports and register services and
+//! routes, while the daemon's supervisor records exits in
+//! `services.json`. Every write therefore happens under an advisory lock
+//! (`flock` on Unix, `LockFileEx` on Windows) on a `<file>.lock` sidecar —
+//! the state file itself cannot carry the lock, since each write replaces
+//! it with a renamed temp file.
+//!
+//! A load-modify-save cycle must hold the lock for the whole cycle, not
+//! just the save, or concurrent writers lose each other's changes:
+//!
+//! ```ignore
+//! let _lock = lock::lock(&services_state_path())?;
+//! let mut data = load()?;
+//! data.projects.insert(..);
+//! save(&data)?; // takes the same lock again: fine, see below
+//! ```
+//!
+//! Locks are re-entrant within a thread, so `save` functions can lock
+//! for themselves and still be called by a caller that holds the lock.
+//! The nesting is tracked per thread, so guards cannot move to another
+//! thread ([`StateLock`] is not `Send`).
+//! Readers do not lock: writes are atomic renames, so a reader sees the
+//! old file or the new one. Waiting for a lock gives up after
+//! [`LOCK_TIMEOUT`] rather than hanging behind a stuck process.
+
+use crate::error::PmError;
+use anyhow::{Context, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long to wait for another process to release a state file.
+pub const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Delay between attempts while a lock is held elsewhere.
+const RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+thread_local! {
+    /// Lock files this thread holds, with their nesting depth.
+    static HELD: RefCell<HashMap<PathBuf, usize>> = RefCell::new(HashMap::new());
+}
+
+/// Exclusive lock on a state file, released on drop.
+#[must_use = "the lock is released as soon as this guard is dropped"]
+pub struct StateLock {
+    lock_path: PathBuf,
+    /// Only the outermost guard of a thread owns the locked file.
+    file: Option<File>,
+    /// Dropping the guard updates this thread's [`HELD`] entry, so it must
+    /// be dropped on the thread that took it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for StateLock {
+    fn drop(&mut self) {
+        HELD.with(|held| {
+            let mut held = held.borrow_mut();
+            if let Some(depth) = held.get_mut(&self.lock_path) {
+                *depth -= 1;
+                if *depth == 0 {
+                    held.remove(&self.lock_path);
+                }
+            }
+        });
+        // Closing the file releases the lock.
+        self.file.take();
+    }
+}
+
+/// Lock `path` (a state file such as `services.json`) against writers in
+/// other processes and threads, waiting up to [`LOCK_TIMEOUT`].
+pub fn lock(path: &Path) -> Result<StateLock> {
+    lock_with_timeout(path, LOCK_TIMEOUT)
+}
+
+pub fn lock_with_timeout(path: &Path, timeout: Duration) -> Result<StateLock> {
+    let lock_path = lock_path(path);
+    let nested = HELD.with(|held| match held.borrow_mut().get_mut(&lock_path) {
+        Some(depth) => {
+            *depth += 1;
+            true
+        }
+        None => false,
+    });
+    if nested {
+        return Ok(StateLock { lock_path, file: None, _not_send: PhantomData });
+    }
+
+    if let Some(parent) = lock_path.parent() {
+        fs::create_dir_all(parent).ok();
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .with_context(|| format!("opening {}", lock_path.display()))?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                std::thread::sleep(RETRY_INTERVAL);
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(PmError::LockTimeout(path.display().to_string()).into());
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(e).with_context(|| format!("locking {}", lock_path.display()));
+            }
+        }
+    }
+    HELD.with(|held| held.borrow_mut().insert(lock_path.clone(), 1));
+    Ok(StateLock { lock_path, file: Some(file), _not_send: PhantomData })
+}
+
+/// Replace `path` with `contents` under its lock: write a temp file, then
+/// rename it over the original.
+pub fn write_atomic(path: &Path, contents: &str) -> Result<()> {
+    replace(path, contents, OpenOptions::new())
+}
+
+/// [`write_atomic`] for secrets: the file is readable by the owner only
+/// from the moment it is created.
+#[cfg(unix)]
+pub fn write_atomic_private(path: &Path, contents: &str) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut options = OpenOptions::new();
+    options.mode(0o600);
+    replace(path, contents, options)
+}
+
+fn replace(path: &Path, contents: &str, mut options: OpenOptions) -> Result<()> {
+    use std::io::Write;
+    let _lock = lock(path)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    // A temp file left by a crashed writer would keep its old mode.
+    let _ = fs::remove_file(&tmp);
+    let mut file = options
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("writing {}", tmp.display()))?;
+    drop(file);
+    fs::rename(&tmp, path)
+        .with_context(|| format!("renaming {} -> {}", tmp.display(), path.display()))?;
+    Ok(())
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Barrier};
+
+    #[test]
+    fn concurrent_writers_do_not_lose_updates() {
+        const WRITERS: usize = 8;
+        const UPDATES: usize = 25;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.json");
+        fs::write(&path, "{}").unwrap();
+
+        let barrier = Arc::new(Barrier::new(WRITERS));
+        let writers: Vec<_> = (0..WRITERS)
+            .map(|w| {
+                let (path, barrier) = (path.clone(), barrier.clone());
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    for u in 0..UPDATES {
+                        // The same load-modify-save cycle as `services::upsert`.
+                        let _lock = lock(&path).unwrap();
+                        let mut data: BTreeMap<String, usize> =
+                            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+                        data.insert(format!("writer{w}-{u}"), u);
+                        write_atomic(&path, &serde_json::to_string(&data).unwrap()).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let data: BTreeMap<String, usize> =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(data.len(), WRITERS * UPDATES);
+    }
+
+    #[test]
+    fn gives_up_after_the_timeout() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ports.json");
+        let _held = lock(&path).unwrap();
+
+        let other = path.clone();
+        let err = std::thread::spawn(move || {
+            lock_with_timeout(&other, Duration::from_millis(50)).map(|_| ())
+        })
+        .join()
+        .unwrap()
+        .unwrap_err();
+        assert!(matches!(err.downcast_ref::<PmError>(), Some(PmError::LockTimeout(_))));
+    }
+
+    #[test]
+    fn nested_locks_in_one_thread_do_not_deadlock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("routes.json");
+        let outer = lock(&path).unwrap();
+        write_atomic(&path, "{}").unwrap();
+        drop(outer);
+
+        // Released: another thread can take it now.
+        let other = path.clone();
+        std::thread::spawn(move || lock_with_timeout(&other, Duration::from_millis(50)).map(|_| ()))
+            .join()
+            .unwrap()
+            .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}");
+    }
+
+    /// Another process holding the lock (here: [`hold_lock_entry`] in a
+    /// child test process) keeps this one out until it exits.
+    #[test]
+    fn excludes_other_processes() {
+        use std::io::{BufRead, BufReader};
+        use std::process::{Command, Stdio};
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("services.json");
+        let mut child = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "lock::tests::hold_lock_entry", "--test-threads=1", "--nocapture"])
+            .env("PM_TEST_LOCK_HOLD", &path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        while line.trim() != "locked" {
+            line.clear();
+            assert!(stdout.read_line(&mut line).unwrap() > 0, "child exited before locking");
+        }
+
+        let err = lock_with_timeout(&path, Duration::from_millis(100)).map(|_| ()).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PmError>(), Some(PmError::LockTimeout(_))));
+
+        drop(child.stdin.take());
+        assert!(child.wait().unwrap().success());
+        let _relocked = lock_with_timeout(&path, Duration::from_millis(100)).unwrap();
+    }
+
+    /// Holds the lock on `PM_TEST_LOCK_HOLD` until stdin closes; a no-op
+    /// unless started by [`excludes_other_processes`].
+    #[test]
+    fn hold_lock_entry() {
+        use std::io::Read;
+        let Ok(path) = std::env::var("PM_TEST_LOCK_HOLD") else {
+            return;
+        };
+        let _lock = lock(Path::new(&path)).unwrap();
+        println!("\nlocked");
+        let _ = std::io::stdin().read_to_end(&mut Vec::new());
+    }
+}
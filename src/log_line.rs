@@ -0,0 +1,182 @@
+//! Line format of orchestrated service logs.
+//!
+//! Each service's stdout and stderr are pipes into `pm __log-pipe`
+//! processes ([`copy_to_log`]), which write every line to the service log
+//! as
+//!
+//! ```text
+//! 2026-10-18T09:12:01.123Z out listening on 127.0.0.1:3001
+//! 2026-10-18T09:12:01.456Z err warning: unused variable `x`
+//! 2026-10-18T09:12:07.002Z pm process exited with code 1; restart policy is 'never'
+//! ```
+//!
+//! i.e. an RFC 3339 UTC timestamp, the stream (`out`, `err`, or `pm` for
+//! notes written by pm itself) and the text. Readers show only the text
+//! by default ([`display`]); lines in any other shape (logs written before
+//! this format, or by hand) are passed through unchanged.
+
+use chrono::{DateTime, FixedOffset, SecondsFormat, Utc};
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+    /// Written by pm (exits, restarts), not by the service.
+    Pm,
+}
+
+impl Stream {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stream::Stdout => "out",
+            Stream::Stderr => "err",
+            Stream::Pm => "pm",
+        }
+    }
+
+    pub fn parse(tag: &str) -> Option<Self> {
+        match tag {
+            "out" => Some(Stream::Stdout),
+            "err" => Some(Stream::Stderr),
+            "pm" => Some(Stream::Pm),
+            _ => None,
+        }
+    }
+}
+
+/// A parsed log line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine<'a> {
+    pub at: DateTime<FixedOffset>,
+    pub stream: Stream,
+    pub text: &'a str,
+}
+
+/// Format one line (without the trailing newline).
+pub fn format(at: DateTime<Utc>, stream: Stream, text: &str) -> String {
+    format!(
+        "{} {} {}",
+        at.to_rfc3339_opts(SecondsFormat::Millis, true),
+        stream.as_str(),
+        text
+    )
+}
+
+/// Append a `pm` note to the log at `path`, best effort.
+pub fn append_note(path: &Path, text: &str) {
+    if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{}", format(Utc::now(), Stream::Pm, text));
+    }
+}
+
+/// Copy `input` into the log at `path` until it ends, one timestamped
+/// line per write so stdout and stderr lines never interleave mid-line.
+/// The body of `pm __log-pipe`, which outlives the daemon that started it.
+pub fn copy_to_log(input: impl Read, stream: Stream, path: &Path) -> std::io::Result<()> {
+    let mut log = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut reader = BufReader::new(input);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(());
+        }
+        let text = String::from_utf8_lossy(&buf);
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        log.write_all((format(Utc::now(), stream, text) + "\n").as_bytes())?;
+    }
+}
+
+/// Entry point of `pm __log-pipe <out|err> <log>`: copy stdin into `log`.
+pub fn run_pipe(stream: &str, log: &Path) -> anyhow::Result<()> {
+    let stream = Stream::parse(stream)
+        .filter(|s| *s != Stream::Pm)
+        .ok_or_else(|| anyhow::anyhow!("unknown stream '{stream}' (expected out or err)"))?;
+    copy_to_log(std::io::stdin().lock(), stream, log)?;
+    Ok(())
+}
+
+/// Split a stored line into its parts; `None` if it is not in pm's format.
+pub fn parse(line: &str) -> Option<LogLine<'_>> {
+    let (ts, rest) = line.split_once(' ')?;
+    let at = DateTime::parse_from_rfc3339(ts).ok()?;
+    let (tag, text) = rest.split_once(' ').unwrap_or((rest, ""));
+    Some(LogLine {
+        at,
+        stream: Stream::parse(tag)?,
+        text,
+    })
+}
+
+/// What the service printed, without pm's timestamp and stream tag.
+pub fn message(line: &str) -> &str {
+    parse(line).map_or(line, |l| l.text)
+}
+
+/// A stored line as shown to developers: the bare text (pm's own notes
+/// marked `[pm]`), or the full line with `timestamps`.
+pub fn display(line: &str, timestamps: bool) -> String {
+    match parse(line) {
+        Some(_) if timestamps => line.to_string(),
+        Some(LogLine { stream: Stream::Pm, text, .. }) => format!("[pm] {text}"),
+        Some(parsed) => parsed.text.to_string(),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn format_and_parse_round_trip() {
+        let at = Utc.with_ymd_and_hms(2026, 10, 18, 9, 12, 1).unwrap();
+        let line = format(at, Stream::Stderr, "warning: x  y");
+        assert_eq!(line, "2026-10-18T09:12:01.000Z err warning: x  y");
+        let parsed = parse(&line).unwrap();
+        assert_eq!(parsed.at, at);
+        assert_eq!(parsed.stream, Stream::Stderr);
+        assert_eq!(parsed.text, "warning: x  y");
+        assert_eq!(parse("2026-10-18T09:12:01.000Z out").unwrap().text, "");
+    }
+
+    #[test]
+    fn copy_to_log_stamps_each_line() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("svc.log");
+        std::fs::write(&path, "earlier\n").unwrap();
+        copy_to_log(&b"one\r\ntwo\nno newline"[..], Stream::Stderr, &path).unwrap();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines[0], "earlier");
+        let texts: Vec<_> = lines[1..].iter().map(|l| parse(l).unwrap()).collect();
+        assert!(texts.iter().all(|l| l.stream == Stream::Stderr));
+        let texts: Vec<_> = texts.iter().map(|l| l.text).collect();
+        assert_eq!(texts, vec!["one", "two", "no newline"]);
+    }
+
+    #[test]
+    fn foreign_lines_pass_through() {
+        assert!(parse("listening on :3000").is_none());
+        assert!(parse("2026-10-18T09:12:01Z INFO started").is_none());
+        assert_eq!(message("plain"), "plain");
+        assert_eq!(display("plain", true), "plain");
+    }
+
+    #[test]
+    fn display_hides_metadata_unless_asked() {
+        let line = "2026-10-18T09:12:01.000Z out ready";
+        assert_eq!(display(line, false), "ready");
+        assert_eq!(display(line, true), line);
+        assert_eq!(
+            display("2026-10-18T09:12:01.000Z pm process exited", false),
+            "[pm] process exited"
+        );
+    }
+}
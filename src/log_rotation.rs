@@ -64,6 +64,16 @@ pub fn rotate(path: &Path, keep: usize) -> Result<()> {
     Ok(())
 }
 
+/// Rotated files of `path` that exist, oldest first (`.log.<KEEP>` …
+/// `.log.1`). The live file itself is not included.
+pub fn rotated_files(path: &Path, keep: usize) -> Vec<PathBuf> {
+    (1..=keep)
+        .rev()
+        .map(|n| numbered(path, n))
+        .filter(|p| p.exists())
+        .collect()
+}
+
 fn numbered(path: &Path, n: usize) -> PathBuf {
     let mut s = path.as_os_str().to_owned();
     s.push(format!(".{n}"));
@@ -126,6 +136,18 @@ mod tests {
         assert!(!numbered(&p, 4).exists());
     }
 
+    #[test]
+    fn rotated_files_lists_oldest_first() {
+        let dir = TempDir::new().unwrap();
+        let p = dir.path().join("a.log");
+        assert!(rotated_files(&p, 3).is_empty());
+        for _ in 0..2 {
+            write_bytes(&p, 10);
+            rotate(&p, 3).unwrap();
+        }
+        assert_eq!(rotated_files(&p, 3), vec![numbered(&p, 2), numbered(&p, 1)]);
+    }
+
     #[test]
     fn missing_file_is_noop() {
         let dir = TempDir::new().unwrap();
@@ -1,17 +1,23 @@
 mod cli;
 mod commands;
 mod config;
+mod dotenv;
 mod error;
 mod git;
 mod history;
+mod lock;
 mod models;
 mod path;
+mod log_line;
 mod log_rotation;
 mod plugin;
+#[cfg(unix)]
+mod proc_stats;
 mod project;
 mod restore;
 mod routes;
 mod services;
+mod shell_words;
 mod state;
 mod templates;
 
@@ -25,11 +31,11 @@ fn main() -> Result<()> {
         Err(err) => {
             if err.kind() == ErrorKind::InvalidSubcommand {
                 let args: Vec<String> = std::env::args().collect();
-                if let Some(name) = args.get(1) {
-                    if let Some(plugin) = plugin::find_plugin(name) {
-                        let plugin_args: Vec<String> = args.into_iter().skip(2).collect();
-                        return plugin::run_plugin(&plugin, &plugin_args);
-                    }
+                if let Some(name) = args.get(1)
+                    && let Some(plugin) = plugin::find_plugin(name)
+                {
+                    let plugin_args: Vec<String> = args.into_iter().skip(2).collect();
+                    return plugin::run_plugin(&plugin, &plugin_args);
                 }
             }
 
@@ -78,7 +84,8 @@ fn dispatch(cli: Cli) -> Result<()> {
         Commands::Run {
             positional,
             command,
-        } => commands::run::run(positional, command),
+            attach,
+        } => commands::run::run(positional, command, attach),
         Commands::Completion { shell } => commands::completion::run(shell),
         Commands::History { limit } => commands::history::run(limit),
         Commands::Check => commands::check::run(),
@@ -87,8 +94,29 @@ fn dispatch(cli: Cli) -> Result<()> {
         Commands::Db(cmd) => commands::db::run(cmd),
         Commands::Proxy(cmd) => commands::proxy::run(cmd),
         Commands::Daemon { foreground } => commands::proxy::run_daemon(foreground),
+        Commands::LogPipe { stream, log } => log_line::run_pipe(&stream, &log),
         #[cfg(unix)]
-        Commands::Logs { service, project } => commands::logs::run(service, project),
+        Commands::Logs {
+            service,
+            project,
+            lines,
+            since,
+            no_follow,
+            grep,
+            rotated,
+            timestamps,
+        } => commands::logs::run(
+            service,
+            project,
+            commands::logs::LogsOptions {
+                lines,
+                since,
+                no_follow,
+                grep,
+                rotated,
+                timestamps,
+            },
+        ),
         #[cfg(not(unix))]
         Commands::Logs { .. } => Err(anyhow::anyhow!(
             "pm logs is Unix-only in v0.4.0 (orchestrator mode)"
@@ -99,6 +127,18 @@ fn dispatch(cli: Cli) -> Result<()> {
         Commands::Stop { .. } => Err(anyhow::anyhow!(
             "pm stop is Unix-only in v0.4.0 (orchestrator mode)"
         )),
+        #[cfg(unix)]
+        Commands::Restart { service, project } => commands::restart::run(service, project),
+        #[cfg(not(unix))]
+        Commands::Restart { .. } => Err(anyhow::anyhow!(
+            "pm restart is Unix-only in v0.4.0 (orchestrator mode)"
+        )),
+        #[cfg(unix)]
+        Commands::Ps { stats } => commands::ps::run(stats),
+        #[cfg(not(unix))]
+        Commands::Ps { .. } => Err(anyhow::anyhow!(
+            "pm ps is Unix-only in v0.4.0 (orchestrator mode)"
+        )),
         Commands::Upgrade => commands::upgrade::run(),
     }
 }
@@ -52,6 +52,11 @@ pub struct DevConfig {
     #[serde(default = "default_control_port")]
     pub control_port: u16,
 
+    /// Optional HTTPS listener for `*.localhost` routes, terminated with
+    /// certificates from pm's local CA. Disabled when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub https_port: Option<u16>,
+
     #[serde(default = "default_postgres_image")]
     pub postgres_image: String,
 
@@ -85,6 +90,7 @@ impl Default for DevConfig {
             auto_start_docker: default_auto_start_docker(),
             proxy_port: default_proxy_port(),
             control_port: default_control_port(),
+            https_port: None,
             postgres_image: default_postgres_image(),
             redis_image: default_redis_image(),
         }
@@ -7,10 +7,10 @@ pub fn expand_path(path: &str) -> PathBuf {
 
 /// Collapse home directory to ~
 pub fn collapse_path(path: &Path) -> String {
-    if let Some(home) = dirs::home_dir() {
-        if let Ok(stripped) = path.strip_prefix(&home) {
-            return format!("~/{}", stripped.display());
-        }
+    if let Some(home) = dirs::home_dir()
+        && let Ok(stripped) = path.strip_prefix(&home)
+    {
+        return format!("~/{}", stripped.display());
     }
     path.display().to_string()
 }
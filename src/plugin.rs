@@ -159,16 +159,14 @@ pub fn run_plugin(plugin: &Plugin, args: &[String]) -> Result<()> {
             command.env("PM_WORKSPACE", &config.current_workspace);
         }
 
-        if std::env::var_os("PM_PROJECT").is_none() {
-            if let Some(project_name) = &config.current_project {
-                if let Ok(project) = crate::state::find_project(&manifest, project_name) {
-                    if let Ok(path) = project_path(&config, &manifest, project) {
-                        command
-                            .env("PM_CURRENT_PROJECT", &project.name)
-                            .env("PM_CURRENT_PROJECT_PATH", path);
-                    }
-                }
-            }
+        if std::env::var_os("PM_PROJECT").is_none()
+            && let Some(project_name) = &config.current_project
+            && let Ok(project) = crate::state::find_project(&manifest, project_name)
+            && let Ok(path) = project_path(&config, &manifest, project)
+        {
+            command
+                .env("PM_CURRENT_PROJECT", &project.name)
+                .env("PM_CURRENT_PROJECT_PATH", path);
         }
     }
 
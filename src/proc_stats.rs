@@ -0,0 +1,279 @@
+//! Resource usage of running services, read from `/proc` (Linux only).
+//!
+//! A service is its whole process tree: the PID recorded in
+//! `services.json` plus every descendant, so `pnpm dev` is reported
+//! together with the `node` it starts. For each tree we report CPU%
+//! (sampled over a short interval, 100% = one core), resident memory,
+//! thread count and the TCP ports it actually listens on — read from
+//! `/proc/net/tcp{,6}` by matching socket inodes against `/proc/<pid>/fd`.
+
+use crate::services::ServicesData;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How long CPU usage is sampled for.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceStats {
+    pub workspace: String,
+    pub project: String,
+    pub service: String,
+    pub pid: u32,
+    /// Port pm allocated to the service.
+    pub port: u16,
+    /// Processes in the tree (0 when the service is not running).
+    pub processes: usize,
+    pub cpu_percent: f64,
+    pub rss_bytes: u64,
+    pub threads: u64,
+    /// TCP ports some process in the tree is listening on.
+    pub listening: Vec<u16>,
+    /// The service listens, but not on the port pm allocated (and routes
+    /// to): usually a port hard-coded in the dev server config.
+    pub port_mismatch: bool,
+}
+
+/// Per-process figures from `/proc/<pid>/stat`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ProcStat {
+    ppid: u32,
+    /// utime + stime, in clock ticks.
+    cpu_ticks: u64,
+    threads: u64,
+    rss_pages: u64,
+}
+
+/// Stats for every service in `data`. Blocks for `interval` to measure CPU.
+pub fn collect(data: &ServicesData, interval: Duration) -> Result<Vec<ServiceStats>> {
+    if !Path::new("/proc/self/stat").exists() {
+        return Err(anyhow!("per-service stats need /proc (Linux only)"));
+    }
+    let clock_ticks = sysconf(nix::unistd::SysconfVar::CLK_TCK).unwrap_or(100);
+    let page_size = sysconf(nix::unistd::SysconfVar::PAGE_SIZE).unwrap_or(4096);
+
+    let before = read_process_table();
+    let started = Instant::now();
+    std::thread::sleep(interval);
+    let after = read_process_table();
+    let elapsed = started.elapsed().as_secs_f64();
+    let listeners = listening_sockets();
+
+    let mut stats = Vec::new();
+    for (key, services) in &data.projects {
+        let (workspace, project) = key.split_once('/').unwrap_or(("default", key));
+        for (service, state) in services {
+            let tree = process_tree(&after, state.pid);
+            let mut s = ServiceStats {
+                workspace: workspace.to_string(),
+                project: project.to_string(),
+                service: service.clone(),
+                pid: state.pid,
+                port: state.port,
+                processes: tree.len(),
+                cpu_percent: 0.0,
+                rss_bytes: 0,
+                threads: 0,
+                listening: Vec::new(),
+                port_mismatch: false,
+            };
+            let mut ticks = 0;
+            for pid in &tree {
+                let now = &after[pid];
+                // Processes born during the sample count from zero.
+                let then = before.get(pid).map_or(0, |p| p.cpu_ticks);
+                ticks += now.cpu_ticks.saturating_sub(then);
+                s.rss_bytes += now.rss_pages * page_size;
+                s.threads += now.threads;
+            }
+            if elapsed > 0.0 {
+                s.cpu_percent = ticks as f64 / clock_ticks as f64 / elapsed * 100.0;
+            }
+            s.listening = listening_ports(&tree, &listeners);
+            s.port_mismatch = !s.listening.is_empty() && !s.listening.contains(&s.port);
+            stats.push(s);
+        }
+    }
+    stats.sort_by(|a, b| {
+        (&a.workspace, &a.project, &a.service).cmp(&(&b.workspace, &b.project, &b.service))
+    });
+    Ok(stats)
+}
+
+fn sysconf(var: nix::unistd::SysconfVar) -> Option<u64> {
+    nix::unistd::sysconf(var).ok().flatten().map(|v| v as u64)
+}
+
+fn read_process_table() -> HashMap<u32, ProcStat> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| {
+            let content = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+            Some((pid, parse_stat(&content)?))
+        })
+        .collect()
+}
+
+/// Parse `/proc/<pid>/stat`. The command name is parenthesized and may
+/// itself contain spaces and parentheses, so fields are counted from the
+/// last `)`.
+fn parse_stat(content: &str) -> Option<ProcStat> {
+    let rest = &content[content.rfind(')')? + 1..];
+    // Field 3 (state) is the first after the name; see proc(5).
+    let fields: Vec<&str> = rest.split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+    Some(ProcStat {
+        ppid: field(4)? as u32,
+        cpu_ticks: field(14)? + field(15)?,
+        threads: field(20)?,
+        rss_pages: field(24)?,
+    })
+}
+
+/// `root` and all its descendants that are still alive, root first.
+fn process_tree(table: &HashMap<u32, ProcStat>, root: u32) -> Vec<u32> {
+    if !table.contains_key(&root) {
+        return Vec::new();
+    }
+    let mut children: HashMap<u32, Vec<u32>> = HashMap::new();
+    for (&pid, stat) in table {
+        children.entry(stat.ppid).or_default().push(pid);
+    }
+    let mut tree = vec![root];
+    let mut i = 0;
+    while i < tree.len() {
+        if let Some(kids) = children.get(&tree[i]) {
+            tree.extend(kids.iter().copied().filter(|&k| k != root));
+        }
+        i += 1;
+    }
+    tree
+}
+
+/// Socket inode → port for every listening TCP socket on the machine.
+fn listening_sockets() -> HashMap<u64, u16> {
+    ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| parse_net_tcp(&content))
+        .collect()
+}
+
+/// Listening sockets (`st == 0A`) from a `/proc/net/tcp{,6}` table, as
+/// (inode, local port).
+fn parse_net_tcp(content: &str) -> Vec<(u64, u16)> {
+    content
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.get(3) != Some(&"0A") {
+                return None;
+            }
+            let port = u16::from_str_radix(fields.get(1)?.rsplit(':').next()?, 16).ok()?;
+            let inode = fields.get(9)?.parse().ok()?;
+            Some((inode, port))
+        })
+        .collect()
+}
+
+fn listening_ports(pids: &[u32], listeners: &HashMap<u64, u16>) -> Vec<u16> {
+    let mut inodes = HashSet::new();
+    for pid in pids {
+        let Ok(fds) = fs::read_dir(format!("/proc/{pid}/fd")) else {
+            continue;
+        };
+        for fd in fds.flatten() {
+            if let Ok(target) = fs::read_link(fd.path())
+                && let Some(inode) = target
+                    .to_str()
+                    .and_then(|t| t.strip_prefix("socket:["))
+                    .and_then(|t| t.strip_suffix(']'))
+                    .and_then(|t| t.parse::<u64>().ok())
+            {
+                inodes.insert(inode);
+            }
+        }
+    }
+    let ports: BTreeSet<u16> = inodes.iter().filter_map(|i| listeners.get(i).copied()).collect();
+    ports.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_stat_with_awkward_command_names() {
+        let line = "4211 (my (dev) server) S 4200 4211 4211 0 -1 4194560 900 0 0 0 \
+                    150 25 0 0 20 0 7 0 123456 987654321 2048 18446744073709551615";
+        assert_eq!(
+            parse_stat(line),
+            Some(ProcStat { ppid: 4200, cpu_ticks: 175, threads: 7, rss_pages: 2048 })
+        );
+        assert_eq!(parse_stat("4211 (truncated"), None);
+    }
+
+    #[test]
+    fn parses_listening_sockets_only() {
+        let table = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+            0: 0100007F:0BB8 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 55501 1 0 100 0 0 10 0\n\
+            1: 0100007F:0BB8 0100007F:D2F0 01 00000000:00000000 00:00000000 00000000  1000        0 55502 1 0 20 4 30 10 -1\n";
+        assert_eq!(parse_net_tcp(table), vec![(55501, 3000)]);
+    }
+
+    #[test]
+    fn process_tree_follows_parent_links() {
+        let stat = |ppid| ProcStat { ppid, cpu_ticks: 0, threads: 1, rss_pages: 0 };
+        let table = HashMap::from([
+            (1, stat(0)),
+            (10, stat(1)),
+            (11, stat(10)),
+            (12, stat(11)),
+            (20, stat(1)),
+        ]);
+        let mut tree = process_tree(&table, 10);
+        tree.sort();
+        assert_eq!(tree, vec![10, 11, 12]);
+        assert!(process_tree(&table, 99).is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reports_this_process_and_its_listener() {
+        use crate::services::{project_key, ServiceState, ServiceStatus};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut data = ServicesData::default();
+        data.projects.entry(project_key("default", "api")).or_default().insert(
+            "back".into(),
+            ServiceState {
+                pid: std::process::id(),
+                port: port.wrapping_add(1),
+                started_at: chrono::Utc::now(),
+                log_path: "/tmp/x.log".into(),
+                dev_cmd: "cargo test".into(),
+                status: ServiceStatus::Running,
+                restart_count: 0,
+                last_exit_code: None,
+                last_exit_signal: None,
+                stop_timeout_secs: None,
+            },
+        );
+        let stats = collect(&data, Duration::from_millis(10)).unwrap();
+        let s = &stats[0];
+        assert_eq!((s.project.as_str(), s.service.as_str()), ("api", "back"));
+        assert!(s.processes >= 1 && s.threads >= 1 && s.rss_bytes > 0);
+        assert!(s.listening.contains(&port), "{:?} lacks {port}", s.listening);
+        assert!(s.port_mismatch);
+    }
+}
@@ -2,7 +2,7 @@ use crate::error::PmError;
 use crate::git;
 use crate::models::PortKind;
 use crate::path::expand_path;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use git2::Repository;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
@@ -31,6 +31,19 @@ pub struct ProjConfig {
     /// When absent or empty, `pm run` falls back to v0.3.0 grammar.
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     pub services: HashMap<String, ServiceDef>,
+
+    /// Environment for every service (and for `pm run -- <cmd>`). Values
+    /// may reference `${VAR}` and `${port:<service>}`.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        deserialize_with = "deserialize_env_map"
+    )]
+    pub env: HashMap<String, String>,
+
+    /// `.env` files (relative to the project root) loaded before `env`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_file: Vec<String>,
 }
 
 /// A single service entry under `.proj.yaml` `services:`.
@@ -43,10 +56,17 @@ pub struct ServiceDef {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dir: Option<String>,
 
-    /// Dev command to spawn. Inferred from `framework` if absent.
+    /// Dev command to spawn. Inferred from `framework` if absent. Split into
+    /// words like a POSIX shell would (quotes, escapes, leading `VAR=value`)
+    /// but not expanded; see `shell`.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dev_cmd: Option<String>,
 
+    /// Run `dev_cmd` through `$SHELL -c` (falling back to `/bin/sh`) so
+    /// that pipes, `&&`, globs and `$VAR` expansion work. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<bool>,
+
     /// Port allocation kind. Inferred from `framework` if absent.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub port_kind: Option<PortKind>,
@@ -55,9 +75,308 @@ pub struct ServiceDef {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub framework: Option<String>,
 
-    /// Reserved for path-based routing (Phase 2). Currently parsed but ignored.
+    /// Path prefix (e.g. `/api`) under which this service is also reachable
+    /// on the project hostname `<project>.<workspace>.localhost`. Requests
+    /// are routed by longest matching prefix; `/` acts as the catch-all.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
+
+    /// Strip `path` from the request URI before forwarding upstream.
+    /// Defaults to `false` (the upstream sees the full path).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strip_path: Option<bool>,
+
+    /// Log every request the proxy forwards to this service (method, path,
+    /// status, latency, size) into the service log. Defaults to `false`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_log: Option<bool>,
+
+    /// Send the upstream `Host: 127.0.0.1:<port>` instead of the hostname
+    /// the client asked for, for dev servers that reject unknown hosts.
+    /// The original stays available in `X-Forwarded-Host`. Defaults to
+    /// `false` (the `Host` header is passed through).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rewrite_host: Option<bool>,
+
+    /// Extra hostnames routed to this service besides
+    /// `<service>.<project>.<workspace>.localhost`, e.g. `api.localhost`.
+    /// `*.example.localhost` matches every subdomain of `example.localhost`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hostnames: Vec<String>,
+
+    /// Also route every subdomain of the service's hostnames
+    /// (`*.front.shop.localhost`), e.g. for per-tenant subdomains.
+    /// Defaults to `false`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wildcard: Option<bool>,
+
+    /// What the daemon does when the process exits. Defaults to `never`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart: Option<RestartPolicy>,
+
+    /// Seconds `pm stop` waits after SIGTERM before killing the service's
+    /// process group. Defaults to [`DEFAULT_STOP_TIMEOUT_SECS`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_timeout: Option<u64>,
+
+    /// Probe `pm run` waits on before reporting the service as running.
+    /// Without it, the service counts as running as soon as it is spawned.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ready: Option<ReadyDef>,
+
+    /// Services that must be started (and ready) before this one. See
+    /// [`service_start_order`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub depends_on: Option<Vec<String>>,
+
+    /// Restart the service when files under `dir` change. For commands
+    /// without their own reload (`cargo run`, `go run`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub watch: Option<WatchDef>,
+
+    /// Environment for this service, applied over the project-level `env`.
+    #[serde(
+        default,
+        skip_serializing_if = "HashMap::is_empty",
+        deserialize_with = "deserialize_env_map"
+    )]
+    pub env: HashMap<String, String>,
+
+    /// `.env` files (relative to the service `dir`) loaded before `env`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env_file: Vec<String>,
+}
+
+/// Accept any YAML scalar as an environment value, so `PORT: 3000` and
+/// `DEBUG: true` do not need quoting.
+fn deserialize_env_map<'de, D>(deserializer: D) -> std::result::Result<HashMap<String, String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::de::Error;
+    let raw: HashMap<String, serde_yaml::Value> = HashMap::deserialize(deserializer)?;
+    raw.into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                serde_yaml::Value::String(s) => s,
+                serde_yaml::Value::Number(n) => n.to_string(),
+                serde_yaml::Value::Bool(b) => b.to_string(),
+                serde_yaml::Value::Null => String::new(),
+                _ => return Err(D::Error::custom(format!("env value for '{key}' must be a scalar"))),
+            };
+            Ok((key, value))
+        })
+        .collect()
+}
+
+/// `ready:` as written in `.proj.yaml`: either a bare probe name
+/// (`ready: tcp`, `ready: http`) or a map with exactly one probe key.
+///
+/// ```yaml
+/// ready:
+///   http: /healthz     # GET this path on the allocated port
+///   status: 200        # default: any 2xx/3xx
+///   timeout: 90        # seconds, default 60
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ReadyDef {
+    Shorthand(String),
+    Full(ReadySpec),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadySpec {
+    /// Connect to the allocated port.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<bool>,
+    /// Path to GET on the allocated port.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<String>,
+    /// Expected HTTP status for `http`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Regex matched against lines the service writes to its log.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log: Option<String>,
+    /// Seconds to wait before giving up.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+/// Default readiness timeout; generous enough for a cold `cargo run`.
+pub const DEFAULT_READY_TIMEOUT_SECS: u64 = 60;
+
+/// Default grace period between SIGTERM and SIGKILL on `pm stop`.
+pub const DEFAULT_STOP_TIMEOUT_SECS: u64 = 10;
+
+/// A validated readiness probe.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadyProbe {
+    pub check: ReadyCheck,
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadyCheck {
+    Tcp,
+    /// `status: None` accepts any 2xx or 3xx response.
+    Http { path: String, status: Option<u16> },
+    Log { pattern: String },
+}
+
+impl ReadyCheck {
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp => "tcp".to_string(),
+            Self::Http { path, status: Some(s) } => format!("GET {path} → {s}"),
+            Self::Http { path, status: None } => format!("GET {path}"),
+            Self::Log { pattern } => format!("log /{pattern}/"),
+        }
+    }
+}
+
+impl ReadyDef {
+    pub fn resolve(&self) -> Result<ReadyProbe> {
+        let spec = match self {
+            Self::Shorthand(name) => match name.as_str() {
+                "tcp" => ReadySpec { tcp: Some(true), ..Default::default() },
+                "http" => ReadySpec { http: Some("/".to_string()), ..Default::default() },
+                other => {
+                    return Err(anyhow::anyhow!(
+                        "unknown ready probe '{other}' (expected tcp, http, or a map)"
+                    ));
+                }
+            },
+            Self::Full(spec) => spec.clone(),
+        };
+
+        let mut checks = Vec::new();
+        if spec.tcp == Some(true) {
+            checks.push(ReadyCheck::Tcp);
+        }
+        if let Some(path) = &spec.http {
+            if !path.starts_with('/') {
+                return Err(anyhow::anyhow!("ready.http '{path}' must start with '/'"));
+            }
+            checks.push(ReadyCheck::Http { path: path.clone(), status: spec.status });
+        } else if spec.status.is_some() {
+            return Err(anyhow::anyhow!("ready.status requires ready.http"));
+        }
+        if let Some(pattern) = &spec.log {
+            regex::Regex::new(pattern)
+                .with_context(|| format!("invalid ready.log regex '{pattern}'"))?;
+            checks.push(ReadyCheck::Log { pattern: pattern.clone() });
+        }
+
+        if checks.len() > 1 {
+            return Err(anyhow::anyhow!(
+                "ready accepts exactly one of tcp, http, log"
+            ));
+        }
+        let check = checks.pop().ok_or_else(|| {
+            anyhow::anyhow!("ready needs one of tcp, http, log")
+        })?;
+        Ok(ReadyProbe {
+            check,
+            timeout_secs: spec.timeout.unwrap_or(DEFAULT_READY_TIMEOUT_SECS),
+        })
+    }
+}
+
+/// `watch:` as written in `.proj.yaml`: `true` to watch everything under
+/// the service `dir`, or a map.
+///
+/// ```yaml
+/// watch:
+///   include: ["src/**", "Cargo.toml"]   # globs relative to `dir`; default: all
+///   exclude: ["**/*.snap"]
+///   debounce_ms: 300                    # default 500
+/// ```
+///
+/// Paths ignored by the project's `.gitignore` never trigger a restart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WatchDef {
+    Enabled(bool),
+    Full(WatchSpec),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchSpec {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debounce_ms: Option<u64>,
+}
+
+/// Default quiet period before a burst of changes triggers a restart.
+pub const DEFAULT_WATCH_DEBOUNCE_MS: u64 = 500;
+
+/// A validated `watch:` block, handed to the daemon as part of the spawn
+/// request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchConfig {
+    /// Empty means every file.
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub debounce_ms: u64,
+}
+
+impl WatchDef {
+    /// `None` for `watch: false`.
+    pub fn resolve(&self) -> Result<Option<WatchConfig>> {
+        let spec = match self {
+            Self::Enabled(false) => return Ok(None),
+            Self::Enabled(true) => WatchSpec::default(),
+            Self::Full(spec) => spec.clone(),
+        };
+        for glob in spec.include.iter().chain(&spec.exclude) {
+            globset::Glob::new(glob).with_context(|| format!("invalid watch glob '{glob}'"))?;
+        }
+        Ok(Some(WatchConfig {
+            include: spec.include,
+            exclude: spec.exclude,
+            debounce_ms: spec.debounce_ms.unwrap_or(DEFAULT_WATCH_DEBOUNCE_MS),
+        }))
+    }
+}
+
+/// Restart policy applied by the daemon's supervisor when a service exits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Restart after every exit, including a clean `exit 0`.
+    Always,
+    /// Restart only after a non-zero exit code or a fatal signal.
+    OnFailure,
+    /// Leave the service down and record how it exited.
+    #[default]
+    Never,
+}
+
+impl RestartPolicy {
+    pub fn should_restart(self, exited_successfully: bool) -> bool {
+        match self {
+            Self::Always => true,
+            Self::OnFailure => !exited_successfully,
+            Self::Never => false,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Always => "always",
+            Self::OnFailure => "on-failure",
+            Self::Never => "never",
+        }
+    }
 }
 
 /// Fully resolved service definition with framework defaults applied.
@@ -66,10 +385,25 @@ pub struct ServiceDef {
 pub struct ResolvedService {
     pub dir: String,
     pub dev_cmd: String,
+    pub shell: bool,
     pub port_kind: PortKind,
     pub framework: Option<String>,
-    /// Reserved for Phase 2 path-based routing. Always `None` in v0.4.0.
+    /// Normalized path prefix (leading `/`, no trailing `/`) for path-based
+    /// routing on the project hostname.
     pub path: Option<String>,
+    pub strip_path: bool,
+    pub access_log: bool,
+    pub rewrite_host: bool,
+    /// Normalized (lowercase, no trailing dot) extra hostnames.
+    pub hostnames: Vec<String>,
+    pub wildcard: bool,
+    pub restart: RestartPolicy,
+    pub stop_timeout_secs: u64,
+    pub ready: Option<ReadyProbe>,
+    pub depends_on: Vec<String>,
+    pub watch: Option<WatchConfig>,
+    pub env: HashMap<String, String>,
+    pub env_file: Vec<String>,
 }
 
 /// Resolve a [`ServiceDef`] into a [`ResolvedService`] using framework-driven defaults.
@@ -107,15 +441,176 @@ pub fn resolve_service_defaults(
         )
     })?;
 
+    let path = def.path.as_deref().map(normalize_route_path).transpose()?;
+    let hostnames = def
+        .hostnames
+        .iter()
+        .map(|h| normalize_hostname(h))
+        .collect::<Result<Vec<_>>>()?;
+    let ready = def.ready.as_ref().map(ReadyDef::resolve).transpose()?;
+    let watch = match &def.watch {
+        Some(watch) => watch.resolve()?,
+        None => None,
+    };
+
+    let shell = def.shell.unwrap_or(false);
+    if !shell {
+        // Surface unbalanced quotes at resolve time, before anything starts.
+        crate::shell_words::parse_command(&dev_cmd).context("invalid dev_cmd")?;
+    }
+
     Ok(ResolvedService {
         dir,
         dev_cmd,
+        shell,
         port_kind,
         framework,
-        path: def.path.clone(),
+        path,
+        strip_path: def.strip_path.unwrap_or(false),
+        access_log: def.access_log.unwrap_or(false),
+        rewrite_host: def.rewrite_host.unwrap_or(false),
+        hostnames,
+        wildcard: def.wildcard.unwrap_or(false),
+        restart: def.restart.unwrap_or_default(),
+        stop_timeout_secs: def.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT_SECS),
+        ready,
+        depends_on: def.depends_on.clone().unwrap_or_default(),
+        watch,
+        env: def.env.clone(),
+        env_file: def.env_file.clone(),
     })
 }
 
+/// Order service keys so that every service comes after everything in its
+/// `depends_on`. Independent services keep alphabetical order, so the
+/// result is stable across runs.
+///
+/// Errors on a dependency that is not defined in `services` and on cycles,
+/// naming the services involved (`a → b → a`).
+pub fn service_start_order(services: &HashMap<String, ServiceDef>) -> Result<Vec<String>> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Visiting,
+        Done,
+    }
+
+    fn deps_of<'a>(services: &'a HashMap<String, ServiceDef>, name: &str) -> Vec<&'a String> {
+        let mut deps: Vec<&String> = services[name].depends_on.iter().flatten().collect();
+        deps.sort();
+        deps
+    }
+
+    fn visit<'a>(
+        name: &'a String,
+        services: &'a HashMap<String, ServiceDef>,
+        marks: &mut HashMap<&'a str, Mark>,
+        stack: &mut Vec<&'a str>,
+        order: &mut Vec<String>,
+    ) -> Result<()> {
+        match marks.get(name.as_str()) {
+            Some(Mark::Done) => return Ok(()),
+            Some(Mark::Visiting) => {
+                let start = stack.iter().position(|s| *s == name).unwrap_or(0);
+                let mut cycle: Vec<&str> = stack[start..].to_vec();
+                cycle.push(name);
+                return Err(anyhow::anyhow!(
+                    "dependency cycle in services: {}",
+                    cycle.join(" → ")
+                ));
+            }
+            None => {}
+        }
+        marks.insert(name, Mark::Visiting);
+        stack.push(name);
+        for dep in deps_of(services, name) {
+            visit(dep, services, marks, stack, order)?;
+        }
+        stack.pop();
+        marks.insert(name, Mark::Done);
+        order.push(name.clone());
+        Ok(())
+    }
+
+    let mut names: Vec<&String> = services.keys().collect();
+    names.sort();
+    for name in &names {
+        for dep in deps_of(services, name) {
+            if !services.contains_key(dep) {
+                return Err(anyhow::anyhow!(
+                    "service '{name}' depends on '{dep}', which is not defined in services"
+                ));
+            }
+        }
+    }
+
+    let mut marks = HashMap::new();
+    let mut order = Vec::with_capacity(names.len());
+    for name in names {
+        visit(name, services, &mut marks, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+/// `target` plus everything it transitively depends on.
+pub fn dependency_closure(
+    services: &HashMap<String, ServiceDef>,
+    target: &str,
+) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut pending = vec![target.to_string()];
+    while let Some(name) = pending.pop() {
+        if !seen.insert(name.clone()) {
+            continue;
+        }
+        if let Some(def) = services.get(&name) {
+            pending.extend(def.depends_on.iter().flatten().cloned());
+        }
+    }
+    seen
+}
+
+/// Normalize a service `path:` into the form stored in `routes.json`:
+/// a leading `/`, no trailing `/` (except for the root `/` itself).
+fn normalize_route_path(raw: &str) -> Result<String> {
+    let trimmed = raw.trim();
+    if !trimmed.starts_with('/') {
+        return Err(anyhow::anyhow!(
+            "service path '{raw}' must start with '/' (e.g. /api)"
+        ));
+    }
+    if trimmed.contains(['?', '#', '*']) {
+        return Err(anyhow::anyhow!(
+            "service path '{raw}' must be a plain prefix without query, fragment or wildcards"
+        ));
+    }
+    let without_trailing = trimmed.trim_end_matches('/');
+    if without_trailing.is_empty() {
+        Ok("/".to_string())
+    } else {
+        Ok(without_trailing.to_string())
+    }
+}
+
+/// Lowercase `raw`, drop a trailing dot and check that it is a hostname,
+/// optionally starting with a `*.` wildcard label.
+fn normalize_hostname(raw: &str) -> Result<String> {
+    let host = raw.trim().trim_end_matches('.').to_ascii_lowercase();
+    let name = host.strip_prefix("*.").unwrap_or(&host);
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if !name.contains('.') || !name.split('.').all(valid_label) {
+        return Err(anyhow::anyhow!(
+            "hostname '{raw}' must be a dotted name like api.localhost, optionally starting with '*.'"
+        ));
+    }
+    Ok(host)
+}
+
 /// Framework → (default dev_cmd, default port_kind).
 ///
 /// See `design.md` D9 for the canonical table. Next.js follows the
@@ -278,10 +773,10 @@ pub fn detect_language(project_dir: &Path, manifest: &GlobalManifest) -> Option<
         for marker in &lang.markers {
             if marker.contains('*') {
                 // Glob pattern like "*.c"
-                if let Some(ext) = marker.strip_prefix("*.") {
-                    if has_files_with_extension(project_dir, ext) {
-                        return Some(lang.id.clone());
-                    }
+                if let Some(ext) = marker.strip_prefix("*.")
+                    && has_files_with_extension(project_dir, ext)
+                {
+                    return Some(lang.id.clone());
                 }
             } else if project_dir.join(marker).exists() {
                 return Some(lang.id.clone());
@@ -340,20 +835,20 @@ fn detect_ts_framework(dir: &Path) -> Option<String> {
 
 fn detect_python_framework(dir: &Path) -> Option<String> {
     let pyproject = dir.join("pyproject.toml");
-    if let Ok(content) = fs::read_to_string(pyproject) {
-        if content.contains("fastapi") {
-            return Some("fastapi".to_string());
-        }
+    if let Ok(content) = fs::read_to_string(pyproject)
+        && content.contains("fastapi")
+    {
+        return Some("fastapi".to_string());
     }
     None
 }
 
 fn detect_dart_framework(dir: &Path) -> Option<String> {
     let pubspec = dir.join("pubspec.yaml");
-    if let Ok(content) = fs::read_to_string(pubspec) {
-        if content.contains("flutter") {
-            return Some("flutter".to_string());
-        }
+    if let Ok(content) = fs::read_to_string(pubspec)
+        && content.contains("flutter")
+    {
+        return Some("flutter".to_string());
     }
     None
 }
@@ -622,6 +1117,8 @@ mod tests {
             config_version: "abc1234".to_string(),
             includes: vec!["ci".to_string(), "docker".to_string()],
             services: Default::default(),
+            env: Default::default(),
+            env_file: Vec::new(),
         };
         save_proj_config(dir.path(), &config).unwrap();
 
@@ -641,6 +1138,8 @@ mod tests {
             config_version: "def5678".to_string(),
             includes: Vec::new(),
             services: Default::default(),
+            env: Default::default(),
+            env_file: Vec::new(),
         };
         save_proj_config(dir.path(), &config).unwrap();
 
@@ -1279,6 +1778,8 @@ mod tests {
             config_version: String::new(),
             includes: vec!["ci".to_string()],
             services: Default::default(),
+            env: Default::default(),
+            env_file: Vec::new(),
         };
         let files = collect_all_source_files(dir.path(), &config).unwrap();
         // common(2) + axum(1) + shared(1) + ci(1)
@@ -1324,6 +1825,8 @@ mod tests {
             config_version: String::new(),
             includes: Vec::new(),
             services: Default::default(),
+            env: Default::default(),
+            env_file: Vec::new(),
         };
         let files = collect_all_source_files(repo.path(), &config).unwrap();
 
@@ -1367,6 +1870,8 @@ mod tests {
             config_version: String::new(),
             includes: Vec::new(),
             services: Default::default(),
+            env: Default::default(),
+            env_file: Vec::new(),
         };
         let files = collect_all_source_files(repo.path(), &config).unwrap();
         for (source, entry) in &files {
@@ -1522,17 +2027,235 @@ services:
     }
 
     #[test]
-    fn path_field_is_parsed_but_phase_2() {
-        // path-based routing is reserved for Phase 2; we accept the field but ignore it.
+    fn path_field_is_parsed_and_resolved() {
         let yaml = r#"
 language: ts
 framework: nextjs
 config_version: abc123
 services:
   back:
-    path: /api
+    framework: axum
+    path: /api/
+    strip_path: true
 "#;
         let config: ProjConfig = serde_yaml::from_str(yaml).unwrap();
-        assert_eq!(config.services["back"].path.as_deref(), Some("/api"));
+        assert_eq!(config.services["back"].path.as_deref(), Some("/api/"));
+        let resolved = resolve_service_defaults(&config.services["back"], None).unwrap();
+        assert_eq!(resolved.path.as_deref(), Some("/api"));
+        assert!(resolved.strip_path);
+    }
+
+    #[test]
+    fn hostnames_and_wildcard_are_normalized() {
+        let yaml = r#"
+language: ts
+framework: nextjs
+config_version: abc123
+services:
+  front:
+    hostnames: [Shop.localhost., "*.tenants.localhost"]
+    wildcard: true
+  bad:
+    hostnames: [localhost]
+"#;
+        let config: ProjConfig = serde_yaml::from_str(yaml).unwrap();
+        let front = resolve_service_defaults(&config.services["front"], Some("nextjs")).unwrap();
+        assert_eq!(front.hostnames, vec!["shop.localhost", "*.tenants.localhost"]);
+        assert!(front.wildcard);
+        let err = resolve_service_defaults(&config.services["bad"], Some("nextjs")).unwrap_err();
+        assert!(err.to_string().contains("'localhost'"), "{err}");
+        assert!(normalize_hostname("api_v2.localhost").is_err());
+        assert!(normalize_hostname("*.*.localhost").is_err());
+    }
+
+    #[test]
+    fn restart_policy_parses_and_defaults_to_never() {
+        let yaml = r#"
+language: rust
+framework: axum
+config_version: abc123
+services:
+  back:
+    restart: on-failure
+  worker:
+    dev_cmd: cargo run --bin worker
+"#;
+        let config: ProjConfig = serde_yaml::from_str(yaml).unwrap();
+        let back = resolve_service_defaults(&config.services["back"], Some("axum")).unwrap();
+        assert_eq!(back.restart, RestartPolicy::OnFailure);
+        let worker = resolve_service_defaults(&config.services["worker"], Some("axum")).unwrap();
+        assert_eq!(worker.restart, RestartPolicy::Never);
+    }
+
+    #[test]
+    fn watch_shorthand_and_map_resolve() {
+        let services = services_from_yaml(
+            r#"
+back:
+  framework: axum
+  watch: true
+worker:
+  framework: axum
+  watch:
+    include: ["src/**", "Cargo.toml"]
+    debounce_ms: 200
+plain:
+  framework: axum
+  watch: false
+"#,
+        );
+        let back = resolve_service_defaults(&services["back"], None).unwrap();
+        assert_eq!(
+            back.watch,
+            Some(WatchConfig {
+                include: vec![],
+                exclude: vec![],
+                debounce_ms: DEFAULT_WATCH_DEBOUNCE_MS,
+            })
+        );
+        let worker = resolve_service_defaults(&services["worker"], None).unwrap();
+        let watch = worker.watch.unwrap();
+        assert_eq!(watch.include, vec!["src/**", "Cargo.toml"]);
+        assert_eq!(watch.debounce_ms, 200);
+        let plain = resolve_service_defaults(&services["plain"], None).unwrap();
+        assert_eq!(plain.watch, None);
+    }
+
+    #[test]
+    fn watch_rejects_bad_globs_and_unknown_keys() {
+        let services = services_from_yaml("back:\n  framework: axum\n  watch:\n    include: [\"src/[\"]\n");
+        let err = resolve_service_defaults(&services["back"], None).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid watch glob"), "{err:#}");
+        assert!(
+            serde_yaml::from_str::<HashMap<String, ServiceDef>>(
+                "back:\n  watch:\n    paths: [src]\n"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn stop_timeout_parses_and_defaults() {
+        let services = services_from_yaml(
+            r#"
+back:
+  framework: axum
+  stop_timeout: 30
+front:
+  framework: nextjs
+"#,
+        );
+        let back = resolve_service_defaults(&services["back"], None).unwrap();
+        assert_eq!(back.stop_timeout_secs, 30);
+        let front = resolve_service_defaults(&services["front"], None).unwrap();
+        assert_eq!(front.stop_timeout_secs, DEFAULT_STOP_TIMEOUT_SECS);
+    }
+
+    fn services_from_yaml(yaml: &str) -> HashMap<String, ServiceDef> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn start_order_puts_dependencies_first() {
+        let services = services_from_yaml(
+            r#"
+front: { depends_on: [back] }
+back: { depends_on: [migrate] }
+migrate: {}
+admin: {}
+"#,
+        );
+        assert_eq!(
+            service_start_order(&services).unwrap(),
+            vec!["admin", "migrate", "back", "front"]
+        );
+    }
+
+    #[test]
+    fn start_order_reports_cycles_and_unknown_dependencies() {
+        let cyclic = services_from_yaml("a: { depends_on: [b] }\nb: { depends_on: [a] }");
+        let err = service_start_order(&cyclic).unwrap_err().to_string();
+        assert!(err.contains("a → b → a"), "{err}");
+
+        let own = services_from_yaml("a: { depends_on: [a] }");
+        let err = service_start_order(&own).unwrap_err().to_string();
+        assert!(err.contains("a → a"), "{err}");
+
+        let unknown = services_from_yaml("a: { depends_on: [db] }");
+        let err = service_start_order(&unknown).unwrap_err().to_string();
+        assert!(err.contains("'db'"), "{err}");
+    }
+
+    #[test]
+    fn dependency_closure_is_transitive() {
+        let services = services_from_yaml(
+            "front: { depends_on: [back] }\nback: { depends_on: [db] }\ndb: {}\nother: {}",
+        );
+        let closure = dependency_closure(&services, "front");
+        let mut names: Vec<_> = closure.into_iter().collect();
+        names.sort();
+        assert_eq!(names, vec!["back", "db", "front"]);
+    }
+
+    fn ready_from_yaml(yaml: &str) -> Result<ReadyProbe> {
+        let def: ReadyDef = serde_yaml::from_str(yaml).unwrap();
+        def.resolve()
+    }
+
+    #[test]
+    fn ready_shorthand_and_maps_resolve() {
+        let tcp = ready_from_yaml("tcp").unwrap();
+        assert_eq!(tcp.check, ReadyCheck::Tcp);
+        assert_eq!(tcp.timeout_secs, DEFAULT_READY_TIMEOUT_SECS);
+
+        let http = ready_from_yaml("http: /healthz\nstatus: 204\ntimeout: 5").unwrap();
+        assert_eq!(
+            http.check,
+            ReadyCheck::Http { path: "/healthz".into(), status: Some(204) }
+        );
+        assert_eq!(http.timeout_secs, 5);
+
+        let log = ready_from_yaml("log: 'ready in \\d+ms'").unwrap();
+        assert_eq!(log.check, ReadyCheck::Log { pattern: r"ready in \d+ms".into() });
+    }
+
+    #[test]
+    fn ready_rejects_ambiguous_or_invalid_probes() {
+        assert!(ready_from_yaml("bogus").is_err());
+        assert!(ready_from_yaml("tcp: true\nhttp: /").is_err());
+        assert!(ready_from_yaml("status: 200").is_err());
+        assert!(ready_from_yaml("http: health").is_err());
+        assert!(ready_from_yaml("log: '('").is_err());
+        assert!(ready_from_yaml("timeout: 10").is_err());
+    }
+
+    #[test]
+    fn restart_policy_decisions() {
+        assert!(RestartPolicy::Always.should_restart(true));
+        assert!(RestartPolicy::Always.should_restart(false));
+        assert!(!RestartPolicy::OnFailure.should_restart(true));
+        assert!(RestartPolicy::OnFailure.should_restart(false));
+        assert!(!RestartPolicy::Never.should_restart(false));
+    }
+
+    #[test]
+    fn root_path_normalizes_to_slash() {
+        let def = ServiceDef {
+            path: Some("//".to_string()),
+            ..Default::default()
+        };
+        let resolved = resolve_service_defaults(&def, Some("nextjs")).unwrap();
+        assert_eq!(resolved.path.as_deref(), Some("/"));
+        assert!(!resolved.strip_path);
+    }
+
+    #[test]
+    fn path_without_leading_slash_errors() {
+        let def = ServiceDef {
+            path: Some("api".to_string()),
+            ..Default::default()
+        };
+        let err = resolve_service_defaults(&def, Some("axum")).unwrap_err();
+        assert!(err.to_string().contains("must start with '/'"));
     }
 }
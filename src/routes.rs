@@ -4,18 +4,18 @@
 //!
 //! The CLI writes hostname → upstream-port mappings into
 //! `~/.config/pm/routes.json` whenever `pm run` spawns or stops a service.
-//! The daemon (`pm __daemon`) reads this file on every incoming HTTP request
-//! by checking the file's mtime — when it changes, the in-memory route
-//! table is reloaded. This avoids needing inotify/kqueue while keeping
-//! route changes visible within milliseconds.
+//! The daemon (`pm __daemon`) watches the file (inotify/FSEvents, or an
+//! mtime poll where watching is unavailable) and swaps in a fresh
+//! [`RouteTable`] — a hostname-indexed copy of the entries — when it
+//! changes, so requests never touch the filesystem. `POST /reload` on the
+//! control plane forces a reload.
 //!
 //! ## Concurrency
 //!
 //! Concurrent CLI processes (`pm run` invoked twice in parallel) may both
-//! attempt to write `routes.json`. We use atomic `rename` from a temp file
-//! to prevent torn writes; an advisory `flock` adds belt-and-suspenders
-//! safety on Unix. Reads are best-effort and tolerant of mid-write states
-//! (the daemon retries on parse error).
+//! update `routes.json`. Each update holds the file's [`lock`] from load to
+//! save, and the save is an atomic `rename` from a temp file, so readers
+//! never see a torn write and writers never lose each other's entries.
 //!
 //! ## File format
 //!
@@ -28,16 +28,50 @@
 //!       "upstream_port": 26918,
 //!       "project_key": "work/api",
 //!       "service_key": "back"
+//!     },
+//!     {
+//!       "hostname": "api.work.localhost",
+//!       "upstream_port": 26918,
+//!       "project_key": "work/api",
+//!       "service_key": "back",
+//!       "path_prefix": "/api",
+//!       "strip_prefix": true
 //!     }
 //!   ]
 //! }
 //! ```
+//!
+//! ## Path-based routing
+//!
+//! Services that declare `path:` in `.project.yaml` get an extra entry on the
+//! project hostname (`<project>.<workspace>.localhost`) carrying a
+//! `path_prefix`. Several entries may share a hostname; [`RouteTable::lookup`]
+//! picks the longest prefix matching the request path on a segment boundary.
+//! Entries without a prefix match every path with the lowest priority.
+//!
+//! ## Custom and wildcard hostnames
+//!
+//! Services may add hostnames of their own (`hostnames:`) and ask for all
+//! subdomains of their hostnames (`wildcard: true`). A hostname starting
+//! with `*.` matches any name ending in the rest, at any depth:
+//! `*.front.shop.localhost` serves `acme.front.shop.localhost` and
+//! `eu.acme.front.shop.localhost`, but not `front.shop.localhost` itself.
+//! Exact hostnames win over wildcards, and longer wildcard suffixes over
+//! shorter ones.
+//!
+//! A hostname (with the same path prefix) belongs to one service at a
+//! time: [`register_service`] refuses to take one that a running service
+//! of another project already routes.
 
 use crate::config::routes_path;
+use crate::error::PmError;
+use crate::lock;
+use crate::services;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
 
 /// Schema version for `routes.json`. Bumped on incompatible changes.
 pub const ROUTES_SCHEMA_VERSION: u32 = 1;
@@ -52,6 +86,56 @@ pub struct RouteEntry {
     pub project_key: String,
     /// Service identifier (`front`, `back`, ...).
     pub service_key: String,
+    /// Path prefix this entry is restricted to (`/api`). `None` matches any
+    /// path.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path_prefix: Option<String>,
+    /// Remove `path_prefix` from the request path before forwarding.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub strip_prefix: bool,
+    /// Append a line per proxied request to the service's log.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub access_log: bool,
+    /// Send the upstream `Host: 127.0.0.1:<port>` rather than the client's
+    /// `Host`.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub rewrite_host: bool,
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+impl RouteEntry {
+    /// Length of the matched prefix when this entry serves `path`, or `None`
+    /// when it does not. Prefixes match whole segments only, so `/api`
+    /// serves `/api` and `/api/users` but not `/apiary`.
+    fn match_len(&self, path: &str) -> Option<usize> {
+        match self.path_prefix.as_deref() {
+            None => Some(0),
+            Some("/") => Some(1),
+            Some(prefix) => {
+                let rest = path.strip_prefix(prefix)?;
+                (rest.is_empty() || rest.starts_with('/'))
+                    .then_some(prefix.len())
+            }
+        }
+    }
+}
+
+/// Per-service routing options beyond the upstream port, taken from the
+/// resolved `.project.yaml` service definition.
+#[derive(Debug, Clone, Default)]
+pub struct RouteOptions {
+    /// Normalized `path:` of the service (see [`RouteEntry::path_prefix`]).
+    pub path_prefix: Option<String>,
+    pub strip_prefix: bool,
+    pub access_log: bool,
+    pub rewrite_host: bool,
+    /// Extra hostnames, normalized; may start with `*.`.
+    pub hostnames: Vec<String>,
+    /// Also route `*.<hostname>` for each of the service's hostnames.
+    pub wildcard: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,6 +154,88 @@ impl Default for RoutesData {
     }
 }
 
+/// [`RoutesData`] indexed by hostname, for the daemon's per-request
+/// lookups: a hash lookup per candidate hostname, then a scan of that
+/// host's few entries.
+#[derive(Debug, Default)]
+pub struct RouteTable {
+    /// Lowercased hostname → its entries, longest `path_prefix` first.
+    by_host: HashMap<String, Vec<RouteEntry>>,
+    /// The same for `*.` entries, keyed by the suffix after `*.`.
+    wildcards: HashMap<String, Vec<RouteEntry>>,
+    count: usize,
+}
+
+impl From<RoutesData> for RouteTable {
+    fn from(data: RoutesData) -> Self {
+        let count = data.entries.len();
+        let mut by_host: HashMap<String, Vec<RouteEntry>> = HashMap::new();
+        let mut wildcards: HashMap<String, Vec<RouteEntry>> = HashMap::new();
+        for entry in data.entries {
+            let host = entry.hostname.to_ascii_lowercase();
+            match host.strip_prefix("*.") {
+                Some(suffix) => wildcards.entry(suffix.to_string()).or_default().push(entry),
+                None => by_host.entry(host).or_default().push(entry),
+            }
+        }
+        for entries in by_host.values_mut().chain(wildcards.values_mut()) {
+            entries.sort_by_key(|e| std::cmp::Reverse(e.path_prefix.as_deref().map_or(0, str::len)));
+        }
+        Self { by_host, wildcards, count }
+    }
+}
+
+impl RouteTable {
+    /// Find the entry serving `hostname` + `path`: exact (case-insensitive)
+    /// hostname match, then the longest matching wildcard suffix; within
+    /// a hostname, the longest matching path prefix.
+    pub fn lookup(&self, hostname: &str, path: &str) -> Option<&RouteEntry> {
+        let host = lowercase(hostname);
+        let matching = |entries: &Vec<RouteEntry>| entries.iter().position(|e| e.match_len(path).is_some());
+        if let Some(entries) = self.by_host.get(host.as_ref())
+            && let Some(i) = matching(entries)
+        {
+            return Some(&entries[i]);
+        }
+        parent_domains(&host).find_map(|suffix| {
+            let entries = self.wildcards.get(suffix)?;
+            matching(entries).map(|i| &entries[i])
+        })
+    }
+
+    /// Whether any route, exact or wildcard, exists for `hostname`.
+    pub fn serves_host(&self, hostname: &str) -> bool {
+        let host = lowercase(hostname);
+        self.by_host.contains_key(host.as_ref())
+            || parent_domains(&host).any(|suffix| self.wildcards.contains_key(suffix))
+    }
+
+    /// Every entry, exact and wildcard, in no particular order.
+    pub fn entries(&self) -> impl Iterator<Item = &RouteEntry> {
+        self.by_host.values().chain(self.wildcards.values()).flatten()
+    }
+
+    /// Number of entries.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+}
+
+fn lowercase(hostname: &str) -> Cow<'_, str> {
+    if hostname.bytes().any(|b| b.is_ascii_uppercase()) {
+        Cow::Owned(hostname.to_ascii_lowercase())
+    } else {
+        Cow::Borrowed(hostname)
+    }
+}
+
+/// `a.b.example.localhost` → `b.example.localhost`, `example.localhost`,
+/// `localhost`: the suffixes a `*.` entry may be registered under, longest
+/// first.
+fn parent_domains(host: &str) -> impl Iterator<Item = &str> {
+    host.match_indices('.').map(move |(i, _)| &host[i + 1..])
+}
+
 /// Load `routes.json` if it exists; otherwise return an empty default.
 pub fn load_routes() -> Result<RoutesData> {
     let path = routes_path();
@@ -83,31 +249,15 @@ pub fn load_routes() -> Result<RoutesData> {
     Ok(data)
 }
 
-/// Persist `routes.json` atomically (tmp + rename).
-///
-/// On Unix, an advisory file lock is taken on the target during the rename
-/// to serialize concurrent writers.
+/// Persist `routes.json` atomically (tmp + rename) under its lock. Callers
+/// that loaded `data` first must hold [`lock::lock`] on [`routes_path`]
+/// since before the load.
 pub fn save_routes(data: &RoutesData) -> Result<()> {
     let path = routes_path();
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).ok();
     }
-
-    let tmp_path: PathBuf = path.with_extension("json.tmp");
-    let content = serde_json::to_string_pretty(data)?;
-    fs::write(&tmp_path, content).with_context(|| format!("writing tmp {}", tmp_path.display()))?;
-
-    #[cfg(unix)]
-    {
-        // Best-effort advisory lock on the existing file (or tmp if new).
-        // We do not block on the lock — if the OS rejects, fall through to
-        // rename. The atomic rename itself is the real guarantee.
-        // (No-op when the file does not yet exist.)
-    }
-
-    fs::rename(&tmp_path, &path)
-        .with_context(|| format!("renaming {} -> {}", tmp_path.display(), path.display()))?;
-    Ok(())
+    lock::write_atomic(&path, &serde_json::to_string_pretty(data)?)
 }
 
 #[allow(dead_code)] // wired in by Stage 3 (Group 9: orchestrator service spawn)
@@ -127,45 +277,166 @@ pub fn default_workspace_alias(workspace: &str, project: &str, service: &str) ->
     }
 }
 
-#[allow(dead_code)] // wired in by Stage 3 (Group 9)
+/// Hostname shared by all path-routed services of a project:
+/// `<project>.<workspace>.localhost` (`<project>.localhost` for `default`).
+pub fn project_hostnames(workspace: &str, project: &str) -> Vec<String> {
+    let mut out = vec![format!("{project}.{workspace}.localhost")];
+    if workspace == "default" {
+        out.push(format!("{project}.localhost"));
+    }
+    out
+}
+
+/// Every hostname a service is reachable under without a path prefix:
+/// canonical, default-workspace alias, `hostnames:`, and with `wildcard`
+/// the `*.` form of each of these.
+pub fn service_hostnames(
+    workspace: &str,
+    project: &str,
+    service: &str,
+    options: &RouteOptions,
+) -> Vec<String> {
+    let mut hostnames = vec![canonical_hostname(workspace, project, service)];
+    hostnames.extend(default_workspace_alias(workspace, project, service));
+    hostnames.extend(options.hostnames.iter().cloned());
+    if options.wildcard {
+        let wildcards: Vec<String> = hostnames
+            .iter()
+            .filter(|h| !h.starts_with("*."))
+            .map(|h| format!("*.{h}"))
+            .collect();
+        hostnames.extend(wildcards);
+    }
+    let mut seen = std::collections::HashSet::new();
+    hostnames.retain(|h| seen.insert(h.clone()));
+    hostnames
+}
+
 /// Register routes for a service. Replaces any existing entries that share
 /// the same `(project_key, service_key)` (idempotent re-registration).
+///
+/// Fails with [`PmError::HostnameInUse`] when another service already
+/// routes one of the hostnames with the same path prefix, unless that
+/// service is no longer running (its entries are then dropped as stale).
 pub fn register_service(
     workspace: &str,
     project: &str,
     service: &str,
     upstream_port: u16,
+    options: &RouteOptions,
 ) -> Result<()> {
+    let _lock = lock::lock(&routes_path())?;
     let mut data = load_routes()?;
     let project_key = format!("{workspace}/{project}");
     data.entries
         .retain(|e| !(e.project_key == project_key && e.service_key == service));
 
-    let canonical = canonical_hostname(workspace, project, service);
-    data.entries.push(RouteEntry {
-        hostname: canonical,
-        upstream_port,
-        project_key: project_key.clone(),
-        service_key: service.to_string(),
-    });
-
-    if let Some(alias) = default_workspace_alias(workspace, project, service) {
-        data.entries.push(RouteEntry {
-            hostname: alias,
+    let new_entries = service_entries(workspace, project, service, upstream_port, options);
+    if let Some((taken, owner)) = find_conflict(&data, &new_entries, owner_is_running) {
+        return Err(PmError::HostnameInUse(taken.hostname.clone(), owner).into());
+    }
+    // Whatever still overlaps belongs to services that are gone.
+    data.entries.retain(|e| !new_entries.iter().any(|n| same_route(e, n)));
+    data.entries.extend(new_entries);
+    save_routes(&data)?;
+    Ok(())
+}
+
+/// Fail early, before a service is started, if [`register_service`] would
+/// refuse its hostnames.
+pub fn check_available(
+    workspace: &str,
+    project: &str,
+    service: &str,
+    options: &RouteOptions,
+) -> Result<()> {
+    let mut data = load_routes()?;
+    let project_key = format!("{workspace}/{project}");
+    data.entries
+        .retain(|e| !(e.project_key == project_key && e.service_key == service));
+    let wanted = service_entries(workspace, project, service, 0, options);
+    match find_conflict(&data, &wanted, owner_is_running) {
+        Some((taken, owner)) => Err(PmError::HostnameInUse(taken.hostname.clone(), owner).into()),
+        None => Ok(()),
+    }
+}
+
+fn service_entries(
+    workspace: &str,
+    project: &str,
+    service: &str,
+    upstream_port: u16,
+    options: &RouteOptions,
+) -> Vec<RouteEntry> {
+    let project_key = format!("{workspace}/{project}");
+    let mut entries = Vec::new();
+    for hostname in service_hostnames(workspace, project, service, options) {
+        entries.push(RouteEntry {
+            hostname,
             upstream_port,
-            project_key,
+            project_key: project_key.clone(),
             service_key: service.to_string(),
+            path_prefix: None,
+            strip_prefix: false,
+            access_log: options.access_log,
+            rewrite_host: options.rewrite_host,
         });
     }
+    if let Some(prefix) = &options.path_prefix {
+        for hostname in project_hostnames(workspace, project) {
+            entries.push(RouteEntry {
+                hostname,
+                upstream_port,
+                project_key: project_key.clone(),
+                service_key: service.to_string(),
+                path_prefix: Some(prefix.clone()),
+                strip_prefix: options.strip_prefix,
+                access_log: options.access_log,
+                rewrite_host: options.rewrite_host,
+            });
+        }
+    }
+    entries
+}
 
-    save_routes(&data)?;
-    Ok(())
+/// The first of `new` that an existing entry of a running service already
+/// claims, with that service as `<workspace>/<project>/<service>`.
+fn find_conflict<'a>(
+    data: &RoutesData,
+    new: &'a [RouteEntry],
+    is_running: impl Fn(&RouteEntry) -> bool,
+) -> Option<(&'a RouteEntry, String)> {
+    new.iter().find_map(|n| {
+        data.entries
+            .iter()
+            .find(|e| same_route(e, n) && is_running(e))
+            .map(|e| (n, format!("{}/{}", e.project_key, e.service_key)))
+    })
+}
+
+fn same_route(a: &RouteEntry, b: &RouteEntry) -> bool {
+    a.hostname.eq_ignore_ascii_case(&b.hostname) && a.path_prefix == b.path_prefix
+}
+
+fn owner_is_running(entry: &RouteEntry) -> bool {
+    let Some((workspace, project)) = entry.project_key.split_once('/') else {
+        return false;
+    };
+    match services::get(workspace, project, &entry.service_key) {
+        Ok(Some(state)) => {
+            state.status == services::ServiceStatus::Restarting || services::pid_alive(state.pid)
+        }
+        // Unreadable state: assume it runs rather than hijack its hostname.
+        Err(_) => true,
+        Ok(None) => false,
+    }
 }
 
 /// Remove all routes belonging to `(workspace, project)`. Used by `pm stop`
 /// without a service argument.
 #[allow(dead_code)]
 pub fn unregister_project(workspace: &str, project: &str) -> Result<usize> {
+    let _lock = lock::lock(&routes_path())?;
     let mut data = load_routes()?;
     let project_key = format!("{workspace}/{project}");
     let before = data.entries.len();
@@ -181,6 +452,7 @@ pub fn unregister_project(workspace: &str, project: &str) -> Result<usize> {
 /// `pm stop <service>`.
 #[allow(dead_code)]
 pub fn unregister_service(workspace: &str, project: &str, service: &str) -> Result<usize> {
+    let _lock = lock::lock(&routes_path())?;
     let mut data = load_routes()?;
     let project_key = format!("{workspace}/{project}");
     let before = data.entries.len();
@@ -221,20 +493,139 @@ mod tests {
         assert!(d.entries.is_empty());
     }
 
+    fn entry(hostname: &str, port: u16, service: &str, prefix: Option<&str>) -> RouteEntry {
+        RouteEntry {
+            hostname: hostname.into(),
+            upstream_port: port,
+            project_key: "work/api".into(),
+            service_key: service.into(),
+            path_prefix: prefix.map(str::to_string),
+            strip_prefix: false,
+            access_log: false,
+            rewrite_host: false,
+        }
+    }
+
     #[test]
     fn routes_data_serializes_roundtrip() {
         let data = RoutesData {
             version: 1,
-            entries: vec![RouteEntry {
-                hostname: "back.api.work.localhost".into(),
-                upstream_port: 26918,
-                project_key: "work/api".into(),
-                service_key: "back".into(),
-            }],
+            entries: vec![entry("back.api.work.localhost", 26918, "back", None)],
         };
         let json = serde_json::to_string(&data).unwrap();
+        assert!(!json.contains("path_prefix"));
         let back: RoutesData = serde_json::from_str(&json).unwrap();
         assert_eq!(back.entries.len(), 1);
         assert_eq!(back.entries[0].upstream_port, 26918);
     }
+
+    #[test]
+    fn project_hostnames_include_default_alias() {
+        assert_eq!(project_hostnames("work", "api"), vec!["api.work.localhost"]);
+        assert_eq!(
+            project_hostnames("default", "blog"),
+            vec!["blog.default.localhost", "blog.localhost"]
+        );
+    }
+
+    #[test]
+    fn lookup_prefers_longest_prefix() {
+        let table = RouteTable::from(RoutesData {
+            version: 1,
+            entries: vec![
+                entry("api.work.localhost", 10000, "any", None),
+                entry("api.work.localhost", 10001, "front", Some("/")),
+                entry("api.work.localhost", 20001, "back", Some("/api")),
+                entry("api.work.localhost", 20002, "admin", Some("/api/admin")),
+            ],
+        });
+        let port = |path| table.lookup("api.work.localhost", path).map(|e| e.upstream_port);
+        assert_eq!(port("/"), Some(10001));
+        assert_eq!(port("/dashboard"), Some(10001));
+        assert_eq!(port("/api"), Some(20001));
+        assert_eq!(port("/api/users"), Some(20001));
+        assert_eq!(port("/api/admin/x"), Some(20002));
+    }
+
+    #[test]
+    fn lookup_matches_prefix_on_segment_boundary() {
+        let table = RouteTable::from(RoutesData {
+            version: 1,
+            entries: vec![entry("Api.Work.localhost", 20001, "back", Some("/api"))],
+        });
+        assert!(table.lookup("api.work.localhost", "/apiary").is_none());
+        assert!(table.lookup("API.work.localhost", "/api/").is_some());
+    }
+
+    #[test]
+    fn lookup_without_prefix_matches_any_path() {
+        let table = RouteTable::from(RoutesData {
+            version: 1,
+            entries: vec![entry("back.api.work.localhost", 20001, "back", None)],
+        });
+        assert!(table.lookup("back.api.work.localhost", "/anything").is_some());
+        assert!(table.lookup("front.api.work.localhost", "/").is_none());
+    }
+
+    #[test]
+    fn wildcards_match_subdomains_below_exact_names() {
+        let table = RouteTable::from(RoutesData {
+            version: 1,
+            entries: vec![
+                entry("*.front.shop.localhost", 30001, "front", None),
+                entry("*.shop.localhost", 30002, "catchall", None),
+                entry("admin.front.shop.localhost", 30003, "admin", None),
+                entry("api.localhost", 30004, "back", None),
+            ],
+        });
+        let port = |host| table.lookup(host, "/").map(|e| e.upstream_port);
+        assert_eq!(port("acme.front.shop.localhost"), Some(30001));
+        assert_eq!(port("eu.acme.Front.shop.localhost"), Some(30001));
+        assert_eq!(port("admin.front.shop.localhost"), Some(30003));
+        assert_eq!(port("front.shop.localhost"), Some(30002));
+        assert_eq!(port("shop.localhost"), None);
+        assert_eq!(port("api.localhost"), Some(30004));
+        assert!(table.serves_host("x.front.shop.localhost"));
+        assert!(!table.serves_host("other.localhost"));
+    }
+
+    #[test]
+    fn service_hostnames_add_custom_and_wildcard_names() {
+        let options = RouteOptions {
+            hostnames: vec!["shop.localhost".into(), "*.tenants.localhost".into()],
+            wildcard: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            service_hostnames("default", "shop", "front", &options),
+            vec![
+                "front.shop.default.localhost",
+                "front.shop.localhost",
+                "shop.localhost",
+                "*.tenants.localhost",
+                "*.front.shop.default.localhost",
+                "*.front.shop.localhost",
+                "*.shop.localhost",
+            ]
+        );
+    }
+
+    #[test]
+    fn conflicts_only_with_running_services_on_the_same_route() {
+        let mut other = entry("api.localhost", 20001, "back", None);
+        other.project_key = "work/other".into();
+        let data = RoutesData { version: 1, entries: vec![other] };
+
+        let wanted = [
+            entry("back.api.work.localhost", 20002, "back", None),
+            entry("api.localhost", 20002, "back", None),
+        ];
+        let (taken, owner) = find_conflict(&data, &wanted, |_| true).unwrap();
+        assert_eq!((taken.hostname.as_str(), owner.as_str()), ("api.localhost", "work/other/back"));
+        // A stale entry (its service is gone) does not block the hostname.
+        assert!(find_conflict(&data, &wanted, |_| false).is_none());
+        // Neither does the same hostname under another path prefix.
+        let prefixed = [entry("api.localhost", 20002, "back", Some("/v2"))];
+        assert!(find_conflict(&data, &prefixed, |_| true).is_none());
+    }
 }
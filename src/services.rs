@@ -10,8 +10,14 @@
 //! Schema is keyed by `<workspace>/<project>` → service identifier →
 //! [`ServiceState`]. Processes are tracked across pm invocations because
 //! services are spawned detached (`setsid`) and survive the CLI exit.
+//!
+//! The daemon's supervisor owns the entries of services it spawned: it
+//! records how each process exited and how often it has been restarted.
+//! Removing an entry (what `pm stop` does first) tells the supervisor the
+//! exit was intentional and must not trigger a restart.
 
 use crate::config::services_state_path;
+use crate::lock;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -26,6 +32,50 @@ pub struct ServiceState {
     pub log_path: PathBuf,
     /// The dev_cmd that was spawned (recorded for `pm logs` headers).
     pub dev_cmd: String,
+    #[serde(default)]
+    pub status: ServiceStatus,
+    /// How many times the supervisor has restarted this service since
+    /// `pm run` started it.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub restart_count: u32,
+    /// Exit code of the most recent exit, when the process exited normally.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_exit_code: Option<i32>,
+    /// Signal number of the most recent exit, when the process was killed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_exit_signal: Option<i32>,
+    /// Grace period between SIGTERM and SIGKILL for `pm stop`; entries
+    /// written before it was recorded use the default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_timeout_secs: Option<u64>,
+}
+
+/// Lifecycle of a supervised service as seen by the daemon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceStatus {
+    #[default]
+    Running,
+    /// Exited; the supervisor will start it again after a backoff delay.
+    Restarting,
+    /// Exited and will stay down (restart policy or crash-loop limit).
+    Exited,
+}
+
+impl ServiceState {
+    /// Human-readable description of the last exit, e.g. `exit code 1` or
+    /// `signal 9`. `None` while the service has never exited.
+    pub fn last_exit(&self) -> Option<String> {
+        match (self.last_exit_code, self.last_exit_signal) {
+            (Some(code), _) => Some(format!("exit code {code}")),
+            (None, Some(sig)) => Some(format!("signal {sig}")),
+            (None, None) => None,
+        }
+    }
+}
+
+fn is_zero(n: &u32) -> bool {
+    *n == 0
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -47,15 +97,14 @@ pub fn load() -> Result<ServicesData> {
     Ok(data)
 }
 
+/// Write the whole file. Callers that loaded `data` first must hold
+/// [`lock::lock`] on [`services_state_path`] since before the load.
 pub fn save(data: &ServicesData) -> Result<()> {
     let path = services_state_path();
     if let Some(parent) = path.parent() {
         fs::create_dir_all(parent).ok();
     }
-    let tmp = path.with_extension("json.tmp");
-    fs::write(&tmp, serde_json::to_string_pretty(data)?)?;
-    fs::rename(&tmp, &path)?;
-    Ok(())
+    lock::write_atomic(&path, &serde_json::to_string_pretty(data)?)
 }
 
 /// Project key as stored in the file.
@@ -70,15 +119,38 @@ pub fn upsert(
     service: &str,
     state: ServiceState,
 ) -> Result<()> {
+    let _lock = lock::lock(&services_state_path())?;
     let mut data = load()?;
     let entry = data.projects.entry(project_key(workspace, project)).or_default();
     entry.insert(service.to_string(), state);
     save(&data)
 }
 
-#[allow(dead_code)] // wired in by Group 10 (pm stop / pm logs)
+/// Apply `f` to a single service's state and persist it. Returns false
+/// (without writing) when the service is not registered.
+pub fn update(
+    workspace: &str,
+    project: &str,
+    service: &str,
+    f: impl FnOnce(&mut ServiceState),
+) -> Result<bool> {
+    let _lock = lock::lock(&services_state_path())?;
+    let mut data = load()?;
+    let Some(state) = data
+        .projects
+        .get_mut(&project_key(workspace, project))
+        .and_then(|m| m.get_mut(service))
+    else {
+        return Ok(false);
+    };
+    f(state);
+    save(&data)?;
+    Ok(true)
+}
+
 /// Remove a single service. Called by `pm stop <svc>`.
 pub fn remove(workspace: &str, project: &str, service: &str) -> Result<Option<ServiceState>> {
+    let _lock = lock::lock(&services_state_path())?;
     let mut data = load()?;
     let key = project_key(workspace, project);
     let removed = match data.projects.get_mut(&key) {
@@ -96,9 +168,9 @@ pub fn remove(workspace: &str, project: &str, service: &str) -> Result<Option<Se
     Ok(removed)
 }
 
-#[allow(dead_code)] // wired in by Group 10
 /// Remove and return all services for a project. Called by `pm stop` (no args).
 pub fn remove_project(workspace: &str, project: &str) -> Result<HashMap<String, ServiceState>> {
+    let _lock = lock::lock(&services_state_path())?;
     let mut data = load()?;
     let removed = data
         .projects
@@ -120,7 +192,6 @@ pub fn get(workspace: &str, project: &str, service: &str) -> Result<Option<Servi
         .cloned())
 }
 
-#[allow(dead_code)] // wired in by Group 10
 /// Look up all services of a project.
 pub fn list_project(workspace: &str, project: &str) -> Result<HashMap<String, ServiceState>> {
     let data = load()?;
@@ -131,15 +202,28 @@ pub fn list_project(workspace: &str, project: &str) -> Result<HashMap<String, Se
         .unwrap_or_default())
 }
 
-#[allow(dead_code)] // wired in by Group 10 (pm stop / pm proxy status)
+/// Remove entries whose process is gone (see [`prune_dead`]) from the
+/// file. Returns how many were removed.
+#[cfg(unix)]
+pub fn prune() -> Result<usize> {
+    let _lock = lock::lock(&services_state_path())?;
+    let mut data = load()?;
+    let pruned = prune_dead(&mut data);
+    if pruned > 0 {
+        save(&data)?;
+    }
+    Ok(pruned)
+}
+
 /// Filter the in-memory state to only services whose PID is still alive.
-/// Used to clean up stale entries left by ungraceful exits.
+/// Used by `pm ps` to clean up stale entries left by ungraceful exits.
+/// Entries the daemon is about to restart are kept.
 #[cfg(unix)]
 pub fn prune_dead(data: &mut ServicesData) -> usize {
     let mut removed = 0;
     data.projects.retain(|_proj, services| {
         services.retain(|_svc, state| {
-            let alive = pid_alive(state.pid);
+            let alive = state.status == ServiceStatus::Restarting || pid_alive(state.pid);
             if !alive {
                 removed += 1;
             }
@@ -174,9 +258,39 @@ mod tests {
             started_at: Utc::now(),
             log_path: PathBuf::from("/tmp/x.log"),
             dev_cmd: "cargo run".into(),
+            status: ServiceStatus::Running,
+            restart_count: 0,
+            last_exit_code: None,
+            last_exit_signal: None,
+            stop_timeout_secs: None,
         }
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn prune_dead_keeps_live_and_restarting_entries() {
+        let dead_pid = 999_999_999;
+        let mut data = ServicesData::default();
+        let services = data.projects.entry(project_key("work", "api")).or_default();
+        services.insert(
+            "live".into(),
+            ServiceState { pid: std::process::id(), ..sample_state() },
+        );
+        services.insert("gone".into(), ServiceState { pid: dead_pid, ..sample_state() });
+        services.insert(
+            "restarting".into(),
+            ServiceState {
+                pid: dead_pid,
+                status: ServiceStatus::Restarting,
+                ..sample_state()
+            },
+        );
+        assert_eq!(prune_dead(&mut data), 1);
+        let mut left: Vec<_> = data.projects["work/api"].keys().cloned().collect();
+        left.sort();
+        assert_eq!(left, vec!["live", "restarting"]);
+    }
+
     #[test]
     fn project_key_format() {
         assert_eq!(project_key("work", "api"), "work/api");
@@ -200,4 +314,23 @@ mod tests {
         assert_eq!(back.projects.len(), 1);
         assert_eq!(back.projects["work/api"]["back"].port, 26918);
     }
+
+    #[test]
+    fn state_without_supervisor_fields_defaults_to_running() {
+        let json = r#"{"pid":1,"port":2,"started_at":"2026-01-01T00:00:00Z",
+            "log_path":"/tmp/x.log","dev_cmd":"cargo run"}"#;
+        let state: ServiceState = serde_json::from_str(json).unwrap();
+        assert_eq!(state.status, ServiceStatus::Running);
+        assert_eq!(state.restart_count, 0);
+        assert_eq!(state.last_exit(), None);
+    }
+
+    #[test]
+    fn last_exit_prefers_code_over_signal() {
+        let mut state = sample_state();
+        state.last_exit_signal = Some(9);
+        assert_eq!(state.last_exit().as_deref(), Some("signal 9"));
+        state.last_exit_code = Some(1);
+        assert_eq!(state.last_exit().as_deref(), Some("exit code 1"));
+    }
 }
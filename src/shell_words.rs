@@ -0,0 +1,208 @@
+//! POSIX shell-word splitting for `dev_cmd`.
+//!
+//! Covers what dev commands realistically contain without invoking a shell:
+//!
+//! - whitespace-separated words;
+//! - single quotes (everything literal until the closing `'`);
+//! - double quotes, where `\"`, `\\`, `\$` and `` \` `` are unescaped and
+//!   every other backslash is kept;
+//! - backslash escapes outside quotes (`\ ` joins words, `\<newline>`
+//!   continues the line);
+//! - leading `NAME=value` assignments, returned separately as environment.
+//!
+//! No expansion happens (`$VAR`, globs, `~`, `&&`, pipes): services that
+//! need any of that set `shell: true` and run through `$SHELL -c`.
+
+use anyhow::{Result, anyhow};
+
+/// A `dev_cmd` split into its environment prefix, program and arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCommand {
+    pub env: Vec<(String, String)>,
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+/// Parse `input` the way `sh` would tokenize a simple command.
+pub fn parse_command(input: &str) -> Result<ParsedCommand> {
+    let mut words = split_words(input)?.into_iter().peekable();
+    let mut env = Vec::new();
+    while let Some(word) = words.peek() {
+        let Some((name, value)) = word.assignment() else { break };
+        env.push((name.to_string(), value.to_string()));
+        words.next();
+    }
+    let program = match words.next() {
+        Some(word) => word.text,
+        None if env.is_empty() => return Err(anyhow!("command is empty")),
+        None => return Err(anyhow!("command `{input}` only assigns variables")),
+    };
+    Ok(ParsedCommand {
+        env,
+        program,
+        args: words.map(|w| w.text).collect(),
+    })
+}
+
+struct Word {
+    text: String,
+    /// Byte offset in `text` of the first character that came from a
+    /// quote or escape. `NAME=` must lie before it to be an assignment.
+    first_quoted: Option<usize>,
+}
+
+impl Word {
+    fn assignment(&self) -> Option<(&str, &str)> {
+        let eq = self.text.find('=')?;
+        if self.first_quoted.is_some_and(|q| q <= eq) {
+            return None;
+        }
+        let name = &self.text[..eq];
+        let mut chars = name.chars();
+        let valid = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        valid.then(|| (name, &self.text[eq + 1..]))
+    }
+}
+
+fn split_words(input: &str) -> Result<Vec<Word>> {
+    let mut words = Vec::new();
+    let mut current: Option<Word> = None;
+    let mut chars = input.chars();
+
+    fn word(current: &mut Option<Word>) -> &mut Word {
+        current.get_or_insert_with(|| Word { text: String::new(), first_quoted: None })
+    }
+    fn mark_quoted(w: &mut Word) {
+        if w.first_quoted.is_none() {
+            w.first_quoted = Some(w.text.len());
+        }
+    }
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(w) = current.take() {
+                    words.push(w);
+                }
+            }
+            '\'' => {
+                let w = word(&mut current);
+                mark_quoted(w);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => w.text.push(c),
+                        None => return Err(anyhow!("unterminated single quote in `{input}`")),
+                    }
+                }
+            }
+            '"' => {
+                let w = word(&mut current);
+                mark_quoted(w);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => w.text.push(c),
+                            Some('\n') => {}
+                            Some(c) => {
+                                w.text.push('\\');
+                                w.text.push(c);
+                            }
+                            None => return Err(anyhow!("unterminated double quote in `{input}`")),
+                        },
+                        Some(c) => w.text.push(c),
+                        None => return Err(anyhow!("unterminated double quote in `{input}`")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // Line continuation: not part of any word.
+                Some('\n') => {}
+                Some(c) => {
+                    let w = word(&mut current);
+                    mark_quoted(w);
+                    w.text.push(c);
+                }
+                None => return Err(anyhow!("trailing backslash in `{input}`")),
+            },
+            c => word(&mut current).text.push(c),
+        }
+    }
+    if let Some(w) = current {
+        words.push(w);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &str) -> Result<Vec<String>> {
+        Ok(split_words(input)?.into_iter().map(|w| w.text).collect())
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(
+            split("  pnpm  next dev\t--turbopack ").unwrap(),
+            vec!["pnpm", "next", "dev", "--turbopack"]
+        );
+    }
+
+    #[test]
+    fn handles_quotes_and_escapes() {
+        assert_eq!(
+            split(r#"uvicorn main:app --host "0.0.0.0""#).unwrap(),
+            vec!["uvicorn", "main:app", "--host", "0.0.0.0"]
+        );
+        assert_eq!(split("sh -c 'a && b'").unwrap(), vec!["sh", "-c", "a && b"]);
+        assert_eq!(split(r#"echo "say \"hi\" \n""#).unwrap(), vec!["echo", r#"say "hi" \n"#]);
+        assert_eq!(split(r"ls my\ dir").unwrap(), vec!["ls", "my dir"]);
+        assert_eq!(split("a'b'\"c\"d").unwrap(), vec!["abcd"]);
+        assert_eq!(split("echo '' \"\"").unwrap(), vec!["echo", "", ""]);
+        assert_eq!(split("cargo \\\n  run").unwrap(), vec!["cargo", "run"]);
+    }
+
+    #[test]
+    fn rejects_unbalanced_quotes() {
+        let err = split("echo 'oops").unwrap_err().to_string();
+        assert!(err.contains("unterminated single quote"), "{err}");
+        let err = split("echo \"oops").unwrap_err().to_string();
+        assert!(err.contains("unterminated double quote"), "{err}");
+        assert!(split("echo oops\\").is_err());
+    }
+
+    #[test]
+    fn leading_assignments_become_env() {
+        let parsed = parse_command("FOO=bar RUST_LOG='debug,hyper=info' cargo run X=1").unwrap();
+        assert_eq!(
+            parsed.env,
+            vec![
+                ("FOO".to_string(), "bar".to_string()),
+                ("RUST_LOG".to_string(), "debug,hyper=info".to_string()),
+            ]
+        );
+        assert_eq!(parsed.program, "cargo");
+        assert_eq!(parsed.args, vec!["run", "X=1"]);
+    }
+
+    #[test]
+    fn quoted_names_are_not_assignments() {
+        let parsed = parse_command("'FOO=bar' cmd").unwrap();
+        assert!(parsed.env.is_empty());
+        assert_eq!(parsed.program, "FOO=bar");
+        let parsed = parse_command("1X=2 cmd").unwrap();
+        assert_eq!(parsed.program, "1X=2");
+    }
+
+    #[test]
+    fn empty_or_assignment_only_commands_fail() {
+        assert!(parse_command("   ").is_err());
+        assert!(parse_command("FOO=bar").is_err());
+    }
+}
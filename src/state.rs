@@ -1,5 +1,6 @@
-use crate::config::{load_config, load_manifest, save_all};
+use crate::config::{config_path, load_config, load_manifest, manifest_path, save_all};
 use crate::error::PmError;
+use crate::lock::{self, StateLock};
 use crate::models::{Config, Manifest, Project, Workspace};
 use crate::path::{collapse_path, expand_path};
 use anyhow::Result;
@@ -13,9 +14,27 @@ pub fn save_state(config: &Config, manifest: &Manifest) -> Result<()> {
     save_all(config, manifest)
 }
 
+/// Lock `config.json` and `manifest.json` for a load-modify-save cycle
+/// (see [`crate::lock`]). Take it before [`load_state`] and hold it past
+/// [`save_state`], but not across prompts or slow work: other pm commands
+/// give up after [`lock::LOCK_TIMEOUT`].
+pub fn lock_state() -> Result<(StateLock, StateLock)> {
+    Ok((lock::lock(&config_path())?, lock::lock(&manifest_path())?))
+}
+
+/// Load the state, apply `f` and save it, all under [`lock_state`].
+/// Nothing is written when `f` fails.
+pub fn update_state<T>(f: impl FnOnce(&mut Config, &mut Manifest) -> Result<T>) -> Result<T> {
+    let _lock = lock_state()?;
+    let (mut config, mut manifest) = load_state()?;
+    let out = f(&mut config, &mut manifest)?;
+    save_state(&config, &manifest)?;
+    Ok(out)
+}
+
 pub fn parse_target(target: String) -> (Option<String>, String) {
-    if target.starts_with('@') {
-        let parts: Vec<&str> = target[1..].splitn(2, '/').collect();
+    if let Some(rest) = target.strip_prefix('@') {
+        let parts: Vec<&str> = rest.splitn(2, '/').collect();
         if parts.len() == 2 {
             return (Some(parts[0].to_string()), parts[1].to_string());
         }
@@ -0,0 +1,183 @@
+//! Service management over the daemon's control plane, end to end: a real
+//! `pm` daemon in a scratch `PM_CONFIG_DIR`, driven over raw HTTP the way
+//! an editor integration would.
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::time::{Duration, Instant};
+
+const PROJECT_YAML: &str = concat!(
+    "language: shell\n",
+    "config_version: \"1\"\n",
+    "services:\n",
+    "  web:\n",
+    "    framework: generic\n",
+    "    port_kind: backend\n",
+    "    shell: true\n",
+    "    dev_cmd: \"echo hello on $APP_PORT; while :; do echo tick; sleep 0.05; done\"\n",
+);
+
+/// A scratch pm installation with one project, `default/demo`, whose
+/// daemon is stopped on drop.
+struct Sandbox {
+    root: tempfile::TempDir,
+    control_port: u16,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        let root = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox { root, control_port: free_port() };
+        sandbox.pm(&["init"]);
+
+        let config_path = sandbox.config_dir().join("config.json");
+        let mut config: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&config_path).unwrap()).unwrap();
+        config["base_root"] = sandbox.root.path().join("src").display().to_string().into();
+        config["dev"]["proxy_port"] = free_port().into();
+        config["dev"]["control_port"] = sandbox.control_port.into();
+        std::fs::write(&config_path, config.to_string()).unwrap();
+
+        std::fs::create_dir_all(sandbox.project_dir()).unwrap();
+        std::fs::write(sandbox.project_dir().join(".project.yaml"), PROJECT_YAML).unwrap();
+        let dir = sandbox.project_dir().display().to_string();
+        sandbox.pm(&["add", &dir, "--name", "demo"]);
+        sandbox.pm(&["proxy", "start"]);
+        sandbox
+    }
+
+    fn config_dir(&self) -> PathBuf {
+        self.root.path().join("config")
+    }
+
+    fn project_dir(&self) -> PathBuf {
+        self.root.path().join("src/default/demo")
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_pm"));
+        cmd.args(args)
+            .env("PM_CONFIG_DIR", self.config_dir())
+            .env("HOME", self.root.path())
+            .current_dir(self.root.path());
+        cmd
+    }
+
+    fn pm(&self, args: &[&str]) -> Output {
+        let out = self.command(args).output().unwrap();
+        assert!(
+            out.status.success(),
+            "pm {args:?} failed:\n{}{}",
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        );
+        out
+    }
+
+    fn services_json(&self) -> serde_json::Value {
+        let path = self.config_dir().join("services.json");
+        std::fs::read_to_string(path)
+            .map(|s| serde_json::from_str(&s).unwrap())
+            .unwrap_or_default()
+    }
+
+    /// The `services.json` entry of `default/demo/web`.
+    fn web_state(&self) -> Option<serde_json::Value> {
+        let services = self.services_json();
+        let entry = &services["projects"]["default/demo"]["web"];
+        (!entry.is_null()).then(|| entry.clone())
+    }
+
+    fn send(&self, method: &str, path: &str, extra_headers: &str) -> TcpStream {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut stream = loop {
+            match TcpStream::connect(("127.0.0.1", self.control_port)) {
+                Ok(stream) => break stream,
+                Err(e) if Instant::now() > deadline => panic!("control plane unreachable: {e}"),
+                Err(_) => std::thread::sleep(Duration::from_millis(20)),
+            }
+        };
+        stream.set_read_timeout(Some(Duration::from_secs(30))).unwrap();
+        let head = format!(
+            "{method} {path} HTTP/1.1\r\nhost: 127.0.0.1:{}\r\ncontent-type: application/json\r\n\
+             {extra_headers}content-length: 0\r\nconnection: close\r\n\r\n",
+            self.control_port
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        stream
+    }
+
+    fn request(&self, method: &str, path: &str) -> String {
+        read_all(self.send(method, path, ""))
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = self.command(&["stop", "web", "@default/demo"]).output();
+        let _ = self.command(&["proxy", "stop"]).output();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn read_all(mut stream: TcpStream) -> String {
+    let mut out = String::new();
+    stream.read_to_string(&mut out).unwrap();
+    out
+}
+
+fn pid_alive(pid: u64) -> bool {
+    let pid = nix::unistd::Pid::from_raw(pid as i32);
+    nix::sys::signal::kill(pid, None).is_ok()
+}
+
+#[test]
+fn start_logs_and_stop_over_the_control_plane() {
+    let sandbox = Sandbox::new();
+
+    // A web page cannot start services.
+    let forged = read_all(sandbox.send(
+        "POST",
+        "/services/default/demo/web/start",
+        "origin: http://evil.test\r\n",
+    ));
+    assert!(forged.starts_with("HTTP/1.1 403"), "{forged}");
+    assert!(sandbox.web_state().is_none());
+
+    let started = sandbox.request("POST", "/services/default/demo/%77eb/start");
+    assert!(started.starts_with("HTTP/1.1 200"), "{started}");
+    let state = sandbox.web_state().expect("recorded");
+    let pid = state["pid"].as_u64().unwrap();
+    assert!(pid_alive(pid));
+
+    // Follow the log until new lines have arrived after the history.
+    let mut follow = sandbox.send("GET", "/services/default/demo/web/logs?lines=1&follow=1", "");
+    follow.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    let mut seen = String::new();
+    let mut buf = [0u8; 4096];
+    while seen.matches(" out tick").count() < 10 {
+        let n = follow.read(&mut buf).unwrap_or_else(|e| panic!("no new log lines ({e}): {seen}"));
+        assert!(n > 0, "log stream closed: {seen}");
+        seen.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    assert!(seen.starts_with("HTTP/1.1 200"), "{seen}");
+    drop(follow);
+
+    // Renaming the service in `.proj.yaml` does not strand the running one.
+    let yaml_path = sandbox.project_dir().join(".project.yaml");
+    std::fs::write(&yaml_path, PROJECT_YAML.replace("  web:", "  api:")).unwrap();
+    let stopped = sandbox.request("POST", "/services/default/demo/web/stop");
+    assert!(stopped.starts_with("HTTP/1.1 200"), "{stopped}");
+    assert!(stopped.contains(r#""stopped":true"#), "{stopped}");
+    assert!(sandbox.web_state().is_none());
+    assert!(!pid_alive(pid));
+
+    let log = std::fs::read_to_string(state["log_path"].as_str().unwrap()).unwrap();
+    let port = state["port"].as_u64().unwrap();
+    assert!(log.contains(&format!(" out hello on {port}")), "{log}");
+}